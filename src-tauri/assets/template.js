@@ -0,0 +1,23 @@
+/*
+ * render math and diagrams left by the markdown renderer, see src-tauri/src/render.rs
+ * d3 blocks hold formulas parsed by the app (see components/D3.tsx), so they are left as text here.
+ * embedded into the exported html by template.rs
+ */
+window.addEventListener("load", function () {
+  if (window.katex) {
+    document.querySelectorAll(".math-inline, .math-display").forEach(function (el) {
+      try {
+        window.katex.render(el.textContent, el, {
+          displayMode: el.classList.contains("math-display"),
+          throwOnError: false,
+        });
+      } catch (err) {
+        console.error(err);
+      }
+    });
+  }
+  if (window.mermaid) {
+    window.mermaid.initialize({ startOnLoad: false });
+    window.mermaid.init(undefined, document.querySelectorAll(".mermaid"));
+  }
+});
@@ -0,0 +1,260 @@
+use super::attachments::{self, store_dir, Store};
+use std::{
+  fs::File,
+  io::{self, Read, Seek, SeekFrom},
+  path::Path,
+};
+
+/*
+ * the `notex-asset` protocol, which serves the attachment store to the webview.
+ * - `notex-asset://<sha256>.<ext>`, or `https://notex-asset.localhost/<sha256>.<ext>` on windows
+ * - a variant of an image by its file name, e.g. `notex-asset://<sha256>.thumbnail.png`
+ * - the content of a hash never changes, so it is cached as immutable with the hash as the etag
+ * - a single byte range is served partially for audio and video. multiple ranges get the whole file.
+ *
+ * the frontend rewrites `notex://` links into it, and the csp allows nothing else but the app itself.
+ * see tauri.conf.json and main.rs
+ */
+
+pub static SCHEME: &str = "notex-asset";
+static CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Asset {
+  pub status: u16,
+  pub mime: String,
+  pub headers: Vec<(&'static str, String)>,
+  pub body: Vec<u8>,
+}
+impl Asset {
+  fn error(status: u16, message: &str) -> Asset {
+    Asset {
+      status,
+      mime: String::from("text/plain"),
+      headers: vec![],
+      body: message.as_bytes().to_vec(),
+    }
+  }
+}
+
+/*
+ * the file name and the hash in the uri, in either form of the scheme. a query or fragment is ignored.
+ */
+pub fn hash_of(uri: &str) -> Option<(&str, &str)> {
+  let rest = uri.split(&['?', '#'][..]).next()?;
+  let name = rest.trim_end_matches('/').rsplit('/').next()?;
+  let hash = name.split('.').next()?;
+  if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+    Some((name, hash))
+  } else {
+    None
+  }
+}
+
+/*
+ * the first range of a `Range: bytes=...` header as inclusive offsets in a file of len bytes.
+ * Ok(None) for no or a malformed header, which is answered by the whole file,
+ * and Err for a range outside of the file.
+ */
+fn range(header: Option<&str>, len: u64) -> Result<Option<(u64, u64)>, ()> {
+  let spec = match header.and_then(|h| h.trim().strip_prefix("bytes=")) {
+    Some(spec) if !spec.contains(',') => spec.trim(),
+    _ => return Ok(None),
+  };
+  let (start, end) = match spec.split_once('-') {
+    Some(pair) => pair,
+    None => return Ok(None),
+  };
+  let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+    (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+    (Ok(start), Err(_)) if end.is_empty() => (start, len.saturating_sub(1)),
+    (Err(_), Ok(suffix)) if start.is_empty() && 0 < suffix => {
+      (len.saturating_sub(suffix), len.saturating_sub(1))
+    }
+    _ => return Ok(None),
+  };
+  if len <= start {
+    Err(())
+  } else {
+    Ok(Some((start, end)))
+  }
+}
+
+/*
+ * the response to a request of the uri with the `Range` and `If-None-Match` headers.
+ */
+pub fn serve(
+  target_dir: &Path,
+  uri: &str,
+  range_header: Option<&str>,
+  if_none_match: Option<&str>,
+) -> io::Result<Asset> {
+  let (name, hash) = match hash_of(uri) {
+    Some(pair) => pair,
+    None => return Ok(Asset::error(400, "invalid attachment uri")),
+  };
+  let store = Store::open(target_dir)?;
+  let attachment = match store.get(hash) {
+    Some(attachment) => attachment,
+    None => return Ok(Asset::error(404, "attachment not found")),
+  };
+  // an unknown name of a known hash is the original
+  let name = attachment
+    .files()
+    .into_iter()
+    .find(|file| file == name)
+    .unwrap_or_else(|| attachment.file_name());
+  let mime = mime_guess::from_path(&name)
+    .first()
+    .map(|mime| mime.essence_str().to_string())
+    .unwrap_or_else(|| attachment.mime.clone());
+  let etag = format!("\"{}\"", name);
+  let mut headers = vec![
+    ("Cache-Control", CACHE_CONTROL.to_string()),
+    ("ETag", etag.clone()),
+    ("Accept-Ranges", String::from("bytes")),
+  ];
+  if if_none_match
+    .map(|tags| {
+      tags
+        .split(',')
+        .any(|tag| tag.trim() == etag || tag.trim() == "*")
+    })
+    .unwrap_or(false)
+  {
+    return Ok(Asset {
+      status: 304,
+      mime: mime.clone(),
+      headers,
+      body: vec![],
+    });
+  }
+
+  let mut file = match File::open(store_dir(target_dir).join(&name)) {
+    Ok(file) => file,
+    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+      return Ok(Asset::error(404, "attachment not found"))
+    }
+    Err(err) => return Err(err),
+  };
+  let len = file.metadata()?.len();
+  match range(range_header, len) {
+    Ok(Some((start, end))) => {
+      let mut body = vec![0; (end - start + 1) as usize];
+      file.seek(SeekFrom::Start(start))?;
+      file.read_exact(&mut body)?;
+      headers.push(("Content-Range", format!("bytes {}-{}/{}", start, end, len)));
+      headers.push(("Content-Length", body.len().to_string()));
+      Ok(Asset {
+        status: 206,
+        mime: mime.clone(),
+        headers,
+        body,
+      })
+    }
+    Ok(None) => {
+      let mut body = vec![];
+      file.read_to_end(&mut body)?;
+      headers.push(("Content-Length", body.len().to_string()));
+      Ok(Asset {
+        status: 200,
+        mime: mime.clone(),
+        headers,
+        body,
+      })
+    }
+    Err(_) => {
+      let mut res = Asset::error(416, "range not satisfiable");
+      res
+        .headers
+        .push(("Content-Range", format!("bytes */{}", len)));
+      Ok(res)
+    }
+  }
+}
+
+/*
+ * the uri of an attachment link for the webview, for the platforms other than windows.
+ */
+pub fn uri_of(link: &str) -> Option<String> {
+  attachments::hash_of(link)?;
+  link
+    .strip_prefix(attachments::SCHEME)
+    .map(|name| format!("{}://{}", SCHEME, name))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::get_random_key;
+  use std::fs;
+
+  #[test]
+  fn serve_asset_test() {
+    let dir = std::env::temp_dir().join(format!("notex-asset-{}", get_random_key()));
+    fs::create_dir_all(&dir).unwrap();
+    let mut store = Store::open(&dir).unwrap();
+    let clip = store
+      .add_bytes("clip.mp3", b"0123456789", &Default::default())
+      .unwrap();
+    store.save().unwrap();
+    let uri = uri_of(&clip.link()).unwrap();
+    assert_eq!(uri, format!("notex-asset://{}.mp3", clip.hash));
+
+    let full = serve(&dir, &uri, None, None).unwrap();
+    assert_eq!(full.status, 200);
+    assert_eq!(full.mime, "audio/mpeg");
+    assert_eq!(full.body, b"0123456789");
+    assert!(full
+      .headers
+      .contains(&("Cache-Control", CACHE_CONTROL.to_string())));
+
+    let windows = format!("https://notex-asset.localhost/{}.mp3?v=1", clip.hash);
+    let part = serve(&dir, &windows, Some("bytes=2-5"), None).unwrap();
+    assert_eq!(part.status, 206);
+    assert_eq!(part.body, b"2345");
+    assert!(part
+      .headers
+      .contains(&("Content-Range", String::from("bytes 2-5/10"))));
+    assert_eq!(
+      serve(&dir, &uri, Some("bytes=-3"), None).unwrap().body,
+      b"789"
+    );
+    assert_eq!(
+      serve(&dir, &uri, Some("bytes=7-"), None).unwrap().body,
+      b"789"
+    );
+    assert_eq!(
+      serve(&dir, &uri, Some("bytes=10-"), None).unwrap().status,
+      416
+    );
+    assert_eq!(
+      serve(&dir, &uri, Some("bytes=0-1,4-5"), None)
+        .unwrap()
+        .status,
+      200
+    );
+
+    let etag = format!("\"{}.mp3\"", clip.hash);
+    assert_eq!(serve(&dir, &uri, None, Some(&etag)).unwrap().status, 304);
+    assert_eq!(
+      serve(
+        &dir,
+        &format!("notex-asset://{}", "0".repeat(64)),
+        None,
+        None
+      )
+      .unwrap()
+      .status,
+      404
+    );
+    assert_eq!(
+      serve(&dir, "notex-asset://../index.json", None, None)
+        .unwrap()
+        .status,
+      400
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,404 @@
+use super::{
+  constants::{ATTACHMENTS, ATTACHMENT_INDEX},
+  images::{self, ImageOptions, Variant},
+  model,
+};
+use regex::{Captures, Regex};
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs, io,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/*
+ * content addressed store of the files attached to notes, under `.attachments` in the target directory.
+ * - <sha256>.<ext> => the file, so the same content is stored once whatever it is named
+ * - index.json => the attachments by hash, with the original name and the notes which refer to them
+ *
+ * notes link them by `notex://<sha256>.<ext>`, which doesn't depend on where the body or the workspace is.
+ * the exports look them up in the store -- see standalone.rs
+ *
+ * the references of a note are updated when it is saved or deleted, and rebuilt from all the bodies by gc,
+ * which removes the attachments no note refers to. recently added ones are kept, since the note may be unsaved yet.
+ *
+ * images are processed as they are added -- see images.rs
+ * the hash is of the stored original, i.e. after the metadata is stripped,
+ * and the variants are stored next to it as <sha256>.optimized.<ext> and <sha256>.thumbnail.<ext>.
+ */
+
+pub static SCHEME: &str = "notex://";
+static GRACE_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Attachment {
+  pub hash: String,
+  pub name: String, //file name when added
+  pub ext: String,  //lowercase, empty if none
+  pub mime: String,
+  pub size: u64,
+  pub added_at: u64, //millis
+  #[serde(default)]
+  pub notes: BTreeSet<String>, //titles of the notes which refer to it
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub image: Option<ImageInfo>,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ImageInfo {
+  pub width: u32,
+  pub height: u32,
+  pub optimized: Option<String>, //file name in the store
+  pub thumbnail: Option<String>, //file name in the store
+}
+
+impl Attachment {
+  pub fn file_name(&self) -> String {
+    if self.ext.is_empty() {
+      self.hash.clone()
+    } else {
+      format!("{}.{}", self.hash, self.ext)
+    }
+  }
+
+  pub fn link(&self) -> String {
+    format!("{}{}", SCHEME, self.file_name())
+  }
+
+  /*
+   * the file of the variant, or the original if the variant is not made.
+   */
+  pub fn variant_name(&self, variant: Variant) -> String {
+    match (variant, &self.image) {
+      (
+        Variant::Optimized,
+        Some(ImageInfo {
+          optimized: Some(name),
+          ..
+        }),
+      ) => name.clone(),
+      _ => self.file_name(),
+    }
+  }
+
+  /*
+   * the original and the variants.
+   */
+  pub fn files(&self) -> Vec<String> {
+    let mut files = vec![self.file_name()];
+    if let Some(image) = &self.image {
+      files.extend(image.optimized.iter().cloned());
+      files.extend(image.thumbnail.iter().cloned());
+    }
+    files
+  }
+}
+
+pub fn store_dir(target_dir: &Path) -> PathBuf {
+  target_dir.join(ATTACHMENTS)
+}
+
+/*
+ * the hash of a `notex://` link, if it is one.
+ */
+pub fn hash_of(link: &str) -> Option<&str> {
+  let name = link.strip_prefix(SCHEME)?;
+  let hash = name.split('.').next()?;
+  if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+    Some(hash)
+  } else {
+    None
+  }
+}
+
+/*
+ * hashes of the attachments linked in a body.
+ */
+pub fn references(md: &str) -> BTreeSet<String> {
+  let link = Regex::new(r"notex://([0-9a-f]{64})").unwrap();
+  link
+    .captures_iter(md)
+    .map(|caps| caps[1].to_string())
+    .collect()
+}
+
+fn now_millis() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+pub struct Store {
+  dir: PathBuf,
+  index: BTreeMap<String, Attachment>,
+}
+impl Store {
+  /*
+   * the store of the target directory. it is created on the first add.
+   */
+  pub fn open(target_dir: &Path) -> io::Result<Store> {
+    let dir = store_dir(target_dir);
+    let index = match fs::read_to_string(dir.join(ATTACHMENT_INDEX)) {
+      Ok(src) => serde_json::from_str(&src)?,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+      Err(err) => return Err(err),
+    };
+    Ok(Store { dir, index })
+  }
+
+  pub fn save(&self) -> io::Result<()> {
+    fs::create_dir_all(&self.dir)?;
+    fs::write(
+      self.dir.join(ATTACHMENT_INDEX),
+      serde_json::to_string_pretty(&self.index)?,
+    )
+  }
+
+  pub fn get(&self, hash: &str) -> Option<&Attachment> {
+    self.index.get(hash)
+  }
+
+  pub fn list(&self) -> Vec<Attachment> {
+    self.index.values().cloned().collect()
+  }
+
+  pub fn path_of(&self, attachment: &Attachment) -> PathBuf {
+    self.dir.join(attachment.file_name())
+  }
+
+  /*
+   * copy the file into the store unless the same content is there already.
+   */
+  pub fn add(&mut self, src: &Path, options: &ImageOptions) -> io::Result<Attachment> {
+    let bytes = fs::read(src)?;
+    let name = src
+      .file_name()
+      .map(|name| name.to_string_lossy().to_string())
+      .unwrap_or_default();
+    self.add_bytes(&name, &bytes, options)
+  }
+
+  pub fn add_bytes(
+    &mut self,
+    name: &str,
+    bytes: &[u8],
+    options: &ImageOptions,
+  ) -> io::Result<Attachment> {
+    let ext = Path::new(name)
+      .extension()
+      .map(|ext| ext.to_string_lossy().to_lowercase())
+      .filter(|ext| ext.chars().all(|c| c.is_ascii_alphanumeric()))
+      .unwrap_or_default();
+    // an image which can't be decoded is stored as a plain file
+    let processed =
+      images::format_of(&ext).and_then(|format| match images::process(bytes, format, options) {
+        Ok(processed) => Some(processed),
+        Err(err) => {
+          println!("WARNING: the image is stored as it is\n{}", err);
+          None
+        }
+      });
+    let (bytes, processed) = match processed {
+      Some(mut processed) => (std::mem::take(&mut processed.original), Some(processed)),
+      None => (bytes.to_vec(), None),
+    };
+
+    let hash = model::digest(&bytes);
+    if let Some(attachment) = self.index.get(&hash) {
+      if self.path_of(attachment).is_file() {
+        return Ok(attachment.clone());
+      }
+    }
+    fs::create_dir_all(&self.dir)?;
+    let image = match processed {
+      Some(processed) => {
+        let write = |kind: &str, encoded: Option<images::Encoded>| -> io::Result<_> {
+          match encoded {
+            Some(encoded) => {
+              let name = format!("{}.{}.{}", hash, kind, encoded.ext);
+              fs::write(self.dir.join(&name), encoded.bytes)?;
+              Ok(Some(name))
+            }
+            None => Ok(None),
+          }
+        };
+        Some(ImageInfo {
+          width: processed.width,
+          height: processed.height,
+          optimized: write("optimized", processed.optimized)?,
+          thumbnail: write("thumbnail", processed.thumbnail)?,
+        })
+      }
+      None => None,
+    };
+    let attachment = Attachment {
+      mime: mime_guess::from_ext(&ext)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string(),
+      hash: hash.clone(),
+      name: name.to_string(),
+      ext,
+      size: bytes.len() as u64,
+      added_at: now_millis(),
+      notes: BTreeSet::new(),
+      image,
+    };
+    fs::write(self.path_of(&attachment), bytes)?;
+    self.index.insert(hash, attachment.clone());
+    Ok(attachment)
+  }
+
+  /*
+   * the links in the body pointed at the variant. unknown attachments are left as they are.
+   */
+  pub fn with_variant(&self, md: &str, variant: Variant) -> String {
+    let link = Regex::new(r"notex://([0-9a-f]{64})(\.[0-9a-z]+)*").unwrap();
+    link
+      .replace_all(md, |caps: &Captures| match self.index.get(&caps[1]) {
+        Some(attachment) => format!("{}{}", SCHEME, attachment.variant_name(variant)),
+        None => caps[0].to_string(),
+      })
+      .to_string()
+  }
+
+  /*
+   * the note refers to the attachments linked in its body and no others.
+   */
+  pub fn set_references(&mut self, note: &str, md: &str) {
+    let linked = references(md);
+    for (hash, attachment) in self.index.iter_mut() {
+      if linked.contains(hash) {
+        attachment.notes.insert(note.to_string());
+      } else {
+        attachment.notes.remove(note);
+      }
+    }
+  }
+
+  pub fn remove_note(&mut self, note: &str) {
+    for attachment in self.index.values_mut() {
+      attachment.notes.remove(note);
+    }
+  }
+
+  /*
+   * rebuild the references from all the notes, pairs of title and body,
+   * then remove the attachments which are not referred to and older than the grace period.
+   * returns the removed ones.
+   */
+  pub fn gc(&mut self, notes: &[(String, String)]) -> io::Result<Vec<Attachment>> {
+    for attachment in self.index.values_mut() {
+      attachment.notes.clear();
+    }
+    for (note, md) in notes {
+      for hash in references(md) {
+        if let Some(attachment) = self.index.get_mut(&hash) {
+          attachment.notes.insert(note.clone());
+        }
+      }
+    }
+
+    let now = now_millis();
+    let unused = self
+      .index
+      .values()
+      .filter(|a| a.notes.is_empty() && a.added_at + GRACE_MILLIS < now)
+      .cloned()
+      .collect::<Vec<_>>();
+    for attachment in &unused {
+      for name in attachment.files() {
+        match fs::remove_file(self.dir.join(name)) {
+          Ok(_) => (),
+          Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+          Err(err) => return Err(err),
+        }
+      }
+      self.index.remove(&attachment.hash);
+    }
+    Ok(unused)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{images::tests::gradient, model::tests::get_random_key};
+  use image::ImageFormat;
+
+  #[test]
+  fn attachment_store_test() {
+    let dir = std::env::temp_dir().join(format!("notex-attachments-{}", get_random_key()));
+    fs::create_dir_all(&dir).unwrap();
+    let png = gradient(64, 32, ImageFormat::Png);
+    fs::write(dir.join("Dot.PNG"), &png).unwrap();
+
+    let options = ImageOptions {
+      max_dimension: 16,
+      ..ImageOptions::default()
+    };
+    let mut store = Store::open(&dir).unwrap();
+    let dot = store.add(&dir.join("Dot.PNG"), &options).unwrap();
+    assert_eq!(dot.hash, model::digest(&png));
+    assert_eq!(dot.mime, "image/png");
+    assert_eq!(dot.link(), format!("notex://{}.png", dot.hash));
+    assert_eq!(hash_of(&dot.link()), Some(dot.hash.as_str()));
+    assert!(store_dir(&dir).join(dot.file_name()).is_file());
+    let image = dot.image.clone().unwrap();
+    assert_eq!((image.width, image.height), (64, 32));
+    let optimized = format!("{}.optimized.png", dot.hash);
+    assert_eq!(image.optimized.as_ref(), Some(&optimized));
+    assert!(store_dir(&dir).join(&optimized).is_file());
+    assert!(store_dir(&dir)
+      .join(image.thumbnail.as_ref().unwrap())
+      .is_file());
+    // the same content is stored once
+    assert_eq!(store.add_bytes("copy.png", &png, &options).unwrap(), dot);
+    // what can't be decoded is kept as it is
+    let broken = store
+      .add_bytes("broken.png", &[0x89, b'P', b'N', b'G'], &options)
+      .unwrap();
+    assert_eq!(broken.image, None);
+    store.index.get_mut(&broken.hash).unwrap().added_at = 0;
+    let fresh = store.add_bytes("fresh.txt", b"fresh", &options).unwrap();
+
+    let body = format!("![dot]({})", dot.link());
+    assert_eq!(
+      store.with_variant(&body, Variant::Optimized),
+      format!("![dot](notex://{})", optimized)
+    );
+    assert_eq!(store.with_variant(&body, Variant::Original), body);
+    store.set_references("note", &body);
+    store.set_references("other", &format!("[broken]({})", broken.link()));
+    store.set_references("other", "no links");
+    store.save().unwrap();
+
+    let mut store = Store::open(&dir).unwrap();
+    assert_eq!(
+      store.get(&dot.hash).unwrap().notes,
+      vec![String::from("note")].into_iter().collect()
+    );
+    assert!(store.get(&broken.hash).unwrap().notes.is_empty());
+
+    // the variants go with the original
+    store.index.get_mut(&dot.hash).unwrap().added_at = 0;
+    let removed = store
+      .gc(&[(
+        String::from("renamed"),
+        format!("![broken]({})", broken.link()),
+      )])
+      .unwrap();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].hash, dot.hash);
+    assert!(!store_dir(&dir).join(dot.file_name()).exists());
+    assert!(!store_dir(&dir).join(&optimized).exists());
+    assert!(store.get(&fresh.hash).is_some());
+    assert_eq!(
+      store.get(&broken.hash).unwrap().notes,
+      vec![String::from("renamed")].into_iter().collect()
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
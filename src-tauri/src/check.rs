@@ -0,0 +1,587 @@
+use super::attachments::{self, Store};
+use super::constants::TARGET_EXTENTION;
+use super::links::{self, LinkGraph};
+use super::model::Meta;
+use super::render::{self, anchor};
+use percent_encoding::percent_decode_str;
+use pulldown_cmark::{BrokenLink, Event, LinkType, Parser, Tag};
+use std::{
+  collections::{BTreeMap, HashMap},
+  io::{Read, Write},
+  net::{TcpStream, ToSocketAddrs},
+  path::Path,
+  time::Duration,
+};
+
+/*
+ * checking the links in the bodies, which rot as notes are renamed and attachments are collected.
+ * - wiki links are checked as the markdown links which they render into -- see links.rs
+ * - a link without a scheme is a note by its title, its file name or its body path, or else a file in the target directory
+ * - a fragment has to be an anchor of the heading in the linked note, and of the note itself for `#anchor`
+ * - `notex://` links have to be in the attachment store with their file
+ * - reference links to an undefined reference and link syntax left as text are malformed
+ * - headings whose anchors collide can't be linked but the first
+ * external urls are requested only if asked, with HEAD and then GET if it is not allowed.
+ * the proxy, e.g. a local stand-in for tests, gets every request in the absolute form,
+ * and https is not checked without it.
+ */
+
+static DEFAULT_TIMEOUT: u64 = 5000;
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ExternalOptions {
+  pub proxy: Option<String>, //`host:port` or `http://host:port` to send every request to
+  pub timeout: u64,          //in millis, per request
+}
+impl Default for ExternalOptions {
+  fn default() -> Self {
+    ExternalOptions {
+      proxy: None,
+      timeout: DEFAULT_TIMEOUT,
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CheckOptions {
+  pub external: Option<ExternalOptions>, //if None, no network access
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+#[serde(tag = "kind")]
+pub enum Problem {
+  #[serde(rename = "missing_note")]
+  MissingNote { line: usize, dest: String },
+  #[serde(rename = "missing_heading")]
+  MissingHeading { line: usize, dest: String },
+  #[serde(rename = "missing_attachment")]
+  MissingAttachment { line: usize, dest: String },
+  #[serde(rename = "missing_file")]
+  MissingFile { line: usize, dest: String },
+  #[serde(rename = "malformed_link")]
+  MalformedLink { line: usize, text: String },
+  #[serde(rename = "duplicate_anchor")]
+  DuplicateAnchor { line: usize, anchor: String },
+  #[serde(rename = "broken_url")]
+  BrokenUrl {
+    line: usize,
+    dest: String,
+    status: Option<u16>,
+    error: Option<String>,
+  },
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct DocumentReport {
+  pub title: String,
+  pub problems: Vec<Problem>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize)]
+pub struct Report {
+  pub checked: usize,                 //documents
+  pub documents: Vec<DocumentReport>, //with problems only
+  pub unchecked: Vec<String>, //external urls which could not be requested, i.e. https without the proxy
+}
+
+enum Dest {
+  Note(String, Option<String>), //title or path, and the fragment
+  Attachment(String),
+  Url(String),
+  Other, //other schemes, e.g. mailto
+}
+
+fn classify(dest: &str) -> Dest {
+  if dest.starts_with(attachments::SCHEME) {
+    return Dest::Attachment(dest.to_string());
+  }
+  let scheme = dest.find(':').map(|i| &dest[..i]).filter(|s| {
+    s.chars()
+      .next()
+      .map(|c| c.is_ascii_alphabetic())
+      .unwrap_or(false)
+      && s
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.')
+  });
+  match scheme.map(|s| s.to_ascii_lowercase()) {
+    Some(s) if s == "http" || s == "https" => Dest::Url(dest.to_string()),
+    Some(_) => Dest::Other,
+    None => {
+      let (path, fragment) = match dest.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment.to_string())),
+        None => (dest, None),
+      };
+      let path = percent_decode_str(path).decode_utf8_lossy();
+      Dest::Note(path.trim_start_matches("./").to_string(), fragment)
+    }
+  }
+}
+
+fn line_of(md: &str, offset: usize) -> usize {
+  md[..offset.min(md.len())].matches('\n').count() + 1
+}
+
+/*
+ * link syntax in a run of text, which the parser did not take as a link, from its bracket to the next space.
+ */
+fn left_link((line, text): (usize, String)) -> Option<Problem> {
+  let middle = text.find("](")?;
+  let start = text[..middle].rfind('[').unwrap_or(0);
+  let end = text[middle..]
+    .find(char::is_whitespace)
+    .map(|i| middle + i)
+    .unwrap_or_else(|| text.len());
+  Some(Problem::MalformedLink {
+    line,
+    text: text[start..end].to_string(),
+  })
+}
+
+/*
+ * the problems of a body, and the external urls with their lines to be requested later.
+ */
+fn scan(
+  md: &str,
+  title: &str,
+  names: &HashMap<String, String>,
+  graph: &LinkGraph,
+  store: &Store,
+  target_dir: &Path,
+) -> (Vec<Problem>, Vec<(usize, String)>) {
+  // one line per line, so the offsets are lines of the body
+  let md = links::to_markdown(md);
+  let mut problems = vec![];
+  let mut urls = vec![];
+  let mut broken = vec![];
+  let mut callback = |link: BrokenLink| {
+    if link.link_type == LinkType::Reference || link.link_type == LinkType::Collapsed {
+      broken.push((link.span.start, link.reference.to_string()));
+    }
+    None
+  };
+  let parser = Parser::new_with_broken_link_callback(&md, render::options(), Some(&mut callback));
+
+  let anchors_of = |title: &str| {
+    graph
+      .get(title)
+      .map(|entry| entry.anchors.clone())
+      .unwrap_or_default()
+  };
+  let mut headings: Vec<(usize, String)> = vec![];
+  let mut heading: Option<(usize, String)> = None;
+  let mut in_code = false;
+  // the parser splits text at brackets, so the runs of text are joined to find link syntax left in them
+  let mut run: Option<(usize, String)> = None;
+  for (event, range) in parser.into_offset_iter() {
+    let line = line_of(&md, range.start);
+    match (&event, run.as_mut()) {
+      (Event::Text(text), Some((_, acc))) => acc.push_str(text),
+      (Event::Text(text), None) if !in_code && heading.is_none() => {
+        run = Some((line, text.to_string()))
+      }
+      _ => problems.extend(run.take().and_then(left_link)),
+    }
+    match event {
+      Event::Start(Tag::Heading(_)) => heading = Some((line, String::new())),
+      Event::End(Tag::Heading(_)) => headings.extend(heading.take()),
+      Event::Start(Tag::CodeBlock(_)) => in_code = true,
+      Event::End(Tag::CodeBlock(_)) => in_code = false,
+      Event::Text(text) | Event::Code(text) if heading.is_some() => {
+        if let Some((_, acc)) = heading.as_mut() {
+          acc.push_str(&text);
+        }
+      }
+      Event::Start(Tag::Link(_, dest, _)) | Event::Start(Tag::Image(_, dest, _)) => {
+        let dest = dest.to_string();
+        if dest.trim().is_empty() {
+          problems.push(Problem::MalformedLink {
+            line,
+            text: md[range].to_string(),
+          });
+          continue;
+        }
+        match classify(&dest) {
+          Dest::Attachment(link) => {
+            let found = attachments::hash_of(&link)
+              .and_then(|hash| store.get(hash))
+              .map(|attachment| store.path_of(attachment).is_file())
+              .unwrap_or(false);
+            if !found {
+              problems.push(Problem::MissingAttachment { line, dest });
+            }
+          }
+          Dest::Url(url) => urls.push((line, url)),
+          Dest::Other => (),
+          Dest::Note(path, fragment) if path.is_empty() => {
+            if let Some(fragment) = fragment {
+              if !anchors_of(title).contains(&fragment) {
+                problems.push(Problem::MissingHeading { line, dest });
+              }
+            }
+          }
+          Dest::Note(path, fragment) => match names.get(&path.to_lowercase()) {
+            Some(target) => {
+              if let Some(fragment) = fragment {
+                if !anchors_of(target).contains(&fragment) {
+                  problems.push(Problem::MissingHeading { line, dest });
+                }
+              }
+            }
+            None if target_dir.join(&path).exists() => (),
+            None if path.ends_with(TARGET_EXTENTION) || !path.contains('.') => {
+              problems.push(Problem::MissingNote { line, dest })
+            }
+            None => problems.push(Problem::MissingFile { line, dest }),
+          },
+        }
+      }
+      _ => (),
+    }
+  }
+
+  problems.extend(run.take().and_then(left_link));
+  for (offset, reference) in broken {
+    problems.push(Problem::MalformedLink {
+      line: line_of(&md, offset),
+      text: format!("[{}]", reference),
+    });
+  }
+
+  let mut seen = HashMap::new();
+  for (line, text) in headings {
+    let id = anchor(&text);
+    if seen.insert(id.clone(), line).is_some() {
+      problems.push(Problem::DuplicateAnchor { line, anchor: id });
+    }
+  }
+  (problems, urls)
+}
+
+/*
+ * the names which a link can call a note by, lowercased.
+ * the title first, so that it wins over the file name of another note.
+ */
+fn names_of(metas: &[&Meta]) -> HashMap<String, String> {
+  let mut names = HashMap::new();
+  for meta in metas {
+    names.insert(
+      meta.get_filename().to_lowercase(),
+      meta.get_filename().to_string(),
+    );
+  }
+  for meta in metas {
+    for name in [
+      format!("{}{}", meta.get_filename(), TARGET_EXTENTION),
+      meta.body_path(),
+      meta.get_hashed_filename(),
+    ] {
+      names
+        .entry(name.to_lowercase())
+        .or_insert_with(|| meta.get_filename().to_string());
+    }
+  }
+  names
+}
+
+/*
+ * the status of the url, or why it could not be requested. Ok(None) if it is not checked.
+ */
+fn request(url: &str, options: &ExternalOptions) -> Result<Option<u16>, String> {
+  let (scheme, rest) = url.split_once("://").ok_or("invalid url")?;
+  let authority = rest.split(&['/', '?', '#'][..]).next().unwrap_or("");
+  let host = authority.rsplit('@').next().unwrap_or(authority);
+  let path = &rest[authority.len()..];
+  let path = path.split('#').next().unwrap_or("");
+  let path = if path.is_empty() { "/" } else { path };
+
+  let (addr, target) = match &options.proxy {
+    Some(proxy) => {
+      let proxy = proxy.trim_start_matches("http://").trim_end_matches('/');
+      (
+        proxy.to_string(),
+        url.split('#').next().unwrap_or(url).to_string(),
+      )
+    }
+    None if scheme.eq_ignore_ascii_case("http") => {
+      let addr = if host.contains(':') {
+        host.to_string()
+      } else {
+        format!("{}:80", host)
+      };
+      (addr, path.to_string())
+    }
+    None => return Ok(None),
+  };
+
+  let timeout = Duration::from_millis(options.timeout);
+  let send = |method: &str| -> Result<u16, String> {
+    let addr = addr
+      .to_socket_addrs()
+      .map_err(|err| err.to_string())?
+      .next()
+      .ok_or("host is not found")?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(|err| err.to_string())?;
+    stream
+      .set_read_timeout(Some(timeout))
+      .and_then(|_| stream.set_write_timeout(Some(timeout)))
+      .map_err(|err| err.to_string())?;
+    let request = format!(
+      "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: notex\r\nConnection: close\r\n\r\n",
+      method, target, host
+    );
+    stream
+      .write_all(request.as_bytes())
+      .map_err(|err| err.to_string())?;
+    let mut head = [0u8; 64];
+    let mut len = 0;
+    while len < head.len() {
+      match stream.read(&mut head[len..]) {
+        Ok(0) => break,
+        Ok(n) => len += n,
+        Err(err) => return Err(err.to_string()),
+      }
+      if head[..len].contains(&b'\n') {
+        break;
+      }
+    }
+    String::from_utf8_lossy(&head[..len])
+      .split_whitespace()
+      .nth(1)
+      .and_then(|status| status.parse().ok())
+      .ok_or_else(|| String::from("invalid response"))
+  };
+
+  match send("HEAD")? {
+    405 | 501 => send("GET").map(Some),
+    status => Ok(Some(status)),
+  }
+}
+
+/*
+ * check all the notes, pairs of meta and body without front matter.
+ */
+pub fn check(
+  notes: &[(Meta, String)],
+  graph: &LinkGraph,
+  store: &Store,
+  target_dir: &Path,
+  options: &CheckOptions,
+) -> Report {
+  let names = names_of(&notes.iter().map(|(meta, _)| meta).collect::<Vec<_>>());
+  let mut report = Report {
+    checked: notes.len(),
+    ..Report::default()
+  };
+  let mut statuses = BTreeMap::new();
+
+  for (meta, body) in notes {
+    let title = meta.get_filename();
+    let (mut problems, urls) = scan(body, title, &names, graph, store, target_dir);
+    if let Some(external) = &options.external {
+      for (line, url) in urls {
+        let res = statuses
+          .entry(url.clone())
+          .or_insert_with(|| request(&url, external));
+        match res {
+          Ok(Some(status)) if 400 <= *status => problems.push(Problem::BrokenUrl {
+            line,
+            dest: url,
+            status: Some(*status),
+            error: None,
+          }),
+          Ok(Some(_)) => (),
+          Ok(None) => {
+            if !report.unchecked.contains(&url) {
+              report.unchecked.push(url);
+            }
+          }
+          Err(err) => problems.push(Problem::BrokenUrl {
+            line,
+            dest: url,
+            status: None,
+            error: Some(err.clone()),
+          }),
+        }
+      }
+    }
+    if !problems.is_empty() {
+      problems.sort_by_key(|problem| match problem {
+        Problem::MissingNote { line, .. }
+        | Problem::MissingHeading { line, .. }
+        | Problem::MissingAttachment { line, .. }
+        | Problem::MissingFile { line, .. }
+        | Problem::MalformedLink { line, .. }
+        | Problem::DuplicateAnchor { line, .. }
+        | Problem::BrokenUrl { line, .. } => *line,
+      });
+      report.documents.push(DocumentReport {
+        title: title.to_string(),
+        problems,
+      });
+    }
+  }
+  report
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::{self, get_random_key};
+  use std::{fs, net::TcpListener, thread};
+
+  fn meta(title: &str) -> Meta {
+    tests::meta(title, &[], "")
+  }
+
+  #[test]
+  fn check_links_test() {
+    let dir = std::env::temp_dir().join(format!("notex-check-{}", get_random_key()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("data.csv"), "").unwrap();
+    let mut store = Store::open(&dir).unwrap();
+    let clip = store
+      .add_bytes("clip.mp3", b"0123", &Default::default())
+      .unwrap();
+
+    let a = format!(
+      "# Intro\n\n[[B#Part]] [[B#None]] [[C]] [b](b.md) [#](#intro) [#](#outro)\n\n\
+       [clip]({}) [gone](notex://{}.png) [csv](data.csv) [pdf](paper.pdf)\n\n\
+       [ref][nowhere] [unclosed](b\n\n[empty]()\n\n`[[C]]` [mail](mailto:a@b.c)\n\n# Intro\n",
+      clip.link(),
+      "0".repeat(64)
+    );
+    let notes = vec![
+      (meta("A"), a),
+      (
+        meta("B"),
+        String::from("## Part\n\n[site](http://example.test/ok)"),
+      ),
+    ];
+    let graph = LinkGraph::build(
+      &notes
+        .iter()
+        .map(|(m, b)| (m.get_filename().to_string(), b.clone()))
+        .collect::<Vec<_>>(),
+    );
+
+    let report = check(&notes, &graph, &store, &dir, &CheckOptions::default());
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.documents.len(), 1);
+    let problems = &report.documents[0].problems;
+    assert_eq!(
+      problems,
+      &vec![
+        Problem::MissingHeading {
+          line: 3,
+          dest: String::from("B#none"),
+        },
+        Problem::MissingNote {
+          line: 3,
+          dest: String::from("C"),
+        },
+        Problem::MissingHeading {
+          line: 3,
+          dest: String::from("#outro"),
+        },
+        Problem::MissingAttachment {
+          line: 5,
+          dest: format!("notex://{}.png", "0".repeat(64)),
+        },
+        Problem::MissingFile {
+          line: 5,
+          dest: String::from("paper.pdf"),
+        },
+        Problem::MalformedLink {
+          line: 7,
+          text: String::from("[unclosed](b"),
+        },
+        Problem::MalformedLink {
+          line: 7,
+          text: String::from("[nowhere]"),
+        },
+        Problem::MalformedLink {
+          line: 9,
+          text: String::from("[empty]()"),
+        },
+        Problem::DuplicateAnchor {
+          line: 13,
+          anchor: String::from("intro"),
+        },
+      ]
+    );
+
+    // a stand-in which answers 404 to the second request, i.e. GET after 405
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = listener.local_addr().unwrap().to_string();
+    let server = thread::spawn(move || {
+      let mut requests = vec![];
+      for (i, stream) in listener.incoming().take(2).enumerate() {
+        let mut stream = stream.unwrap();
+        let mut buf = vec![];
+        let mut chunk = [0u8; 512];
+        while !buf.ends_with(b"\r\n\r\n") {
+          match stream.read(&mut chunk).unwrap() {
+            0 => break,
+            len => buf.extend_from_slice(&chunk[..len]),
+          }
+        }
+        requests.push(
+          String::from_utf8_lossy(&buf)
+            .lines()
+            .next()
+            .unwrap()
+            .to_string(),
+        );
+        let status = if i == 0 {
+          "405 Method Not Allowed"
+        } else {
+          "404 Not Found"
+        };
+        write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+      }
+      requests
+    });
+    let options = CheckOptions {
+      external: Some(ExternalOptions {
+        proxy: Some(proxy),
+        timeout: 2000,
+      }),
+    };
+    let report = check(&notes[1..], &graph, &store, &dir, &options);
+    assert_eq!(
+      report.documents[0].problems,
+      vec![Problem::BrokenUrl {
+        line: 3,
+        dest: String::from("http://example.test/ok"),
+        status: Some(404),
+        error: None,
+      }]
+    );
+    assert_eq!(
+      server.join().unwrap(),
+      vec![
+        String::from("HEAD http://example.test/ok HTTP/1.1"),
+        String::from("GET http://example.test/ok HTTP/1.1"),
+      ]
+    );
+
+    // https is left unchecked without the proxy
+    let notes = vec![(meta("C"), String::from("[s](https://example.test)"))];
+    let report = check(
+      &notes,
+      &graph,
+      &store,
+      &dir,
+      &CheckOptions {
+        external: Some(ExternalOptions::default()),
+      },
+    );
+    assert_eq!(report.unchecked, vec![String::from("https://example.test")]);
+    assert!(report.documents.is_empty());
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
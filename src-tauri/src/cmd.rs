@@ -1,10 +1,35 @@
-use super::model::Meta;
-use super::{Casher, Env, HiddenWindow, Setting};
+use super::attachments::{self, Attachment};
+use super::check::{self, CheckOptions};
+use super::daily::{self, CalendarDay};
+use super::doctemplate::{self, DocTemplate, Vars};
+use super::epub::{self, EpubError, EpubOptions};
+use super::frontmatter;
+use super::graph::{self, Graph, GraphOptions};
+use super::images::Variant;
+use super::jobs::{JobId, Jobs};
+use super::latex;
+use super::layout::{self, Layout};
+use super::links::{Backlink, LinkGraph, Unresolved};
+use super::lock::IndexLock;
+use super::macros;
+use super::merge::{self, Merged};
+use super::migrate::{self, MigrateError, Transfer};
+use super::model::{self, Filter, Matter, Meta};
+use super::pdf::{Browser, PdfError};
+use super::render;
+use super::site::{self, SiteError};
+use super::standalone;
+use super::stats::{self, Bucket, StatsOptions};
+use super::template;
+use super::{Casher, Env, HiddenWindow, MainWindow, Memo, Setting};
+use chrono::Local;
 use std::{
-  collections::HashSet,
+  collections::{HashMap, HashSet},
   fs::{self, File, OpenOptions},
   io::{BufRead, BufReader, Write},
   path::{Path, PathBuf},
+  sync::mpsc,
+  time::{Duration, UNIX_EPOCH},
 };
 use tauri::State;
 
@@ -17,15 +42,31 @@ enum Code {
   Redirect,
   #[serde(rename = "400")]
   ClientError,
+  #[serde(rename = "409")]
+  Conflict,
   #[serde(rename = "500")]
   ProcessError,
 }
 
+/*
+ * detail of the conflict response.
+ * on_disk is the body edited outside the app, and proposal is the three-way merge of the base, the editing body and on_disk.
+ */
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Conflict {
+  on_disk: String,
+  proposal: Merged,
+}
+
 #[derive(Debug, PartialEq, Clone, serde::Serialize)]
 pub struct Response {
   #[serde(flatten)]
   code: Code,
   message: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  conflict: Option<Conflict>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  meta: Option<Meta>, //as stored in the index, for the client to send back instead of its own copy
 }
 impl Response {
   pub fn new<T: ToString + std::fmt::Debug>(message: T) -> Response {
@@ -33,6 +74,8 @@ impl Response {
     Response {
       code: Code::Success,
       message: message.to_string(),
+      conflict: None,
+      meta: None,
     }
   }
 
@@ -41,6 +84,8 @@ impl Response {
     Response {
       code: Code::ClientError,
       message: message.to_string(),
+      conflict: None,
+      meta: None,
     }
   }
 
@@ -49,6 +94,25 @@ impl Response {
     Response {
       code: Code::ProcessError,
       message: format!("{}\n{}", "Internal Process Error", message.to_string()),
+      conflict: None,
+      meta: None,
+    }
+  }
+
+  pub fn conflict<T: ToString + std::fmt::Debug>(message: T, conflict: Conflict) -> Response {
+    println!("conflict: {:?}", message);
+    Response {
+      code: Code::Conflict,
+      message: message.to_string(),
+      conflict: Some(conflict),
+      meta: None,
+    }
+  }
+
+  pub fn saved<T: ToString + std::fmt::Debug>(message: T, meta: Meta) -> Response {
+    Response {
+      meta: Some(meta),
+      ..Response::new(message)
     }
   }
 }
@@ -69,6 +133,18 @@ pub fn update_setting(setting: Setting, env: State<'_, Env>) -> Result<Response,
     .open(crate::conf_path())
     .map_err(Response::process_error)?;
   let mut inner = env.0.lock().map_err(Response::process_error)?;
+  if inner.target_dir != setting.target_dir {
+    return Err(Response::client_error(
+      "target directory must be changed with change_target_dir",
+    ));
+  }
+  if inner.layout != setting.layout {
+    return Err(Response::client_error(
+      "layout must be changed with change_layout",
+    ));
+  }
+  macros::table(&setting.macros).map_err(Response::client_error)?;
+  daily::validate(&setting.daily).map_err(Response::client_error)?;
   *inner = setting;
   match serde_json::to_writer(conf, &*inner) {
     Ok(_) => Ok(Response::new("Setting successfully updated.")),
@@ -76,11 +152,142 @@ pub fn update_setting(setting: Setting, env: State<'_, Env>) -> Result<Response,
   }
 }
 
+fn write_index(metas: &[Meta]) -> Result<(), Response> {
+  let mut lines = vec![];
+  for meta in metas {
+    lines.push(serde_json::to_string(meta).map_err(Response::process_error)?);
+  }
+  File::create(crate::index_path())
+    .map_err(Response::process_error)?
+    .write_all(lines.join("\n").as_bytes())
+    .map_err(Response::process_error)
+}
+
+/*
+ * the entries of the documents in the index, which the metas sent by the client may be older than.
+ */
+fn lookup_all(metas: &[Meta]) -> Result<Vec<Meta>, Response> {
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let stored = read_index()?
+    .into_iter()
+    .map(|m| (m.get_hashed_filename(), m))
+    .collect::<HashMap<_, _>>();
+  metas
+    .iter()
+    .map(|meta| {
+      stored
+        .get(&meta.get_hashed_filename())
+        .cloned()
+        .ok_or_else(|| Response::client_error(format!("{} is not found", meta.get_filename())))
+    })
+    .collect()
+}
+
+fn lookup(meta: &Meta) -> Result<Meta, Response> {
+  lookup_all(std::slice::from_ref(meta)).map(|mut metas| metas.remove(0))
+}
+
+/*
+ * keep the memo exactly the same as the index just written, or the workspace watcher regards own saves as external changes.
+ */
+fn refresh_memo(memo: &mut Memo) {
+  match Memo::from_index() {
+    Ok(fresh) => *memo = fresh,
+    Err(err) => println!("WARNING: failed to refresh index\n{}", err.to_string()),
+  }
+}
+
+fn read_index() -> Result<Vec<Meta>, Response> {
+  let mut metas = vec![];
+  let lines =
+    BufReader::new(File::open(crate::index_path()).map_err(Response::process_error)?).lines();
+  for line in lines {
+    let line = line.map_err(Response::process_error)?;
+    if !line.is_empty() {
+      metas.push(serde_json::from_str::<Meta>(&line).map_err(Response::process_error)?);
+    }
+  }
+  Ok(metas)
+}
+
+/*
+ * move or copy all the bodies, html sources, attachments and templates into the new target directory, then switch the setting.
+ * progress is notified with event "migrate_progress" -- see migrate::Progress.
+ */
+#[tauri::command]
+pub fn change_target_dir(
+  path: PathBuf,
+  mode: Transfer,
+  env: State<'_, Env>,
+  main: State<'_, MainWindow>,
+) -> Result<Response, Response> {
+  let mut setting = env.0.lock().map_err(Response::process_error)?;
+  let main = main.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let metas = read_index()?;
+  let from = setting.target_dir.clone();
+  let emit = |progress| {
+    if let Err(err) = main.emit("migrate_progress", progress) {
+      println!("{}", err);
+    }
+  };
+
+  let mut report =
+    migrate::transfer(&metas, &from, &path, emit).map_err(|err| match err {
+      MigrateError::Io(_) | MigrateError::HashMismatch(_) => Response::process_error(err),
+      _ => Response::client_error(err),
+    })?;
+
+  let mut new_setting = setting.clone();
+  new_setting.target_dir = path.clone();
+  if let Err(err) = new_setting.write_to_conf() {
+    migrate::revert(&metas, &from, &path, &report);
+    return Err(Response::process_error(err));
+  }
+  *setting = new_setting;
+
+  if mode == Transfer::Move {
+    migrate::cleanup(&metas, &from, &mut report, emit);
+  }
+
+  let mut message = format!(
+    "{} files successfully {}.",
+    report.transferred,
+    if mode == Transfer::Move { "moved" } else { "copied" }
+  );
+  if !report.missing.is_empty() {
+    message.push_str(&format!("\n{} bodies were not found.", report.missing.len()));
+  }
+  if !report.left.is_empty() {
+    message.push_str(&format!("\n{} files could not be removed.", report.left.len()));
+  }
+  Ok(Response::new(message))
+}
+
+fn modified_millis(path: &Path) -> Option<u64> {
+  fs::metadata(path)
+    .and_then(|m| m.modified())
+    .ok()
+    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    .map(|d| d.as_millis() as u64)
+}
+
+/*
+ * what the index keeps of the body, which has been written into path.
+ */
+fn refresh_derived(meta: &mut Meta, body: &str, path: &Path) {
+  meta.set_version(model::digest(body.as_bytes()), modified_millis(path));
+  meta.set_outline(render::outline(body));
+  meta.set_stats(stats::of(body));
+}
+
 #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SaveDoc {
   overwrite: bool,
   meta: Meta,
   body: String,
+  #[serde(default)]
+  base: Option<String>, //the body which the editing started from. if None, digest in meta is used
 }
 #[tauri::command]
 pub fn save_document(
@@ -90,14 +297,18 @@ pub fn save_document(
 ) -> Result<Response, Response> {
   let setting = env.0.lock().map_err(Response::new)?.clone();
   let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
   let SaveDoc {
     overwrite,
     mut meta,
     body,
+    base,
   } = document;
+  macros::table(meta.get_shortcut()).map_err(Response::client_error)?;
   meta.stamp();
-  let filename = meta.get_hashed_filename();
   let mut new_index = vec![];
+  let mut is_new_doc = true;
+  let mut taken = HashSet::new();
 
   let lines =
     BufReader::new(File::open(crate::index_path()).map_err(Response::process_error)?).lines();
@@ -105,22 +316,56 @@ pub fn save_document(
     let line = line.map_err(Response::new)?;
     let line_meta = serde_json::from_str::<Meta>(&line).map_err(Response::process_error)?;
     if !line.is_empty() && meta.get_hashed_filename() != line_meta.get_hashed_filename() {
+      taken.insert(line_meta.body_path().to_lowercase());
       new_index.push(line);
+    } else {
+      is_new_doc = false;
+      // the index knows where the body is placed better than the client
+      meta.set_path(line_meta.get_path());
+    }
+  }
+  if is_new_doc {
+    meta.set_path(layout::assign(
+      &meta,
+      setting.layout,
+      &taken,
+      &setting.target_dir,
+    ));
+  }
+  let path = setting.path_to_file(&meta.body_path());
+
+  if overwrite {
+    if let Ok(on_disk) = fs::read_to_string(&path) {
+      let on_disk = frontmatter::strip(&on_disk, setting.front_matter).to_string();
+      if meta.is_modified_outside(base.as_deref(), &on_disk) {
+        let proposal = merge::merge3(base.as_deref().unwrap_or(""), &body, &on_disk);
+        return Err(Response::conflict(
+          "File was modified outside the app. Check the merged body and save again.",
+          Conflict { on_disk, proposal },
+        ));
+      }
     }
   }
-  new_index
-    .push(serde_json::to_string(&meta).map_err(|_| Response::client_error("Invalid format."))?);
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(Response::process_error)?;
+  }
+  let src = frontmatter::attach(&meta, &body, setting.front_matter)
+    .map_err(Response::client_error)?;
 
   OpenOptions::new()
     .write(true)
     .append(false)
     .truncate(true)
     .create_new(!overwrite)
-    .open(setting.path_to_file(&filename))
+    .open(&path)
     .map_err(Response::process_error)?
-    .write_all(&body.as_bytes())
+    .write_all(&src.as_bytes())
     .map_err(Response::process_error)
     .and_then(|_| {
+      refresh_derived(&mut meta, &body, &path);
+      new_index.push(
+        serde_json::to_string(&meta).map_err(|_| Response::client_error("Invalid format."))?,
+      );
       OpenOptions::new()
         .write(true)
         .append(false)
@@ -132,14 +377,17 @@ pub fn save_document(
         .map_err(Response::process_error)
     })
     .map(|_| {
-      memo.all_tags = memo
-        .all_tags
-        .union(&meta.get_into_tag().into_iter().collect())
-        .map(|s| s.clone())
-        .collect();
-      memo.page += 1;
+      if let Err(err) = update_references(&setting.target_dir, |store| {
+        store.set_references(meta.get_filename(), &body)
+      }) {
+        println!("WARNING: attachment references are not updated\n{}", err.to_string());
+      }
+      if let Err(err) = update_links(|graph| graph.update(meta.get_filename(), &body)) {
+        println!("WARNING: link graph is not updated\n{}", err.to_string());
+      }
+      refresh_memo(&mut memo);
       println!("page: {}, all_tags: {:?}", memo.page, memo.all_tags);
-      Response::new("File successfully saved")
+      Response::saved("File successfully saved", meta)
     })
 }
 
@@ -151,8 +399,9 @@ pub fn delete_file(
 ) -> Result<Response, Response> {
   let setting = env.0.lock().map_err(Response::process_error)?;
   let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
   let target_name = target.get_hashed_filename();
-  let path = setting.path_to_file(&target_name);
+  let mut path = setting.path_to_file(&target.body_path());
   let mut new_index = vec![];
   let lines =
     BufReader::new(File::open(crate::index_path()).map_err(Response::process_error)?).lines();
@@ -162,6 +411,8 @@ pub fn delete_file(
     let meta = serde_json::from_str::<Meta>(&line).map_err(Response::process_error)?;
     if target_name != meta.get_hashed_filename() {
       new_index.push(line);
+    } else {
+      path = setting.path_to_file(&meta.body_path());
     }
   }
 
@@ -177,13 +428,14 @@ pub fn delete_file(
         .map_err(Response::process_error)
     })
     .map(|_| {
-      memo.all_tags = memo
-        .all_tags
-        .difference(&target.get_into_tag().into_iter().collect())
-        .map(|s| s.clone())
-        .collect();
-      if 0 < memo.page {
-        memo.page -= 1;
+      refresh_memo(&mut memo);
+      if let Err(err) = update_references(&setting.target_dir, |store| {
+        store.remove_note(target.get_filename())
+      }) {
+        println!("WARNING: attachment references are not updated\n{}", err.to_string());
+      }
+      if let Err(err) = update_links(|graph| graph.remove(target.get_filename())) {
+        println!("WARNING: link graph is not updated\n{}", err.to_string());
       }
       println!("page: {}, all_tags: {:?}", memo.page, memo.all_tags);
       Response::new("File successfully deleted")
@@ -193,14 +445,9 @@ pub fn delete_file(
 #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct RequestDocs {
   offset: usize,
-  limit: usize,                     //if 0, return all satisfied docs meta
-  filename_start: String,           //if empty, ignored
-  filename_contain: String,         //if empty, ignored
-  created_at: (String, String), //left is min, right is max. if invalid format, the one is ignored
-  updated_at: (String, String), //left is min, right is max. if invalid format, the one is ignored
-  tags: Vec<String>,            //if length is 0, ignored
-  author: String,               //if empty, ignored
-  is_html_src_exists: Option<bool>, //if None,  ignored
+  limit: usize, //if 0, return all satisfied docs meta
+  #[serde(flatten)]
+  filter: Filter,
 }
 #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct ResponseDocs {
@@ -218,31 +465,12 @@ pub fn get_documents_by_filter(
   let RequestDocs {
     mut offset,
     limit,
-    filename_start,
-    filename_contain,
-    created_at,
-    updated_at,
-    tags,
-    author,
-    is_html_src_exists,
+    filter,
   } = req;
   let mut lines =
     BufReader::new(File::open(crate::index_path()).map_err(Response::process_error)?).lines();
 
-  let filter = |meta: &Meta| -> Result<bool, Response> {
-    Ok(
-      meta.filter_by_filename(&filename_start, &filename_contain)
-        && meta
-          .filter_by_created(&created_at.0, &created_at.1)
-          .map_err(Response::process_error)?
-        && meta
-          .filter_by_updated(&updated_at.0, &updated_at.1)
-          .map_err(Response::process_error)?
-        && meta.filter_by_tags(&tags)
-        && meta.filter_by_author(&author)
-        && meta.filter_by_html_src(is_html_src_exists),
-    )
-  };
+  let filter = |meta: &Meta| filter.matches(meta).map_err(Response::process_error);
 
   if limit == 0 {
     while let Some(line) = lines.next() {
@@ -315,8 +543,146 @@ pub fn get_documents_by_filter(
 #[tauri::command]
 pub fn get_document(meta: Meta, env: State<Env>) -> Result<String, Response> {
   let setting = env.0.lock().map_err(Response::process_error)?;
-  let path = setting.path_to_file(&meta.get_hashed_filename());
-  fs::read_to_string(path).map_err(Response::process_error)
+  let path = setting.path_to_file(&meta.body_path());
+  fs::read_to_string(path)
+    .map(|src| frontmatter::strip(&src, setting.front_matter).to_string())
+    .map_err(Response::process_error)
+}
+
+/*
+ * render the body into an html fragment without the webview.
+ * math and diagrams are left for template.js, see render.rs
+ */
+#[tauri::command]
+pub fn render_document(meta: Meta, env: State<Env>) -> Result<String, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?;
+  let path = setting.path_to_file(&meta.body_path());
+  fs::read_to_string(path)
+    .map(|src| {
+      let macros = macros::merge(&setting.macros, meta.get_shortcut());
+      render::render_with(frontmatter::strip(&src, setting.front_matter), |_| None, &macros).html
+    })
+    .map_err(Response::process_error)
+}
+
+/*
+ * rebuild index.jsonl from front matter of the bodies in the target directory.
+ * bodies without front matter keep their current index entries, and the others are skipped.
+ * so are the bodies whose title duplicates another one.
+ */
+#[tauri::command]
+pub fn rebuild_index(env: State<Env>, cashe: State<Casher>) -> Result<Response, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let old = read_index().unwrap_or_default();
+  let mut metas: Vec<Meta> = vec![];
+  let mut skipped = vec![];
+
+  for name in layout::list_bodies(&setting.target_dir).map_err(Response::process_error)? {
+    let path = setting.path_to_file(&name);
+    let src = fs::read_to_string(&path).map_err(Response::process_error)?;
+    let prev = old.iter().find(|m| m.body_path() == name);
+    match frontmatter::parse(&src) {
+      Some(Ok(mut meta))
+        if metas
+          .iter()
+          .all(|m| m.get_hashed_filename() != meta.get_hashed_filename()) =>
+      {
+        if let Some(prev) = prev {
+          meta.inherit(prev);
+        }
+        if meta.get_hashed_filename() != name {
+          meta.set_path(Some(name));
+        }
+        refresh_derived(
+          &mut meta,
+          frontmatter::strip(&src, setting.front_matter),
+          &path,
+        );
+        metas.push(meta);
+      }
+      _ => match prev {
+        Some(prev) => metas.push(prev.clone()),
+        None => skipped.push(name),
+      },
+    }
+  }
+
+  write_index(&metas)?;
+  if let Err(err) = read_notes(&setting, &metas).and_then(|notes| {
+    LinkGraph::build(&notes)
+      .save(&crate::links_path())
+      .map_err(Response::process_error)
+  }) {
+    println!("WARNING: link graph is not rebuilt\n{}", err.message);
+  }
+
+  refresh_memo(&mut memo);
+  Ok(Response::new(format!(
+    "index rebuilt with {} documents. {} files were skipped.",
+    metas.len(),
+    skipped.len()
+  )))
+}
+
+/*
+ * move all the bodies into the given layout, then switch the setting.
+ * if something fails partway, the bodies, the index and the setting are left as they were.
+ */
+#[tauri::command]
+pub fn change_layout(
+  layout: Layout,
+  env: State<Env>,
+  cashe: State<Casher>,
+) -> Result<Response, Response> {
+  let mut setting = env.0.lock().map_err(Response::process_error)?;
+  let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let metas = read_index()?;
+  let plan = layout::plan(&metas, layout, &setting.target_dir);
+  layout::relocate(&metas, &plan, &setting.target_dir).map_err(Response::process_error)?;
+
+  let relocated = metas
+    .iter()
+    .zip(plan.iter())
+    .map(|(meta, relocation)| {
+      let mut meta = meta.clone();
+      meta.set_path(relocation.to.clone());
+      meta
+    })
+    .collect::<Vec<_>>();
+  let mut new_setting = setting.clone();
+  new_setting.layout = layout;
+
+  if let Err(err) = write_index(&relocated).and_then(|_| {
+    new_setting
+      .write_to_conf()
+      .map_err(Response::process_error)
+  }) {
+    let reverse = metas
+      .iter()
+      .zip(relocated.iter())
+      .map(|(meta, moved)| layout::Relocation {
+        from: moved.body_path(),
+        to: meta.get_path(),
+      })
+      .collect::<Vec<_>>();
+    if let Err(err) = layout::relocate(&metas, &reverse, &setting.target_dir)
+      .map_err(Response::process_error)
+      .and_then(|_| write_index(&metas))
+    {
+      println!("WARNING: failed to restore the layout\n{:?}", err);
+    }
+    refresh_memo(&mut memo);
+    return Err(err);
+  }
+  *setting = new_setting;
+  refresh_memo(&mut memo);
+  Ok(Response::new(format!(
+    "{} files successfully relocated.",
+    plan.len()
+  )))
 }
 
 #[tauri::command]
@@ -338,50 +704,1029 @@ pub fn ls_dir(search: &Path) -> Result<Vec<PathBuf>, Response> {
 }
 
 /*
- * finish this command don't mean whole process finish.
- * improve response with tauri event.
+ * print through the hidden window.
+ * it is a job, so that prints queue up instead of clobbering the window -- see jobs.rs
+ * event "ready" is emitted by the hidden window when the page is rendered -- see /src/pages/Listener.tsx
  */
+static READY_TIMEOUT: Duration = Duration::from_secs(30);
 #[derive(Debug, PartialEq, serde::Serialize)]
-struct PayloadPDF<'a> {
+struct PayloadPDF {
   meta: Meta,
-  body: &'a str,
+  body: String,
 }
 #[tauri::command]
-pub fn print(meta: Meta, body: &str, hidden: State<'_, HiddenWindow>) -> Result<(), Response> {
-  let hidden = hidden.0.lock().map_err(Response::process_error)?;
-  println!("{:?}, {}", meta, body);
-  hidden
-    .emit("print", PayloadPDF { meta, body })
-    .map_err(Response::process_error)?;
-  Ok(())
+pub fn print(
+  meta: Meta,
+  body: String,
+  hidden: State<'_, HiddenWindow>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let hidden = hidden.0.lock().map_err(Response::process_error)?.clone();
+  jobs.push(move |job| {
+    let (tx, rx) = mpsc::channel();
+    hidden.once("ready", move |e| {
+      let _ = tx.send(e.payload().unwrap_or_default().to_string());
+    });
+    hidden
+      .emit("print", PayloadPDF { meta, body })
+      .map_err(Response::process_error)?;
+    let status = rx
+      .recv_timeout(READY_TIMEOUT)
+      .map_err(|_| Response::process_error("the hidden window did not get ready"))?;
+    if status != "success" {
+      return Err(Response::process_error(status));
+    }
+
+    job.progress(1, 2, "sending to the printer...");
+    job.check()?;
+    /*
+     * if hidden window would not be showed once, the page execution is still not complete
+     * though the reason is unknown.
+     * the problem may be tauri's hidden window processing but not sure (also may be frontend implementation).
+     * need splitting process more to get what the problem is.
+     */
+    hidden
+      .show()
+      .and_then(|_| hidden.print())
+      .and_then(|_| hidden.hide())
+      .map_err(Response::process_error)?;
+    Ok(Response::new("sent to the printer"))
+  })
 }
 
 #[tauri::command]
-pub fn html(mut meta: Meta, htmlsrc: &str, path: PathBuf) -> Result<(), Response> {
-  if !path.exists() {
-    return Err(Response::client_error("given path can't be found"));
+pub fn html(meta: Meta, htmlsrc: &str, path: PathBuf) -> Result<(), Response> {
+  write_html(&meta, htmlsrc, &path, |_| ())
+}
+
+/*
+ * render the body with the template on the rust side and write it into the path, as a job.
+ * if template is None, the one chosen last time for the document is used, or the builtin one.
+ * the choice is remembered in the index -- see template.rs
+ * if standalone, everything is put into the one file so that it can be read offline -- see standalone.rs
+ */
+#[tauri::command]
+pub fn export_html(
+  meta: Meta,
+  template: Option<String>,
+  standalone: bool,
+  variant: Option<Variant>,
+  path: PathBuf,
+  env: State<'_, Env>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  jobs.push(move |_| {
+    let meta = lookup(&meta)?;
+    let name = template.or_else(|| meta.get_template().map(String::from));
+    let engine =
+      template::load(&setting.target_dir, name.as_deref()).map_err(Response::client_error)?;
+    let body_path = setting.path_to_file(&meta.body_path());
+    let src = fs::read_to_string(&body_path).map_err(Response::process_error)?;
+    let macros = macros::merge(&setting.macros, meta.get_shortcut());
+    let (htmlsrc, missing) = if standalone {
+      let res = standalone::export(
+        &meta,
+        &with_variant(&setting, frontmatter::strip(&src, setting.front_matter), variant),
+        &macros,
+        &engine,
+        &image_dirs(&setting, &body_path),
+        &setting.target_dir,
+        setting.lang(),
+      );
+      (res.html, res.missing)
+    } else {
+      let rendered = render::render_with(frontmatter::strip(&src, setting.front_matter), |_| None, &macros);
+      (
+        engine.render(&template::context(&meta, &rendered, setting.lang())),
+        vec![],
+      )
+    };
+
+    let chosen = name.filter(|name| name != template::BUILTIN);
+    write_html(&meta, &htmlsrc, &path, |m| m.set_template(chosen))?;
+    if missing.is_empty() {
+      Ok(Response::new("html file successfully created"))
+    } else {
+      Ok(Response::new(format!(
+        "html file successfully created, but {} images couldn't be embedded: {}",
+        missing.len(),
+        missing.join(", ")
+      )))
+    }
+  })
+}
+
+/*
+ * the attachment links in the body pointed at the variant, or the one in the setting if None.
+ */
+fn with_variant(setting: &Setting, md: &str, variant: Option<Variant>) -> String {
+  match attachments::Store::open(&setting.target_dir) {
+    Ok(store) => store.with_variant(md, variant.unwrap_or(setting.images.export)),
+    Err(err) => {
+      println!("WARNING: attachments are exported as they are\n{}", err.to_string());
+      md.to_string()
+    }
   }
+}
 
+/*
+ * relative image paths in a body are looked up from its directory, then the target directory.
+ */
+fn image_dirs(setting: &Setting, body_path: &Path) -> Vec<PathBuf> {
+  body_path
+    .parent()
+    .map(Path::to_path_buf)
+    .into_iter()
+    .chain(std::iter::once(setting.target_dir.clone()))
+    .collect()
+}
+
+/*
+ * print the documents into `<path>/<slug>.pdf` by a headless browser, with the pdf options in the setting.
+ * every document is rendered with its template as a single file html. see pdf.rs
+ * it is a job which reports the progress per document and can be cancelled between them.
+ */
+#[tauri::command]
+pub fn export_pdf(
+  metas: Vec<Meta>,
+  variant: Option<Variant>,
+  path: PathBuf,
+  env: State<'_, Env>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
   if !path.is_dir() {
     return Err(Response::client_error("given path is not a directory"));
   }
+  jobs.push(move |job| {
+    let metas = lookup_all(&metas)?;
+    let mut browser = Browser::launch(&setting.pdf.browser).map_err(|err| match err {
+      PdfError::BrowserNotFound => Response::client_error(err),
+      _ => Response::process_error(err),
+    })?;
+    let slugs = site::unique_slugs(metas.iter().map(Meta::get_filename));
 
-  let lines =
-    BufReader::new(File::open(crate::index_path()).map_err(Response::process_error)?).lines();
-  let mut new_index = vec![];
-  let target_name = meta.get_hashed_filename();
+    for (i, meta) in metas.iter().enumerate() {
+      job.check()?;
+      job.progress(i, metas.len(), meta.get_filename());
+      let engine = template::load(&setting.target_dir, meta.get_template())
+        .map_err(Response::client_error)?;
+      let body_path = setting.path_to_file(&meta.body_path());
+      let src = fs::read_to_string(&body_path).map_err(Response::process_error)?;
+      let htmlsrc = standalone::export(
+        meta,
+        &with_variant(&setting, frontmatter::strip(&src, setting.front_matter), variant),
+        &macros::merge(&setting.macros, meta.get_shortcut()),
+        &engine,
+        &image_dirs(&setting, &body_path),
+        &setting.target_dir,
+        setting.lang(),
+      )
+      .html;
+      let pdf = browser
+        .print(&htmlsrc, &setting.pdf)
+        .map_err(Response::process_error)?;
+      fs::write(
+        path.join(format!("{}.pdf", slugs[meta.get_filename()])),
+        pdf,
+      )
+      .map_err(Response::process_error)?;
+    }
+    Ok(Response::new(format!(
+      "{} pdf files successfully created.",
+      metas.len()
+    )))
+  })
+}
 
-  for line in lines {
-    let line = line.map_err(Response::process_error)?;
-    let meta = serde_json::from_str::<Meta>(&line).map_err(Response::process_error)?;
-    if target_name != meta.get_hashed_filename() {
-      new_index.push(line);
+/*
+ * write the document into `<path>/<slug>.tex` with the preamble of the workspace. see latex.rs
+ */
+#[tauri::command]
+pub fn export_latex(
+  meta: Meta,
+  variant: Option<Variant>,
+  path: PathBuf,
+  env: State<'_, Env>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  if !path.is_dir() {
+    return Err(Response::client_error("given path is not a directory"));
+  }
+  jobs.push(move |_| {
+    let meta = lookup(&meta)?;
+    let body_path = setting.path_to_file(&meta.body_path());
+    let src = fs::read_to_string(&body_path).map_err(Response::process_error)?;
+    let res = latex::export(
+      &meta,
+      &with_variant(&setting, frontmatter::strip(&src, setting.front_matter), variant),
+      &macros::merge(&setting.macros, meta.get_shortcut()),
+      &image_dirs(&setting, &body_path),
+      &setting.target_dir,
+    )
+    .map_err(Response::process_error)?;
+    fs::write(
+      path.join(format!("{}.tex", layout::slugify(meta.get_filename()))),
+      res.tex,
+    )
+    .map_err(Response::process_error)?;
+    if res.missing.is_empty() {
+      Ok(Response::new("tex file successfully created"))
+    } else {
+      Ok(Response::new(format!(
+        "tex file successfully created, but {} images couldn't be included: {}",
+        res.missing.len(),
+        res.missing.join(", ")
+      )))
+    }
+  })
+}
+
+/*
+ * bind the documents matching the filter into `<path>/<slug of the title>.epub`, in the order of the options.
+ * it is a job. see epub.rs
+ */
+#[tauri::command]
+pub fn export_epub(
+  filter: Filter,
+  options: EpubOptions,
+  variant: Option<Variant>,
+  path: PathBuf,
+  env: State<'_, Env>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  if !path.is_dir() {
+    return Err(Response::client_error("given path is not a directory"));
+  }
+  jobs.push(move |job| {
+    let mut notes = vec![];
+    {
+      let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+      for meta in read_index()? {
+        if filter.matches(&meta).map_err(Response::process_error)? {
+          let src = fs::read_to_string(setting.path_to_file(&meta.body_path()))
+            .map_err(Response::process_error)?;
+          let md = with_variant(&setting, frontmatter::strip(&src, setting.front_matter), variant);
+          notes.push((meta, md));
+        }
+      }
+    }
+    epub::sort(&mut notes, options.sort, options.descending);
+    job.check()?;
+    job.progress(0, notes.len(), "rendering");
+
+    let book = epub::build(
+      &notes,
+      &options,
+      &setting.target_dir,
+      &setting.macros,
+      setting.lang(),
+    )
+    .map_err(|err| match err {
+      EpubError::Empty => Response::client_error(err),
+      _ => Response::process_error(err),
+    })?;
+    job.check()?;
+    let name = if options.title.is_empty() {
+      "notes"
+    } else {
+      &options.title
+    };
+    fs::write(
+      path.join(format!("{}.epub", layout::slugify(name))),
+      book.bytes,
+    )
+    .map_err(Response::process_error)?;
+    if book.missing.is_empty() {
+      Ok(Response::new(format!(
+        "epub of {} notes successfully created.",
+        book.chapters
+      )))
+    } else {
+      Ok(Response::new(format!(
+        "epub of {} notes successfully created, but {} images couldn't be embedded: {}",
+        book.chapters,
+        book.missing.len(),
+        book.missing.join(", ")
+      )))
+    }
+  })
+}
+
+/*
+ * render every document matching the filter into a static site in the path, with the site options in the setting.
+ * it is a job. see site.rs
+ */
+#[tauri::command]
+pub fn build_site(
+  filter: Filter,
+  path: PathBuf,
+  env: State<'_, Env>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  jobs.push(move |job| {
+    let mut notes = vec![];
+    {
+      let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+      for meta in read_index()? {
+        if filter.matches(&meta).map_err(Response::process_error)? {
+          let src = fs::read_to_string(setting.path_to_file(&meta.body_path()))
+            .map_err(Response::process_error)?;
+          notes.push((meta, frontmatter::strip(&src, setting.front_matter).to_string()));
+        }
+      }
+    }
+    job.check()?;
+    job.progress(0, notes.len(), "rendering");
+
+    let report = site::build(
+      &notes,
+      &setting.site,
+      &setting.target_dir,
+      &setting.macros,
+      setting.lang(),
+      &path,
+    )
+    .map_err(|err| match err {
+      SiteError::Io(_) => Response::process_error(err),
+      _ => Response::client_error(err),
+    })?;
+    Ok(Response::new(format!(
+      "{} pages of {} notes successfully written.",
+      report.pages, report.notes
+    )))
+  })
+}
+
+/*
+ * stop a queued or running job. the job fails with "job cancelled" -- see jobs.rs
+ */
+#[tauri::command]
+pub fn cancel_job(id: JobId, jobs: State<'_, Jobs>) -> Result<Response, Response> {
+  if jobs.cancel(id)? {
+    Ok(Response::new(format!("job {} is cancelled", id)))
+  } else {
+    Err(Response::client_error(format!("job {} is not found", id)))
+  }
+}
+
+fn update_references<F: FnOnce(&mut attachments::Store)>(
+  target_dir: &Path,
+  update: F,
+) -> std::io::Result<()> {
+  let mut store = attachments::Store::open(target_dir)?;
+  update(&mut store);
+  store.save()
+}
+
+/*
+ * the titles and bodies without front matter of the documents.
+ */
+fn read_notes(setting: &Setting, metas: &[Meta]) -> Result<Vec<(String, String)>, Response> {
+  let mut notes = vec![];
+  for meta in metas {
+    let src =
+      fs::read_to_string(setting.path_to_file(&meta.body_path())).map_err(Response::process_error)?;
+    notes.push((
+      meta.get_filename().to_string(),
+      frontmatter::strip(&src, setting.front_matter).to_string(),
+    ));
+  }
+  Ok(notes)
+}
+
+/*
+ * the graph is left to be built by get_backlinks if it is not made yet.
+ */
+fn update_links<F: FnOnce(&mut LinkGraph)>(update: F) -> std::io::Result<()> {
+  let path = crate::links_path();
+  match LinkGraph::open(&path)? {
+    Some(mut graph) => {
+      update(&mut graph);
+      graph.save(&path)
     }
+    None => Ok(()),
   }
-  meta.set_html_src();
-  new_index.push(serde_json::to_string(&meta).map_err(Response::client_error)?);
+}
 
-  println!("html src created with {:?} in {:?}", meta, path);
+/*
+ * the link graph, which is built from the bodies at the first use.
+ */
+fn open_links(setting: &Setting, metas: &[Meta]) -> Result<LinkGraph, Response> {
+  match LinkGraph::open(&crate::links_path()).map_err(Response::process_error)? {
+    Some(graph) => Ok(graph),
+    None => {
+      let graph = LinkGraph::build(&read_notes(setting, metas)?);
+      graph
+        .save(&crate::links_path())
+        .map_err(Response::process_error)?;
+      Ok(graph)
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Backlinks {
+  backlinks: Vec<Backlink>,
+  unresolved: Vec<Unresolved>, //of the links in the document
+}
+
+/*
+ * the wiki links to the document from the others, and its own links which point to nothing.
+ */
+#[tauri::command]
+pub fn get_backlinks(meta: Meta, env: State<'_, Env>) -> Result<Backlinks, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let graph = open_links(&setting, &read_index()?)?;
+  Ok(Backlinks {
+    backlinks: graph.backlinks(meta.get_filename()),
+    unresolved: graph.unresolved(Some(meta.get_filename())),
+  })
+}
+
+/*
+ * the headings of a document kept in the index. entries saved before outlines are parsed from the body.
+ */
+#[tauri::command]
+pub fn get_outline(meta: Meta, env: State<'_, Env>) -> Result<Vec<render::Section>, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let meta = read_index()?
+    .into_iter()
+    .find(|m| m.get_hashed_filename() == meta.get_hashed_filename())
+    .ok_or_else(|| Response::client_error("document is not found"))?;
+  if !meta.get_outline().is_empty() {
+    return Ok(meta.get_outline().to_vec());
+  }
+  let src =
+    fs::read_to_string(setting.path_to_file(&meta.body_path())).map_err(Response::process_error)?;
+  Ok(render::outline(frontmatter::strip(&src, setting.front_matter)))
+}
+
+/*
+ * scan every body for links to missing notes, headings, attachments and files, malformed links and duplicate anchors.
+ * it is a job since external urls are requested if options.external is given, after the index lock is released.
+ * the report is sent with event "link_report" -- see check.rs and jobs.rs
+ */
+#[tauri::command]
+pub fn check_links(
+  options: CheckOptions,
+  env: State<'_, Env>,
+  jobs: State<'_, Jobs>,
+) -> Result<JobId, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  jobs.push(move |job| {
+    let (notes, graph, store) = {
+      let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+      let metas = read_index()?;
+      let graph = open_links(&setting, &metas)?;
+      let bodies = read_notes(&setting, &metas)?;
+      let store =
+        attachments::Store::open(&setting.target_dir).map_err(Response::process_error)?;
+      let notes = metas
+        .into_iter()
+        .zip(bodies.into_iter().map(|(_, body)| body))
+        .collect::<Vec<_>>();
+      (notes, graph, store)
+    };
+    let report = check::check(&notes, &graph, &store, &setting.target_dir, &options);
+    job.check()?;
+    job.report("link_report", &report);
+    Ok(Response::new(format!("{} documents checked.", report.checked)))
+  })
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RequestGraph {
+  #[serde(flatten)]
+  filter: Filter,
+  #[serde(flatten)]
+  options: GraphOptions,
+}
+/*
+ * the notes matching the filter and their links and tags, for the graph view -- see graph.rs
+ */
+#[tauri::command]
+pub fn get_graph(req: RequestGraph, env: State<'_, Env>) -> Result<Graph, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let all = read_index()?;
+  let links = open_links(&setting, &all)?;
+  let mut metas = vec![];
+  for meta in all {
+    if req.filter.matches(&meta).map_err(Response::process_error)? {
+      metas.push(meta);
+    }
+  }
+  if let Some(center) = &req.options.center {
+    if metas.iter().all(|meta| meta.get_filename() != center) {
+      return Err(Response::client_error(format!("{} is not found", center)));
+    }
+  }
+  Ok(graph::build(&metas, &links, &req.options))
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RequestStats {
+  #[serde(flatten)]
+  filter: Filter,
+  #[serde(flatten)]
+  options: StatsOptions,
+}
+/*
+ * the statistics of the notes matching the filter, by the period and the group -- see stats.rs
+ */
+#[tauri::command]
+pub fn workspace_stats(req: RequestStats) -> Result<Vec<Bucket>, Response> {
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let mut metas = vec![];
+  for meta in read_index()? {
+    if req.filter.matches(&meta).map_err(Response::process_error)? {
+      metas.push(meta);
+    }
+  }
+  Ok(stats::aggregate(&metas, &req.options))
+}
+
+/* rename the links to `old` in the note at `path` and return its new body. */
+fn rewrite_links(
+  setting: &Setting,
+  graph: &LinkGraph,
+  meta: &mut Meta,
+  path: &Path,
+  old: &str,
+  new: &str,
+) -> Result<String, String> {
+  let src = fs::read_to_string(path).map_err(|err| err.to_string())?;
+  let body = graph.rename_in(
+    meta.get_filename(),
+    frontmatter::strip(&src, setting.front_matter),
+    old,
+    new,
+  );
+  let src = frontmatter::attach(meta, &body, setting.front_matter)?;
+  fs::write(path, src.as_bytes()).map_err(|err| err.to_string())?;
+  refresh_derived(meta, &body, path);
+  Ok(body)
+}
+
+/*
+ * change the title of a document. the body follows the hashed name,
+ * and the wiki links to the old title, in the other documents and the document itself, are rewritten to the new title.
+ * exported html is left where the user put it.
+ */
+#[tauri::command]
+pub fn rename_document(
+  meta: Meta,
+  filename: String,
+  env: State<'_, Env>,
+  cashe: State<'_, Casher>,
+) -> Result<Meta, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let filename = filename.trim().to_string();
+  if filename.is_empty() {
+    return Err(Response::client_error("title is empty"));
+  }
+  let mut metas = read_index()?;
+  let original = match metas
+    .iter()
+    .find(|m| m.get_hashed_filename() == meta.get_hashed_filename())
+  {
+    Some(found) => found.clone(),
+    None => return Err(Response::client_error("document is not found")),
+  };
+  let old = original.get_filename().to_string();
+  let mut renamed = original.clone();
+  renamed.set_filename(filename.clone());
+  if metas
+    .iter()
+    .any(|m| m.get_filename() != old && m.get_hashed_filename() == renamed.get_hashed_filename())
+  {
+    return Err(Response::client_error(format!("{} already exists", filename)));
+  }
+
+  let graph = open_links(&setting, &metas)?;
+  let from = setting.path_to_file(&original.body_path());
+  let src = fs::read_to_string(&from).map_err(Response::process_error)?;
+  let body = graph.rename_in(
+    &old,
+    frontmatter::strip(&src, setting.front_matter),
+    &old,
+    &filename,
+  );
+  if renamed.get_path().is_some() {
+    let taken = metas
+      .iter()
+      .filter(|m| m.get_filename() != old)
+      .map(|m| m.body_path().to_lowercase())
+      .collect();
+    renamed.set_path(layout::assign(
+      &renamed,
+      setting.layout,
+      &taken,
+      &setting.target_dir,
+    ));
+  }
+  let to = setting.path_to_file(&renamed.body_path());
+  if let Some(parent) = to.parent() {
+    fs::create_dir_all(parent).map_err(Response::process_error)?;
+  }
+  let src = frontmatter::attach(&renamed, &body, setting.front_matter)
+    .map_err(Response::client_error)?;
+  fs::write(&to, src.as_bytes()).map_err(Response::process_error)?;
+  refresh_derived(&mut renamed, &body, &to);
+
+  // the other documents linking to the old title
+  let sources = graph.linking_to(&old);
+  let mut rewritten = vec![];
+  for m in metas.iter_mut() {
+    if m.get_filename() == old {
+      *m = renamed.clone();
+      continue;
+    }
+    if !sources.contains(m.get_filename()) {
+      continue;
+    }
+    let path = setting.path_to_file(&m.body_path());
+    match rewrite_links(&setting, &graph, m, &path, &old, &filename) {
+      Ok(body) => rewritten.push((m.get_filename().to_string(), body)),
+      Err(err) => println!(
+        "WARNING: links in {} are not renamed\n{}",
+        m.get_filename(),
+        err.to_string()
+      ),
+    }
+  }
+  write_index(&metas)?;
+  refresh_memo(&mut memo);
+  // the old body goes last, so that a failure above leaves the index pointing at a file
+  if from != to {
+    fs::remove_file(&from).map_err(Response::process_error)?;
+  }
+
+  if let Err(err) = update_links(|graph| {
+    graph.remove(&old);
+    graph.update(&filename, &body);
+    for (title, body) in &rewritten {
+      graph.update(title, body);
+    }
+  }) {
+    println!("WARNING: link graph is not updated\n{}", err.to_string());
+  }
+  if let Err(err) = update_references(&setting.target_dir, |store| {
+    store.remove_note(&old);
+    store.set_references(&filename, &body);
+  }) {
+    println!("WARNING: attachment references are not updated\n{}", err.to_string());
+  }
+  Ok(renamed)
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct LinkedAttachment {
+  #[serde(flatten)]
+  attachment: Attachment,
+  link: String,
+}
+impl From<Attachment> for LinkedAttachment {
+  fn from(attachment: Attachment) -> LinkedAttachment {
+    LinkedAttachment {
+      link: attachment.link(),
+      attachment,
+    }
+  }
+}
+
+/*
+ * copy a file into the attachment store of the workspace, or find the same content there.
+ * the note links it by the returned `notex://` link, which is resolved by the exports and the webview.
+ */
+#[tauri::command]
+pub fn add_attachment(
+  path: PathBuf,
+  env: State<'_, Env>,
+) -> Result<LinkedAttachment, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  if !path.is_file() {
+    return Err(Response::client_error("given path is not a file"));
+  }
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let mut store = attachments::Store::open(&setting.target_dir).map_err(Response::process_error)?;
+  let attachment = store
+    .add(&path, &setting.images)
+    .map_err(Response::process_error)?;
+  store.save().map_err(Response::process_error)?;
+  Ok(attachment.into())
+}
+
+#[tauri::command]
+pub fn list_attachments(env: State<'_, Env>) -> Result<Vec<LinkedAttachment>, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?;
+  attachments::Store::open(&setting.target_dir)
+    .map(|store| store.list().into_iter().map(LinkedAttachment::from).collect())
+    .map_err(Response::process_error)
+}
+
+/*
+ * rebuild the references from all the bodies and remove the attachments which no note uses.
+ */
+#[tauri::command]
+pub fn gc_attachments(env: State<'_, Env>) -> Result<Response, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let notes = read_notes(&setting, &read_index()?)?;
+  let mut store = attachments::Store::open(&setting.target_dir).map_err(Response::process_error)?;
+  let removed = store.gc(&notes).map_err(Response::process_error)?;
+  store.save().map_err(Response::process_error)?;
+  Ok(Response::new(format!(
+    "{} unused attachments were removed.",
+    removed.len()
+  )))
+}
+
+#[tauri::command]
+pub fn list_export_templates(env: State<'_, Env>) -> Result<Vec<String>, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?;
+  template::list(&setting.target_dir).map_err(Response::process_error)
+}
+
+/*
+ * write a new document and add it to the index, as save_document does for a new one.
+ * the caller holds the index lock.
+ */
+fn create_document(
+  setting: &Setting,
+  memo: &mut Memo,
+  mut meta: Meta,
+  body: &str,
+) -> Result<Meta, Response> {
+  macros::table(meta.get_shortcut()).map_err(Response::client_error)?;
+  if meta.get_filename().is_empty() {
+    return Err(Response::client_error("title is empty"));
+  }
+  let mut metas = read_index()?;
+  if metas
+    .iter()
+    .any(|m| m.get_hashed_filename() == meta.get_hashed_filename())
+  {
+    return Err(Response::client_error(format!(
+      "{} already exists",
+      meta.get_filename()
+    )));
+  }
+  meta.stamp();
+  let taken = metas.iter().map(|m| m.body_path().to_lowercase()).collect();
+  meta.set_path(layout::assign(
+    &meta,
+    setting.layout,
+    &taken,
+    &setting.target_dir,
+  ));
+  let path = setting.path_to_file(&meta.body_path());
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent).map_err(Response::process_error)?;
+  }
+  let src =
+    frontmatter::attach(&meta, body, setting.front_matter).map_err(Response::client_error)?;
+  OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(&path)
+    .map_err(Response::process_error)?
+    .write_all(src.as_bytes())
+    .map_err(Response::process_error)?;
+  refresh_derived(&mut meta, body, &path);
+  metas.push(meta.clone());
+  write_index(&metas)?;
+
+  if let Err(err) = update_references(&setting.target_dir, |store| {
+    store.set_references(meta.get_filename(), body)
+  }) {
+    println!("WARNING: attachment references are not updated\n{}", err.to_string());
+  }
+  if let Err(err) = update_links(|graph| graph.update(meta.get_filename(), body)) {
+    println!("WARNING: link graph is not updated\n{}", err.to_string());
+  }
+  refresh_memo(memo);
+  Ok(meta)
+}
+
+fn template_vars(setting: &Setting, title: &str) -> Vars {
+  let now = Local::now();
+  Vars {
+    date: now.format("%Y-%m-%d").to_string(),
+    time: now.format("%H:%M").to_string(),
+    author: setting.username.clone(),
+    title: title.to_string(),
+  }
+}
+
+/*
+ * the templates for new documents in the workspace -- see doctemplate.rs
+ */
+#[tauri::command]
+pub fn list_templates(env: State<'_, Env>) -> Result<Vec<DocTemplate>, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?;
+  doctemplate::list(&setting.target_dir).map_err(Response::process_error)
+}
+
+/*
+ * create a document from a template, with the variables filled in. returns the meta to open it.
+ */
+#[tauri::command]
+pub fn create_from_template(
+  name: String,
+  title: String,
+  env: State<'_, Env>,
+  cashe: State<'_, Casher>,
+) -> Result<Meta, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let filled = doctemplate::load(&setting.target_dir, &name)
+    .map_err(Response::client_error)?
+    .fill(&template_vars(&setting, title.trim()));
+  let meta = Meta::from_matter(Matter {
+    title: filled.title,
+    author: setting.username.clone(),
+    tags: filled.tags,
+    created_at: String::new(),
+    updated_at: None,
+    shortcut: filled.shortcut,
+  });
+  create_document(&setting, &mut memo, meta, &filled.body)
+}
+
+/* set the previous and next daily notes in the note at `path` and return its new body. */
+fn rewrite_nav(
+  setting: &Setting,
+  meta: &mut Meta,
+  path: &Path,
+  before: Option<&str>,
+  after: Option<&str>,
+) -> Result<String, String> {
+  let src = fs::read_to_string(path).map_err(|err| err.to_string())?;
+  let body = daily::set_nav(
+    frontmatter::strip(&src, setting.front_matter),
+    before,
+    after,
+  );
+  let src = frontmatter::attach(meta, &body, setting.front_matter)?;
+  fs::write(path, src.as_bytes()).map_err(|err| err.to_string())?;
+  refresh_derived(meta, &body, path);
+  Ok(body)
+}
+
+/*
+ * find or create the daily note of the date, given as `%Y-%m-%d` -- see daily.rs
+ * a new one links to the daily notes before and after it, and they are rewritten to link to it.
+ */
+#[tauri::command]
+pub fn open_daily_note(
+  date: String,
+  env: State<'_, Env>,
+  cashe: State<'_, Casher>,
+) -> Result<Meta, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let mut memo = cashe.0.lock().map_err(Response::process_error)?;
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let options = &setting.daily;
+  let date = daily::parse_date(&date).map_err(Response::client_error)?;
+  let title = daily::title_of(date, options).map_err(Response::client_error)?;
+  let metas = read_index()?;
+  if let Some(found) = metas.iter().find(|m| m.get_filename() == title) {
+    return Ok(found.clone());
+  }
+
+  let template = match &options.template {
+    Some(name) => doctemplate::load(&setting.target_dir, name).map_err(Response::client_error)?,
+    None => DocTemplate::default(),
+  };
+  let mut vars = template_vars(&setting, &title);
+  vars.date = date.format(daily::DATE_FORMAT).to_string();
+  let filled = template.fill(&vars);
+  let mut tags = filled.tags;
+  if !options.tag.is_empty() && !tags.contains(&options.tag) {
+    tags.push(options.tag.clone());
+  }
+
+  // the neighbours of the new one, with their own neighbours on the other side
+  let dailies = daily::dailies(&metas, options);
+  let (prev, next) = daily::neighbours(&dailies, date);
+  let title_of = |meta: Option<&Meta>| meta.map(|m| m.get_filename().to_string());
+  let mut navs = vec![];
+  if let Some(prev) = prev {
+    let before =
+      daily::date_of(prev.get_filename(), options).and_then(|d| daily::neighbours(&dailies, d).0);
+    navs.push((
+      prev.get_filename().to_string(),
+      title_of(before),
+      Some(title.clone()),
+    ));
+  }
+  if let Some(next) = next {
+    let after =
+      daily::date_of(next.get_filename(), options).and_then(|d| daily::neighbours(&dailies, d).1);
+    navs.push((
+      next.get_filename().to_string(),
+      Some(title.clone()),
+      title_of(after),
+    ));
+  }
+  let body = daily::set_nav(
+    &filled.body,
+    prev.map(|m| m.get_filename()),
+    next.map(|m| m.get_filename()),
+  );
+
+  let meta = Meta::from_matter(Matter {
+    title: title.clone(),
+    author: setting.username.clone(),
+    tags,
+    created_at: String::new(),
+    updated_at: None,
+    shortcut: filled.shortcut,
+  });
+  let meta = create_document(&setting, &mut memo, meta, &body)?;
+
+  let mut metas = read_index()?;
+  let mut rewritten = vec![];
+  for m in metas.iter_mut() {
+    let (before, after) = match navs.iter().find(|(t, _, _)| t == m.get_filename()) {
+      Some((_, before, after)) => (before, after),
+      None => continue,
+    };
+    let path = setting.path_to_file(&m.body_path());
+    match rewrite_nav(&setting, m, &path, before.as_deref(), after.as_deref()) {
+      Ok(body) => rewritten.push((m.get_filename().to_string(), body)),
+      Err(err) => println!(
+        "WARNING: navigation in {} is not updated\n{}",
+        m.get_filename(),
+        err.to_string()
+      ),
+    }
+  }
+  write_index(&metas)?;
+  refresh_memo(&mut memo);
+  if let Err(err) = update_links(|graph| {
+    for (title, body) in &rewritten {
+      graph.update(title, body);
+    }
+  }) {
+    println!("WARNING: link graph is not updated\n{}", err.to_string());
+  }
+  Ok(meta)
+}
+
+/*
+ * the dates of the month with the daily note or the notes created on them, for the calendar.
+ */
+#[tauri::command]
+pub fn daily_calendar(
+  year: i32,
+  month: u32,
+  env: State<'_, Env>,
+) -> Result<Vec<CalendarDay>, Response> {
+  let setting = env.0.lock().map_err(Response::process_error)?.clone();
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  Ok(daily::calendar(&read_index()?, year, month, &setting.daily))
+}
+
+/*
+ * write the html source, and mark the entry in the index as having it.
+ * only html_src and what update sets are changed, since the meta given may be older than the entry.
+ */
+fn write_html<F: FnOnce(&mut Meta)>(
+  meta: &Meta,
+  htmlsrc: &str,
+  path: &Path,
+  update: F,
+) -> Result<(), Response> {
+  if !path.exists() {
+    return Err(Response::client_error("given path can't be found"));
+  }
+
+  if !path.is_dir() {
+    return Err(Response::client_error("given path is not a directory"));
+  }
+
+  let _lock = IndexLock::acquire().map_err(Response::process_error)?;
+  let mut metas = read_index()?;
+  let stored = metas
+    .iter_mut()
+    .find(|m| m.get_hashed_filename() == meta.get_hashed_filename())
+    .ok_or_else(|| Response::client_error(format!("{} is not found", meta.get_filename())))?;
+  stored.set_html_src();
+  update(stored);
+
+  println!("html src created with {:?} in {:?}", stored, path);
 
   OpenOptions::new()
     .write(true)
@@ -390,16 +1735,6 @@ pub fn html(mut meta: Meta, htmlsrc: &str, path: PathBuf) -> Result<(), Response
     .open(path.join(&meta.get_hashed_html_name()))
     .map_err(Response::process_error)?
     .write_all(htmlsrc.as_bytes())
-    .map_err(Response::process_error)
-    .and_then(|_| {
-      OpenOptions::new()
-        .write(true)
-        .append(false)
-        .truncate(true)
-        .create(false)
-        .open(crate::index_path())
-        .map_err(Response::process_error)?
-        .write_all(new_index.join("\n").as_bytes())
-        .map_err(Response::process_error)
-    })
+    .map_err(Response::process_error)?;
+  write_index(&metas)
 }
@@ -2,7 +2,17 @@ pub static ROOT: &str = ".notex";
 pub static CONF: &str = "notex.conf";
 pub static DEFAULT_TARGET: &str = "target";
 pub static INDEX: &str = "index.jsonl";
+pub static INDEX_LOCK: &str = "index.lock";
+pub static LINKS: &str = "links.json"; //the link graph of the wiki links, next to the index
+pub static INSTANCE_LOCK: &str = "instance.lock";
+pub static INSTANCE_PORT: &str = "instance.port";
+pub static ACTIVATE: &str = "activate";
 pub static TARGET_EXTENTION: &str = ".md";
 pub static HTML_FILES: &str = "html";
+pub static TEMPLATES: &str = ".templates"; //under the target directory
+pub static TEMPLATE_EXTENTION: &str = ".html";
+pub static PREAMBLE: &str = "preamble.tex"; //under the templates directory, for latex export
+pub static ATTACHMENTS: &str = ".attachments"; //under the target directory
+pub static ATTACHMENT_INDEX: &str = "index.json"; //under the attachments directory
 
 pub static TIME_FORMAT: &str = "%Y/%m/%d %H:%M:%S, %Z";
\ No newline at end of file
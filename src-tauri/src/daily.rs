@@ -0,0 +1,267 @@
+use super::constants::TIME_FORMAT;
+use super::model::Meta;
+use chrono::{
+  format::{Item, StrftimeItems},
+  offset::TimeZone,
+  Datelike, Local, NaiveDate,
+};
+use std::{collections::BTreeMap, fmt::Write};
+
+/*
+ * daily notes for a journal, one for each date.
+ * - the title is the date formatted by the pattern, and a note is a daily one if its title parses back to the date
+ * - new ones are made from the template if set -- see doctemplate.rs, where `{{date}}` is the date of the note
+ * - each has a line of the wiki links to the daily notes before and after it, which is rewritten when one is added
+ *   in between. the line is told by the marker at its end.
+ */
+
+pub static DATE_FORMAT: &str = "%Y-%m-%d"; //of the dates given by the client and the calendar
+static NAV_MARKER: &str = "<!-- daily -->";
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DailyOptions {
+  pub title: String,            //strftime pattern, e.g. `%Y-%m-%d` or `log %Y/%m/%d`
+  pub template: Option<String>, //name of the document template. if None, the notes start empty
+  pub tag: String,              //given to the daily notes. if empty, none
+}
+impl Default for DailyOptions {
+  fn default() -> Self {
+    DailyOptions {
+      title: String::from(DATE_FORMAT),
+      template: None,
+      tag: String::from("daily"),
+    }
+  }
+}
+
+pub fn parse_date(date: &str) -> Result<NaiveDate, String> {
+  NaiveDate::parse_from_str(date, DATE_FORMAT)
+    .map_err(|err| format!("invalid date {}: {}", date, err.to_string()))
+}
+
+/*
+ * None if the pattern needs more than a date, e.g. `%H:%M`, where `to_string` would panic.
+ */
+fn format(date: NaiveDate, pattern: &str) -> Option<String> {
+  let mut res = String::new();
+  write!(res, "{}", date.format(pattern)).ok().map(|_| res)
+}
+
+/*
+ * the pattern must tell the dates apart, or the notes can't be found.
+ */
+pub fn validate(options: &DailyOptions) -> Result<(), String> {
+  let invalid = || format!("invalid title pattern {}", options.title);
+  if StrftimeItems::new(&options.title).any(|item| item == Item::Error) {
+    return Err(invalid());
+  }
+  let sample = NaiveDate::from_ymd(2021, 2, 3);
+  match format(sample, &options.title) {
+    Some(title) if date_of(&title, options) == Some(sample) => Ok(()),
+    _ => Err(invalid()),
+  }
+}
+
+/*
+ * the title of the daily note of the date.
+ */
+pub fn title_of(date: NaiveDate, options: &DailyOptions) -> Result<String, String> {
+  validate(options)?;
+  format(date, &options.title).ok_or_else(|| format!("invalid title pattern {}", options.title))
+}
+
+pub fn date_of(title: &str, options: &DailyOptions) -> Option<NaiveDate> {
+  NaiveDate::parse_from_str(title, &options.title)
+    .ok()
+    .filter(|date| format(*date, &options.title).as_deref() == Some(title))
+}
+
+/*
+ * the line of the links to the daily notes before and after.
+ */
+pub fn nav(prev: Option<&str>, next: Option<&str>) -> String {
+  let link = |title: Option<&str>| title.map(|t| format!("[[{}]]", t)).unwrap_or_default();
+  format!("← {} | {} → {}", link(prev), link(next), NAV_MARKER)
+}
+
+/*
+ * replace the line of the links, or put it at the end if the body has none.
+ */
+pub fn set_nav(body: &str, prev: Option<&str>, next: Option<&str>) -> String {
+  let line = nav(prev, next);
+  let mut found = false;
+  let mut lines = body
+    .lines()
+    .map(|l| {
+      if !found && l.trim_end().ends_with(NAV_MARKER) {
+        found = true;
+        line.clone()
+      } else {
+        l.to_string()
+      }
+    })
+    .collect::<Vec<_>>();
+  if !found {
+    while lines.last().map_or(false, |l| l.trim().is_empty()) {
+      lines.pop();
+    }
+    if !lines.is_empty() {
+      lines.push(String::new());
+    }
+    lines.push(line);
+  }
+  lines.join("\n") + "\n"
+}
+
+/*
+ * the daily notes by date.
+ */
+pub fn dailies<'a>(metas: &'a [Meta], options: &DailyOptions) -> BTreeMap<NaiveDate, &'a Meta> {
+  metas
+    .iter()
+    .filter_map(|meta| date_of(meta.get_filename(), options).map(|date| (date, meta)))
+    .collect()
+}
+
+/*
+ * the daily notes before and after the date, which may have none.
+ */
+pub fn neighbours<'a>(
+  dailies: &BTreeMap<NaiveDate, &'a Meta>,
+  date: NaiveDate,
+) -> (Option<&'a Meta>, Option<&'a Meta>) {
+  (
+    dailies.range(..date).next_back().map(|(_, meta)| *meta),
+    dailies
+      .range(date..)
+      .find(|(d, _)| **d != date)
+      .map(|(_, meta)| *meta),
+  )
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct CalendarDay {
+  pub date: String,
+  pub daily: Option<String>, //title of the daily note
+  pub created: Vec<String>,  //titles of the notes created on the date
+}
+
+fn day_of(days: &mut BTreeMap<NaiveDate, CalendarDay>, date: NaiveDate) -> &mut CalendarDay {
+  days.entry(date).or_insert_with(|| CalendarDay {
+    date: date.format(DATE_FORMAT).to_string(),
+    daily: None,
+    created: vec![],
+  })
+}
+
+/*
+ * the dates of the month with the daily note or the notes created on them.
+ */
+pub fn calendar(metas: &[Meta], year: i32, month: u32, options: &DailyOptions) -> Vec<CalendarDay> {
+  let mut days = BTreeMap::new();
+  let in_month = |date: &NaiveDate| date.year() == year && date.month() == month;
+  for meta in metas {
+    if let Some(date) = date_of(meta.get_filename(), options).filter(in_month) {
+      day_of(&mut days, date).daily = Some(meta.get_filename().to_string());
+    }
+    if let Ok(created) = Local.datetime_from_str(meta.get_created_at(), TIME_FORMAT) {
+      let date = created.naive_local().date();
+      if in_month(&date) {
+        day_of(&mut days, date)
+          .created
+          .push(meta.get_filename().to_string());
+      }
+    }
+  }
+  days.into_iter().map(|(_, day)| day).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests;
+
+  #[test]
+  fn daily_title_test() {
+    let date = NaiveDate::from_ymd(2021, 10, 19);
+    let options = DailyOptions {
+      title: String::from("log %Y/%m/%d"),
+      ..Default::default()
+    };
+    assert_eq!(title_of(date, &options).unwrap(), "log 2021/10/19");
+    assert_eq!(date_of("log 2021/10/19", &options), Some(date));
+    assert_eq!(date_of("log 2021/10/19 extra", &options), None);
+    assert_eq!(date_of("log 2021/1/9", &options), None);
+    for pattern in ["%Y-%m", "%Q", "daily", "%Y-%m-%d %H:%M", "%Y-%m-%d%z"].iter() {
+      let options = DailyOptions {
+        title: pattern.to_string(),
+        ..Default::default()
+      };
+      assert!(title_of(date, &options).is_err());
+    }
+  }
+
+  #[test]
+  fn daily_nav_test() {
+    assert_eq!(
+      set_nav("# today\n\n", Some("2021-10-18"), None),
+      format!("# today\n\n← [[2021-10-18]] |  → {}\n", NAV_MARKER)
+    );
+    let body = set_nav("# today\n", Some("2021-10-18"), None);
+    let body = set_nav(
+      &format!("{}\nmore notes\n", body),
+      Some("2021-10-18"),
+      Some("2021-10-20"),
+    );
+    assert_eq!(
+      body,
+      format!(
+        "# today\n\n← [[2021-10-18]] | [[2021-10-20]] → {}\n\nmore notes\n",
+        NAV_MARKER
+      )
+    );
+    assert_eq!(
+      crate::links::parse(&body)
+        .into_iter()
+        .map(|l| l.target)
+        .collect::<Vec<_>>(),
+      vec!["2021-10-18", "2021-10-20"]
+    );
+
+    let created_at = Local
+      .ymd(2021, 10, 1)
+      .and_hms(12, 0, 0)
+      .format(TIME_FORMAT)
+      .to_string();
+    let meta = |title: &str| tests::meta(title, &[], &created_at);
+    let metas = vec![
+      meta("2021-10-18"),
+      meta("2021-10-25"),
+      meta("2021-09-30"),
+      meta("notes"),
+    ];
+    let options = DailyOptions::default();
+    let dailies = dailies(&metas, &options);
+    assert_eq!(dailies.len(), 3);
+    let (prev, next) = neighbours(&dailies, NaiveDate::from_ymd(2021, 10, 20));
+    assert_eq!(prev.unwrap().get_filename(), "2021-10-18");
+    assert_eq!(next.unwrap().get_filename(), "2021-10-25");
+    let (prev, next) = neighbours(&dailies, NaiveDate::from_ymd(2021, 10, 25));
+    assert_eq!(prev.unwrap().get_filename(), "2021-10-18");
+    assert!(next.is_none());
+
+    let days = calendar(&metas, 2021, 10, &options);
+    assert_eq!(
+      days
+        .iter()
+        .map(|d| (d.date.as_str(), d.daily.as_deref(), d.created.len()))
+        .collect::<Vec<_>>(),
+      vec![
+        ("2021-10-01", None, 4),
+        ("2021-10-18", Some("2021-10-18"), 0),
+        ("2021-10-25", Some("2021-10-25"), 0)
+      ]
+    );
+  }
+}
@@ -0,0 +1,201 @@
+use super::{
+  constants::{TARGET_EXTENTION, TEMPLATES},
+  frontmatter,
+  template::is_valid_name,
+};
+use std::{collections::HashMap, fs, io, path::Path};
+
+/*
+ * templates for new documents, e.g. a lecture note or meeting minutes.
+ * they are `<target_dir>/.templates/<name>.md`, next to the templates for html export -- see template.rs
+ * front matter of a template gives the title, the tags and the shortcut of new documents, each optional.
+ * `{{date}}`, `{{time}}`, `{{author}}` and `{{title}}` in the title, the tags and the body are filled in.
+ * the other braces are left as they are, since they are common in tex.
+ */
+
+static DEFAULT_TITLE: &str = "{{title}}";
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct TemplateMatter {
+  pub title: String, //the title of new documents, where `{{title}}` is the one given on creation
+  pub description: String,
+  pub tags: Vec<String>,
+  pub shortcut: HashMap<String, String>,
+}
+impl Default for TemplateMatter {
+  fn default() -> Self {
+    TemplateMatter {
+      title: String::from(DEFAULT_TITLE),
+      description: String::new(),
+      tags: vec![],
+      shortcut: HashMap::new(),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize)]
+pub struct DocTemplate {
+  pub name: String,
+  #[serde(flatten)]
+  pub matter: TemplateMatter,
+  pub body: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Vars {
+  pub date: String,
+  pub time: String,
+  pub author: String,
+  pub title: String,
+}
+impl Vars {
+  fn get(&self, name: &str) -> Option<&str> {
+    match name {
+      "date" => Some(&self.date),
+      "time" => Some(&self.time),
+      "author" => Some(&self.author),
+      "title" => Some(&self.title),
+      _ => None,
+    }
+  }
+}
+
+/*
+ * a new document made from a template.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct Filled {
+  pub title: String,
+  pub tags: Vec<String>,
+  pub shortcut: HashMap<String, String>,
+  pub body: String,
+}
+
+pub fn fill(src: &str, vars: &Vars) -> String {
+  let mut res = String::new();
+  let mut rest = src;
+  while let Some(start) = rest.find("{{") {
+    let inner = &rest[start + 2..];
+    match inner
+      .find("}}")
+      .and_then(|end| vars.get(inner[..end].trim()).map(|value| (end, value)))
+    {
+      Some((end, value)) => {
+        res.push_str(&rest[..start]);
+        res.push_str(value);
+        rest = &inner[end + 2..];
+      }
+      None => {
+        res.push_str(&rest[..start + 2]);
+        rest = inner;
+      }
+    }
+  }
+  res.push_str(rest);
+  res
+}
+
+impl DocTemplate {
+  pub fn parse(name: &str, src: &str) -> Result<DocTemplate, String> {
+    let (matter, body) = match frontmatter::parse_as::<TemplateMatter>(src) {
+      Some(parsed) => parsed.map_err(|err| format!("{}: {}", name, err))?,
+      None => (TemplateMatter::default(), src),
+    };
+    Ok(DocTemplate {
+      name: name.to_string(),
+      matter,
+      body: body.to_string(),
+    })
+  }
+
+  pub fn fill(&self, vars: &Vars) -> Filled {
+    let mut tags = vec![];
+    for tag in &self.matter.tags {
+      let tag = fill(tag, vars).trim().to_string();
+      if !tag.is_empty() && !tags.contains(&tag) {
+        tags.push(tag);
+      }
+    }
+    Filled {
+      title: fill(&self.matter.title, vars).trim().to_string(),
+      tags,
+      shortcut: self.matter.shortcut.clone(),
+      body: fill(&self.body, vars),
+    }
+  }
+}
+
+pub fn list(target_dir: &Path) -> io::Result<Vec<DocTemplate>> {
+  let mut res = vec![];
+  let dir = target_dir.join(TEMPLATES);
+  if dir.is_dir() {
+    for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+      if let Some(name) = name.strip_suffix(TARGET_EXTENTION) {
+        if !is_valid_name(name) {
+          continue;
+        }
+        match DocTemplate::parse(name, &fs::read_to_string(&path)?) {
+          Ok(template) => res.push(template),
+          Err(err) => println!("WARNING: document template is skipped\n{}", err),
+        }
+      }
+    }
+  }
+  res.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(res)
+}
+
+pub fn load(target_dir: &Path, name: &str) -> Result<DocTemplate, String> {
+  if !is_valid_name(name) {
+    return Err(format!("invalid template name {}", name));
+  }
+  let path = target_dir
+    .join(TEMPLATES)
+    .join(format!("{}{}", name, TARGET_EXTENTION));
+  let src = fs::read_to_string(path).map_err(|err| format!("{}: {}", name, err.to_string()))?;
+  DocTemplate::parse(name, &src)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn doc_template_test() {
+    let vars = Vars {
+      date: String::from("2021-10-19"),
+      time: String::from("09:00"),
+      author: String::from("me"),
+      title: String::from("Weekly"),
+    };
+    assert_eq!(
+      fill(
+        "{{ title }} by {{author}}: $x^{{2}}$ {{unknown}} {{date",
+        &vars
+      ),
+      "Weekly by me: $x^{{2}}$ {{unknown}} {{date"
+    );
+
+    let template = DocTemplate::parse(
+      "minutes",
+      "---\ntitle: \"{{title}} {{date}}\"\ntags: [minutes, \"{{author}}\", minutes]\nshortcut:\n  \\R: \\mathbb{R}\n---\n# {{title}}\n\n- date: {{date}} {{time}}\n",
+    )
+    .unwrap();
+    let filled = template.fill(&vars);
+    assert_eq!(filled.title, "Weekly 2021-10-19");
+    assert_eq!(filled.tags, vec!["minutes", "me"]);
+    assert_eq!(filled.shortcut.get("\\R").unwrap(), "\\mathbb{R}");
+    assert_eq!(filled.body, "# Weekly\n\n- date: 2021-10-19 09:00\n");
+
+    // without front matter, the title is the one given
+    let template = DocTemplate::parse("plain", "{{title}}\n").unwrap();
+    assert_eq!(template.fill(&vars).title, "Weekly");
+    assert!(DocTemplate::parse("broken", "---\ntags: 1\n---\n").is_err());
+  }
+}
@@ -0,0 +1,500 @@
+use super::{
+  constants::TARGET_EXTENTION,
+  macros,
+  model::{self, Meta},
+  render::{self, escape_html, Heading},
+  site::{last_modified, parse_time},
+  standalone::local_file,
+};
+use chrono::Utc;
+use std::{
+  cell::RefCell,
+  collections::{BTreeSet, HashMap},
+  fs,
+  io::{self, Cursor, Write},
+  path::{Path, PathBuf},
+};
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipWriter};
+
+/*
+ * epub 3 book of notes, one chapter per note in the given order.
+ * - OEBPS/nav.xhtml => table of contents with the chapters and their headings up to the second level
+ * - OEBPS/chapter-<n>.xhtml => a note with math in mathml, which epub 3 readers display
+ * - OEBPS/images/<n>.<ext> => local images of the notes. remote ones are left as they are and reported as missing.
+ * - OEBPS/content.opf => metadata from the notes: authors as creators, tags as subjects,
+ *   the earliest created_at as the date and the latest update as the modified time.
+ *
+ * links between notes in the book are resolved to the chapters like site.rs does.
+ * the body must be well formed as xhtml, so raw html written in notes should be closed tags.
+ */
+
+static STYLE: &str = include_str!("../assets/epub.css");
+static NAV: &str = "nav.xhtml";
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum SortKey {
+  #[serde(rename = "created_at")]
+  CreatedAt,
+  #[serde(rename = "updated_at")]
+  UpdatedAt,
+  #[serde(rename = "title")]
+  Title,
+}
+impl Default for SortKey {
+  fn default() -> Self {
+    SortKey::CreatedAt
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct EpubOptions {
+  pub title: String, //if empty, "notes"
+  pub sort: SortKey,
+  pub descending: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Epub {
+  pub bytes: Vec<u8>,
+  pub chapters: usize,
+  pub missing: Vec<String>, //image sources which couldn't be embedded
+}
+
+#[derive(Debug)]
+pub enum EpubError {
+  Empty,
+  Zip(String),
+  Io(io::Error),
+}
+impl ToString for EpubError {
+  fn to_string(&self) -> String {
+    match self {
+      EpubError::Empty => String::from("no notes match the filter"),
+      EpubError::Zip(err) => err.clone(),
+      EpubError::Io(err) => err.to_string(),
+    }
+  }
+}
+impl From<io::Error> for EpubError {
+  fn from(err: io::Error) -> Self {
+    EpubError::Io(err)
+  }
+}
+impl From<ZipError> for EpubError {
+  fn from(err: ZipError) -> Self {
+    match err {
+      ZipError::Io(err) => EpubError::Io(err),
+      err => EpubError::Zip(err.to_string()),
+    }
+  }
+}
+
+pub fn sort(notes: &mut [(Meta, String)], key: SortKey, descending: bool) {
+  match key {
+    SortKey::CreatedAt => notes.sort_by_key(|(m, _)| parse_time(m.get_created_at())),
+    SortKey::UpdatedAt => notes.sort_by_key(|(m, _)| last_modified(m)),
+    SortKey::Title => notes.sort_by(|(a, _), (b, _)| a.get_filename().cmp(b.get_filename())),
+  }
+  if descending {
+    notes.reverse();
+  }
+}
+
+fn chapter_name(i: usize) -> String {
+  format!("chapter-{}.xhtml", i + 1)
+}
+
+fn toc_title(lang: &str) -> &'static str {
+  match lang {
+    "ja" => "目次",
+    _ => "Contents",
+  }
+}
+
+fn xhtml(title: &str, lang: &str, body: &str) -> String {
+  format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+    <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" xml:lang=\"{lang}\" lang=\"{lang}\">\n\
+    <head>\n<meta charset=\"UTF-8\" />\n<title>{title}</title>\n\
+    <link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\" />\n</head>\n\
+    <body>\n{body}\n</body>\n</html>\n",
+    lang = lang,
+    title = escape_html(title),
+    body = body
+  )
+}
+
+fn chapter(meta: &Meta, html: &str, lang: &str) -> String {
+  let mut header = format!(
+    "<h1 class=\"title\">{}</h1>\n<p class=\"meta\">",
+    escape_html(meta.get_filename())
+  );
+  if !meta.get_author().is_empty() {
+    header.push_str(&format!("{}, ", escape_html(meta.get_author())));
+  }
+  header.push_str(&format!(
+    "<time>{}</time></p>\n",
+    escape_html(meta.get_created_at())
+  ));
+  xhtml(
+    meta.get_filename(),
+    lang,
+    &format!(
+      "<section epub:type=\"chapter\">\n{}{}</section>",
+      header, html
+    ),
+  )
+}
+
+fn nav(title: &str, chapters: &[(&Meta, Vec<Heading>)], lang: &str) -> String {
+  let mut toc = format!(
+    "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n",
+    toc_title(lang)
+  );
+  for (i, (meta, headings)) in chapters.iter().enumerate() {
+    let name = chapter_name(i);
+    toc.push_str(&format!(
+      "<li><a href=\"{}\">{}</a>",
+      name,
+      escape_html(meta.get_filename())
+    ));
+    let headings = headings.iter().filter(|h| h.level <= 2).collect::<Vec<_>>();
+    if !headings.is_empty() {
+      toc.push_str("<ol>");
+      for heading in headings {
+        toc.push_str(&format!(
+          "<li><a href=\"{}#{}\">{}</a></li>",
+          name,
+          escape_html(&heading.id),
+          escape_html(&heading.text)
+        ));
+      }
+      toc.push_str("</ol>");
+    }
+    toc.push_str("</li>\n");
+  }
+  toc.push_str("</ol>\n</nav>");
+  xhtml(title, lang, &toc)
+}
+
+struct Image {
+  href: String,
+  media_type: String,
+}
+
+fn package(
+  title: &str,
+  notes: &[(Meta, String)],
+  mathml: &[bool],
+  images: &[Image],
+  lang: &str,
+) -> String {
+  let titles = notes
+    .iter()
+    .map(|(m, _)| m.get_filename())
+    .collect::<Vec<_>>()
+    .join("\n");
+  let mut metadata = format!(
+    "<dc:identifier id=\"bookid\">urn:sha256:{}</dc:identifier>\n\
+    <dc:title>{}</dc:title>\n<dc:language>{}</dc:language>\n",
+    model::digest(format!("{}\n{}", title, titles).as_bytes()),
+    escape_html(title),
+    lang
+  );
+  let authors = notes
+    .iter()
+    .map(|(m, _)| m.get_author())
+    .filter(|a| !a.is_empty())
+    .collect::<BTreeSet<_>>();
+  for author in authors {
+    metadata.push_str(&format!(
+      "<dc:creator>{}</dc:creator>\n",
+      escape_html(author)
+    ));
+  }
+  let tags = notes
+    .iter()
+    .flat_map(|(m, _)| m.get_tags().iter())
+    .collect::<BTreeSet<_>>();
+  for tag in tags {
+    metadata.push_str(&format!("<dc:subject>{}</dc:subject>\n", escape_html(tag)));
+  }
+  if let Some(created) = notes
+    .iter()
+    .filter_map(|(m, _)| parse_time(m.get_created_at()))
+    .min()
+  {
+    metadata.push_str(&format!(
+      "<dc:date>{}</dc:date>\n",
+      created.format("%Y-%m-%d")
+    ));
+  }
+  let modified = notes
+    .iter()
+    .filter_map(|(m, _)| last_modified(m))
+    .max()
+    .map(|time| time.with_timezone(&Utc))
+    .unwrap_or_else(Utc::now);
+  metadata.push_str(&format!(
+    "<meta property=\"dcterms:modified\">{}</meta>\n",
+    modified.format("%Y-%m-%dT%H:%M:%SZ")
+  ));
+
+  let mut manifest = format!(
+    "<item id=\"nav\" href=\"{}\" media-type=\"application/xhtml+xml\" properties=\"nav\" />\n\
+    <item id=\"style\" href=\"style.css\" media-type=\"text/css\" />\n",
+    NAV
+  );
+  let mut spine = String::from("<itemref idref=\"nav\" />\n");
+  for (i, has_math) in mathml.iter().enumerate() {
+    manifest.push_str(&format!(
+      "<item id=\"chapter-{}\" href=\"{}\" media-type=\"application/xhtml+xml\"{} />\n",
+      i + 1,
+      chapter_name(i),
+      if *has_math {
+        " properties=\"mathml\""
+      } else {
+        ""
+      }
+    ));
+    spine.push_str(&format!("<itemref idref=\"chapter-{}\" />\n", i + 1));
+  }
+  for (i, image) in images.iter().enumerate() {
+    manifest.push_str(&format!(
+      "<item id=\"image-{}\" href=\"{}\" media-type=\"{}\" />\n",
+      i + 1,
+      image.href,
+      image.media_type
+    ));
+  }
+
+  format!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+    <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"bookid\" xml:lang=\"{}\">\n\
+    <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n\
+    <manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+    lang, metadata, manifest, spine
+  )
+}
+
+/*
+ * the notes are chapters in the order given. relative image paths are looked up from the directory of each body,
+ * then the target directory. global is the macros in the setting, under the ones of each note.
+ */
+pub fn build(
+  notes: &[(Meta, String)],
+  options: &EpubOptions,
+  target_dir: &Path,
+  global: &HashMap<String, String>,
+  lang: &str,
+) -> Result<Epub, EpubError> {
+  if notes.is_empty() {
+    return Err(EpubError::Empty);
+  }
+  let title = if options.title.is_empty() {
+    "notes"
+  } else {
+    &options.title
+  };
+
+  let mut links = HashMap::new();
+  for (i, (meta, _)) in notes.iter().enumerate() {
+    for key in [
+      meta.get_filename().to_string(),
+      format!("{}{}", meta.get_filename(), TARGET_EXTENTION),
+      meta.body_path(),
+      meta.get_hashed_filename(),
+      meta.get_hashed_html_name(),
+    ] {
+      links.entry(key).or_insert_with(|| chapter_name(i));
+    }
+  }
+
+  // source path => index in images
+  let embedded = RefCell::new(HashMap::<PathBuf, usize>::new());
+  let images = RefCell::new(vec![]);
+  let missing = RefCell::new(vec![]);
+  let mut chapters = vec![];
+  for (meta, md) in notes {
+    let body_path = target_dir.join(meta.body_path());
+    let dirs = body_path
+      .parent()
+      .map(Path::to_path_buf)
+      .into_iter()
+      .chain(std::iter::once(target_dir.to_path_buf()))
+      .collect::<Vec<_>>();
+    let rendered = render::render_book(
+      md,
+      |dest| links.get(dest).cloned(),
+      |src| {
+        let path = match local_file(src, &dirs) {
+          Some(path) => path,
+          None => {
+            missing.borrow_mut().push(src.to_string());
+            return None;
+          }
+        };
+        let mut images = images.borrow_mut();
+        let i = *embedded
+          .borrow_mut()
+          .entry(path.clone())
+          .or_insert_with(|| {
+            let n = images.len() + 1;
+            let ext = path
+              .extension()
+              .map(|ext| ext.to_string_lossy().to_lowercase())
+              .unwrap_or_else(|| String::from("bin"));
+            let image = Image {
+              href: format!("images/{}.{}", n, ext),
+              media_type: mime_guess::from_path(&path)
+                .first_or_octet_stream()
+                .essence_str()
+                .to_string(),
+            };
+            images.push((path.clone(), image));
+            n - 1
+          });
+        Some(images[i].1.href.clone())
+      },
+      &macros::merge(global, meta.get_shortcut()),
+    );
+    chapters.push((meta, rendered));
+  }
+  let images = images.into_inner();
+
+  let mut zip = ZipWriter::new(Cursor::new(vec![]));
+  // the mimetype must come first and uncompressed
+  zip.start_file(
+    "mimetype",
+    FileOptions::default().compression_method(CompressionMethod::Stored),
+  )?;
+  zip.write_all(b"application/epub+zip")?;
+  let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+  zip.start_file("META-INF/container.xml", deflated)?;
+  zip.write_all(
+    b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+    <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+    <rootfiles>\n<rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\" />\n\
+    </rootfiles>\n</container>\n",
+  )?;
+  zip.start_file("OEBPS/style.css", deflated)?;
+  zip.write_all(STYLE.as_bytes())?;
+
+  let mut mathml = vec![];
+  for (i, (meta, rendered)) in chapters.iter().enumerate() {
+    mathml.push(rendered.html.contains("<math"));
+    zip.start_file(format!("OEBPS/{}", chapter_name(i)), deflated)?;
+    zip.write_all(chapter(meta, &rendered.html, lang).as_bytes())?;
+  }
+  let toc = chapters
+    .into_iter()
+    .map(|(meta, rendered)| (meta, rendered.headings))
+    .collect::<Vec<_>>();
+  zip.start_file(format!("OEBPS/{}", NAV), deflated)?;
+  zip.write_all(nav(title, &toc, lang).as_bytes())?;
+  for (path, image) in &images {
+    zip.start_file(format!("OEBPS/{}", image.href), deflated)?;
+    zip.write_all(&fs::read(path)?)?;
+  }
+  let images = images
+    .into_iter()
+    .map(|(_, image)| image)
+    .collect::<Vec<_>>();
+  zip.start_file("OEBPS/content.opf", deflated)?;
+  zip.write_all(package(title, notes, &mathml, &images, lang).as_bytes())?;
+
+  Ok(Epub {
+    bytes: zip.finish()?.into_inner(),
+    chapters: notes.len(),
+    missing: missing.into_inner(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::constants::TIME_FORMAT;
+  use crate::model::tests::{get_random_key, meta};
+  use chrono::{offset::TimeZone, Local};
+  use std::io::Read;
+  use zip::ZipArchive;
+
+  #[test]
+  fn epub_build_test() {
+    let dir = std::env::temp_dir().join(format!("notex-epub-{}", get_random_key()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("dot.png"), [0x89, b'P', b'N', b'G']).unwrap();
+    let note = |title: &str, day: u32| {
+      let created_at = Local.ymd(2021, 10, day).and_hms(9, 0, 0);
+      meta(
+        title,
+        &["course"],
+        &created_at.format(TIME_FORMAT).to_string(),
+      )
+    };
+    let first = note("test file", 1);
+    let second = note("Second Note", 2);
+    let mut notes = vec![
+      (
+        second,
+        String::from("## Part\n\n$x^2$ ![dot](dot.png) ![web](https://example.com/a.png)"),
+      ),
+      (
+        first.clone(),
+        String::from("[next](Second%20Note.md#part) ![again](./dot.png)"),
+      ),
+    ];
+    sort(&mut notes, SortKey::Title, false);
+    assert_eq!(notes[0].0.get_filename(), "Second Note");
+    sort(&mut notes, SortKey::CreatedAt, false);
+    assert_eq!(notes[0].0, first);
+
+    let options = EpubOptions {
+      title: String::from("Course"),
+      ..EpubOptions::default()
+    };
+    let epub = build(&notes, &options, &dir, &HashMap::new(), "en").unwrap();
+    assert_eq!(epub.chapters, 2);
+    assert_eq!(
+      epub.missing,
+      vec![String::from("https://example.com/a.png")]
+    );
+
+    let mut archive = ZipArchive::new(Cursor::new(epub.bytes)).unwrap();
+    let read = |archive: &mut ZipArchive<Cursor<Vec<u8>>>, name: &str| {
+      let mut res = String::new();
+      archive
+        .by_name(name)
+        .unwrap()
+        .read_to_string(&mut res)
+        .unwrap();
+      res
+    };
+    let mimetype = archive.by_index(0).unwrap();
+    assert_eq!(mimetype.name(), "mimetype");
+    assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+    drop(mimetype);
+
+    let opf = read(&mut archive, "OEBPS/content.opf");
+    assert!(opf.contains("<dc:title>Course</dc:title>"));
+    assert!(opf.contains(
+      "href=\"chapter-2.xhtml\" media-type=\"application/xhtml+xml\" properties=\"mathml\""
+    ));
+    assert!(opf.contains("<item id=\"image-1\" href=\"images/1.png\" media-type=\"image/png\" />"));
+    assert!(!opf.contains("image-2"));
+    assert!(opf.contains("<meta property=\"dcterms:modified\">"));
+
+    let nav = read(&mut archive, "OEBPS/nav.xhtml");
+    assert!(nav.contains("<a href=\"chapter-2.xhtml#part\">Part</a>"));
+    let chapter = read(&mut archive, "OEBPS/chapter-1.xhtml");
+    assert!(chapter.contains("href=\"chapter-2.xhtml#part\""));
+    assert!(chapter.contains("src=\"images/1.png\""));
+    assert!(
+      read(&mut archive, "OEBPS/chapter-2.xhtml").contains("<msup><mi>x</mi><mn>2</mn></msup>")
+    );
+    assert!(archive.by_name("OEBPS/images/1.png").is_ok());
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
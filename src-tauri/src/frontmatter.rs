@@ -0,0 +1,166 @@
+use super::model::{Matter, Meta};
+use serde::de::DeserializeOwned;
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum FrontMatter {
+  #[serde(rename = "none")]
+  Disabled,
+  #[serde(rename = "yaml")]
+  Yaml,
+  #[serde(rename = "toml")]
+  Toml,
+}
+impl Default for FrontMatter {
+  fn default() -> Self {
+    FrontMatter::Disabled
+  }
+}
+
+static YAML_FENCE: &str = "---";
+static TOML_FENCE: &str = "+++";
+
+/*
+ * returns the body prefixed with front matter of the meta.
+ * the body is returned as is if front matter is disabled.
+ */
+pub fn attach(meta: &Meta, body: &str, format: FrontMatter) -> Result<String, String> {
+  let matter = meta.to_matter();
+  let (fence, src) = match format {
+    FrontMatter::Disabled => return Ok(body.to_string()),
+    FrontMatter::Yaml => (
+      YAML_FENCE,
+      serde_yaml::to_string(&matter).map_err(|e| e.to_string())?,
+    ),
+    FrontMatter::Toml => (
+      TOML_FENCE,
+      toml::to_string(&matter).map_err(|e| e.to_string())?,
+    ),
+  };
+  // serde_yaml starts its output with the document marker, which is the fence itself.
+  let src = src.trim_start_matches(YAML_FENCE).trim_matches('\n');
+  Ok(format!("{}\n{}\n{}\n{}", fence, src, fence, body))
+}
+
+/*
+ * the fences which front matter may start with. unlike FrontMatter, it can't be disabled.
+ */
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Fence {
+  Yaml,
+  Toml,
+}
+impl Fence {
+  fn as_str(&self) -> &'static str {
+    match self {
+      Fence::Yaml => YAML_FENCE,
+      Fence::Toml => TOML_FENCE,
+    }
+  }
+
+  fn deserialize<T: DeserializeOwned>(&self, matter: &str) -> Result<T, String> {
+    match self {
+      Fence::Yaml => serde_yaml::from_str::<T>(matter).map_err(|e| e.to_string()),
+      Fence::Toml => toml::from_str::<T>(matter).map_err(|e| e.to_string()),
+    }
+  }
+}
+
+/*
+ * split the source into the raw front matter with its fence and the body.
+ * the block may be a thematic break and a setext heading as well, so it has to be parsed to tell.
+ */
+fn split(src: &str) -> Option<(Fence, &str, &str)> {
+  let fence = if src.starts_with(&format!("{}\n", YAML_FENCE)) {
+    Fence::Yaml
+  } else if src.starts_with(&format!("{}\n", TOML_FENCE)) {
+    Fence::Toml
+  } else {
+    return None;
+  };
+  let rest = &src[fence.as_str().len() + 1..];
+  let mut offset = 0;
+  for line in rest.split_inclusive('\n') {
+    if line.trim_end() == fence.as_str() {
+      let body = &rest[offset + line.len()..];
+      return Some((fence, &rest[..offset], body));
+    }
+    offset += line.len();
+  }
+  None
+}
+
+/*
+ * strip front matter, so that the editor and the renderers only see the body.
+ * only when front matter is enabled and the block at the top is front matter of a document,
+ * or a body starting with `---` would lose its first lines.
+ */
+pub fn strip(src: &str, format: FrontMatter) -> &str {
+  if format == FrontMatter::Disabled {
+    return src;
+  }
+  match split(src) {
+    Some((fence, matter, body)) if fence.deserialize::<Matter>(matter).is_ok() => body,
+    _ => src,
+  }
+}
+
+/*
+ * parse front matter into meta. None if the source has no front matter.
+ */
+pub fn parse(src: &str) -> Option<Result<Meta, String>> {
+  Some(parse_as::<Matter>(src)?.map(|(matter, _)| Meta::from_matter(matter)))
+}
+
+/*
+ * parse front matter into any shape, e.g. that of a document template -- see doctemplate.rs
+ * returns the body after it as well.
+ */
+pub fn parse_as<T: DeserializeOwned>(src: &str) -> Option<Result<(T, &str), String>> {
+  let (fence, matter, body) = split(src)?;
+  Some(fence.deserialize::<T>(matter).map(|matter| (matter, body)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::Setup;
+
+  #[test]
+  fn front_matter_round_trip_test() {
+    let mut setup = Setup::init();
+    setup.get_meta_mut().stamp();
+    let meta = setup.get_meta();
+    let body = "# title\n\n---\n\nbody after a rule";
+
+    for format in [FrontMatter::Yaml, FrontMatter::Toml].iter() {
+      let src = attach(meta, body, *format).unwrap();
+      assert_eq!(strip(&src, *format), body);
+      // the other format enabled still strips it
+      assert_eq!(strip(&src, FrontMatter::Yaml), body);
+      assert_eq!(strip(&src, FrontMatter::Disabled), src);
+      assert_eq!(parse(&src).unwrap().unwrap().to_matter(), meta.to_matter());
+    }
+  }
+
+  #[test]
+  fn no_front_matter_test() {
+    let setup = Setup::init();
+    let body = "# title\n---\n";
+    assert_eq!(
+      attach(setup.get_meta(), body, FrontMatter::Disabled).unwrap(),
+      body
+    );
+    assert_eq!(strip(body, FrontMatter::Yaml), body);
+    assert!(parse(body).is_none());
+  }
+
+  #[test]
+  fn setext_heading_is_not_front_matter_test() {
+    // a thematic break followed by a setext heading
+    let body = "---\nheading\n---\n\nbody";
+    for format in [FrontMatter::Yaml, FrontMatter::Toml].iter() {
+      assert_eq!(strip(body, *format), body);
+    }
+    assert!(parse(body).unwrap().is_err());
+  }
+}
@@ -0,0 +1,318 @@
+use super::links::LinkGraph;
+use super::model::Meta;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/*
+ * the graph of the notes for visualisation.
+ * - nodes are notes, and tags if asked, identified by `note:<title>` and `tag:<tag>`
+ * - link edges go from the note which links to the linked one, weighted by the number of wiki links
+ * - tag edges join a note to its tags, and shared tag edges join two notes by the number of tags in common
+ * only the notes given, i.e. those matching the filter, are nodes, so the links out of them are dropped.
+ * a neighbourhood keeps the nodes within the hops from a note, going along the edges in either direction.
+ */
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum NodeKind {
+  #[serde(rename = "note")]
+  Note,
+  #[serde(rename = "tag")]
+  Tag,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Node {
+  pub id: String,
+  pub kind: NodeKind,
+  pub label: String,
+  pub weight: usize, //links to the note, or notes with the tag
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum EdgeKind {
+  #[serde(rename = "link")]
+  Link,
+  #[serde(rename = "tag")]
+  Tag,
+  #[serde(rename = "shared_tags")]
+  SharedTags,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Edge {
+  pub source: String,
+  pub target: String,
+  pub kind: EdgeKind,
+  pub weight: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct GraphOptions {
+  pub tags: bool,             //tags as nodes
+  pub shared_tags: bool,      //edges between the notes with tags in common
+  pub center: Option<String>, //title of the note to take the neighbourhood of. if None, the whole graph
+  pub hops: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize)]
+pub struct Graph {
+  pub nodes: Vec<Node>,
+  pub edges: Vec<Edge>,
+}
+
+pub fn note_id(title: &str) -> String {
+  format!("note:{}", title)
+}
+
+pub fn tag_id(tag: &str) -> String {
+  format!("tag:{}", tag)
+}
+
+pub fn build(metas: &[Meta], links: &LinkGraph, options: &GraphOptions) -> Graph {
+  let titles = metas
+    .iter()
+    .map(|meta| meta.get_filename())
+    .collect::<BTreeSet<_>>();
+  let mut weights = BTreeMap::new();
+  let mut edges = vec![];
+
+  for title in &titles {
+    let mut targets = BTreeMap::new();
+    for link in links
+      .get(title)
+      .map(|entry| &entry.links[..])
+      .unwrap_or(&[])
+    {
+      match links.resolve(title, link) {
+        Some(target) if target != *title && titles.contains(target) => {
+          *targets.entry(target).or_insert(0) += 1;
+        }
+        _ => (),
+      }
+    }
+    for (target, weight) in targets {
+      *weights.entry(note_id(target)).or_insert(0) += weight;
+      edges.push(Edge {
+        source: note_id(title),
+        target: note_id(target),
+        kind: EdgeKind::Link,
+        weight,
+      });
+    }
+  }
+
+  let tags_of = metas
+    .iter()
+    .map(|meta| {
+      (
+        meta.get_filename(),
+        meta.get_tags().iter().collect::<BTreeSet<_>>(),
+      )
+    })
+    .collect::<BTreeMap<_, _>>();
+  let mut tags = BTreeSet::new();
+  if options.tags {
+    for (title, own) in &tags_of {
+      for tag in own {
+        tags.insert(*tag);
+        *weights.entry(tag_id(tag)).or_insert(0) += 1;
+        edges.push(Edge {
+          source: note_id(title),
+          target: tag_id(tag),
+          kind: EdgeKind::Tag,
+          weight: 1,
+        });
+      }
+    }
+  }
+  if options.shared_tags {
+    for (i, (title, own)) in tags_of.iter().enumerate() {
+      for (other, others) in tags_of.iter().skip(i + 1) {
+        let shared = own.intersection(others).count();
+        if 0 < shared {
+          edges.push(Edge {
+            source: note_id(title),
+            target: note_id(other),
+            kind: EdgeKind::SharedTags,
+            weight: shared,
+          });
+        }
+      }
+    }
+  }
+
+  let nodes = titles
+    .iter()
+    .map(|title| (note_id(title), NodeKind::Note, title.to_string()))
+    .chain(
+      tags
+        .iter()
+        .map(|tag| (tag_id(tag), NodeKind::Tag, tag.to_string())),
+    )
+    .map(|(id, kind, label)| Node {
+      weight: weights.get(&id).copied().unwrap_or(0),
+      id,
+      kind,
+      label,
+    })
+    .collect();
+  let graph = Graph { nodes, edges };
+
+  match &options.center {
+    Some(center) => graph.neighbourhood(&note_id(center), options.hops),
+    None => graph,
+  }
+}
+
+impl Graph {
+  pub fn contains(&self, id: &str) -> bool {
+    self.nodes.iter().any(|node| node.id == id)
+  }
+
+  /*
+   * the nodes within the hops from the node, and the edges between them.
+   */
+  pub fn neighbourhood(self, id: &str, hops: usize) -> Graph {
+    let mut adjacent = BTreeMap::new();
+    for edge in &self.edges {
+      adjacent
+        .entry(edge.source.as_str())
+        .or_insert_with(Vec::new)
+        .push(edge.target.as_str());
+      adjacent
+        .entry(edge.target.as_str())
+        .or_insert_with(Vec::new)
+        .push(edge.source.as_str());
+    }
+
+    let mut reached = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    if self.contains(id) {
+      reached.insert(id);
+      queue.push_back((id, 0));
+    }
+    while let Some((node, distance)) = queue.pop_front() {
+      if hops <= distance {
+        continue;
+      }
+      for next in adjacent.get(node).map(|v| &v[..]).unwrap_or(&[]) {
+        if reached.insert(*next) {
+          queue.push_back((*next, distance + 1));
+        }
+      }
+    }
+
+    let reached = reached
+      .into_iter()
+      .map(String::from)
+      .collect::<BTreeSet<_>>();
+    Graph {
+      nodes: self
+        .nodes
+        .into_iter()
+        .filter(|node| reached.contains(&node.id))
+        .collect(),
+      edges: self
+        .edges
+        .into_iter()
+        .filter(|edge| reached.contains(&edge.source) && reached.contains(&edge.target))
+        .collect(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests;
+
+  fn meta(title: &str, tags: &[&str]) -> Meta {
+    tests::meta(title, tags, "")
+  }
+
+  #[test]
+  fn note_graph_test() {
+    let metas = vec![
+      meta("a", &["math", "algebra"]),
+      meta("b", &["math", "algebra"]),
+      meta("c", &["math"]),
+      meta("d", &[]),
+    ];
+    let links = LinkGraph::build(&[
+      (
+        String::from("a"),
+        String::from("[[b]] [[B#x]] [[a]] [[missing]]"),
+      ),
+      (String::from("b"), String::from("[[c]]")),
+      (String::from("c"), String::from("[[d]]")),
+      (String::from("d"), String::new()),
+    ]);
+
+    let graph = build(&metas, &links, &GraphOptions::default());
+    assert_eq!(graph.nodes.len(), 4);
+    assert_eq!(
+      graph.edges[0],
+      Edge {
+        source: note_id("a"),
+        target: note_id("b"),
+        kind: EdgeKind::Link,
+        weight: 2,
+      }
+    );
+    assert_eq!(graph.edges.len(), 3);
+    assert_eq!(graph.nodes[1].weight, 2);
+
+    let graph = build(
+      &metas,
+      &links,
+      &GraphOptions {
+        tags: true,
+        shared_tags: true,
+        ..Default::default()
+      },
+    );
+    assert_eq!(graph.nodes.len(), 6);
+    assert_eq!(
+      graph
+        .nodes
+        .iter()
+        .find(|n| n.id == tag_id("math"))
+        .unwrap()
+        .weight,
+      3
+    );
+    assert!(graph.edges.contains(&Edge {
+      source: note_id("a"),
+      target: note_id("b"),
+      kind: EdgeKind::SharedTags,
+      weight: 2,
+    }));
+
+    // only the filtered notes are nodes
+    let graph = build(&metas[1..], &links, &GraphOptions::default());
+    assert_eq!(graph.edges.len(), 2);
+
+    let near = |hops| {
+      build(
+        &metas,
+        &links,
+        &GraphOptions {
+          center: Some(String::from("a")),
+          hops,
+          ..Default::default()
+        },
+      )
+    };
+    assert_eq!(near(0).nodes.len(), 1);
+    let graph = near(2);
+    assert_eq!(
+      graph
+        .nodes
+        .iter()
+        .map(|n| n.label.as_str())
+        .collect::<Vec<_>>(),
+      vec!["a", "b", "c"]
+    );
+    assert_eq!(graph.edges.len(), 2);
+  }
+}
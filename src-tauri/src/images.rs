@@ -0,0 +1,344 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageFormat, ImageOutputFormat};
+
+/*
+ * processing of the images added to the attachment store -- see attachments.rs
+ * - exif and other metadata are stripped from the original without re-encoding it,
+ *   except when the exif rotates the image, where the original is re-encoded upright instead.
+ * - the optimized variant is downscaled to the max dimension and re-encoded with the quality,
+ *   and is dropped if it is not smaller than the original.
+ * - the thumbnail fits in a square of the thumbnail size.
+ * gifs keep their animation, so they are only measured and thumbnailed.
+ */
+
+static UPRIGHT_QUALITY: u8 = 95;
+static THUMBNAIL_QUALITY: u8 = 80;
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Variant {
+  #[serde(rename = "original")]
+  Original,
+  #[serde(rename = "optimized")]
+  Optimized,
+}
+impl Default for Variant {
+  fn default() -> Self {
+    Variant::Optimized
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ImageOptions {
+  pub optimize: bool,
+  pub max_dimension: u32, //of the longer side in pixels. if 0, not downscaled
+  pub quality: u8,        //of jpeg, 1 to 100
+  pub thumbnail: u32,     //of the longer side in pixels. if 0, no thumbnail
+  pub strip_exif: bool,
+  pub export: Variant, //which the exports use unless they are told
+}
+impl Default for ImageOptions {
+  fn default() -> Self {
+    ImageOptions {
+      optimize: true,
+      max_dimension: 1920,
+      quality: 80,
+      thumbnail: 256,
+      strip_exif: true,
+      export: Variant::default(),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Encoded {
+  pub bytes: Vec<u8>,
+  pub ext: &'static str,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Processed {
+  pub original: Vec<u8>,
+  pub width: u32, //upright
+  pub height: u32,
+  pub optimized: Option<Encoded>,
+  pub thumbnail: Option<Encoded>,
+}
+
+pub fn format_of(ext: &str) -> Option<ImageFormat> {
+  match ext {
+    "png" => Some(ImageFormat::Png),
+    "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+    "gif" => Some(ImageFormat::Gif),
+    _ => None,
+  }
+}
+
+fn be16(b: &[u8]) -> usize {
+  ((b[0] as usize) << 8) | b[1] as usize
+}
+
+/*
+ * the orientation tag in the ifd0 of an exif payload, which follows `Exif\0\0`.
+ */
+fn orientation(tiff: &[u8]) -> Option<u16> {
+  let little = match tiff.get(..2)? {
+    b"II" => true,
+    b"MM" => false,
+    _ => return None,
+  };
+  let u16_at = |i: usize| -> Option<u16> {
+    let b = tiff.get(i..i + 2)?;
+    Some(if little {
+      u16::from_le_bytes([b[0], b[1]])
+    } else {
+      u16::from_be_bytes([b[0], b[1]])
+    })
+  };
+  let u32_at = |i: usize| -> Option<u32> {
+    let b = tiff.get(i..i + 4)?;
+    Some(if little {
+      u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+    } else {
+      u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+    })
+  };
+  let ifd = u32_at(4)? as usize;
+  (0..u16_at(ifd)? as usize)
+    .map(|n| ifd + 2 + n * 12)
+    .find(|&entry| u16_at(entry) == Some(0x0112))
+    .and_then(|entry| u16_at(entry + 8))
+}
+
+/*
+ * the jpeg without the app1 segments of exif and xmp, and the orientation in the exif.
+ * a malformed jpeg is returned as it is, for the decoder to judge.
+ */
+fn strip_jpeg(bytes: &[u8]) -> (Vec<u8>, Option<u16>) {
+  if !bytes.starts_with(&[0xFF, 0xD8]) {
+    return (bytes.to_vec(), None);
+  }
+  let mut res = bytes[..2].to_vec();
+  let mut found = None;
+  let mut i = 2;
+  while i + 4 <= bytes.len() && bytes[i] == 0xFF {
+    let marker = bytes[i + 1];
+    // the scan and what follows are entropy coded
+    if marker == 0xDA || marker == 0xD9 {
+      break;
+    }
+    if marker == 0x01 || (0xD0..=0xD7).contains(&marker) || marker == 0xFF {
+      res.push(bytes[i]);
+      i += 1;
+      continue;
+    }
+    let end = (i + 2 + be16(&bytes[i + 2..])).min(bytes.len());
+    // the length counts itself, so a smaller one is broken
+    if end < i + 4 {
+      return (bytes.to_vec(), None);
+    }
+    let payload = &bytes[i + 4..end];
+    if marker == 0xE1 && payload.starts_with(b"Exif\0\0") {
+      found = orientation(&payload[6..]);
+    } else if !(marker == 0xE1 && payload.starts_with(b"http://ns.adobe.com/xap/1.0/\0")) {
+      res.extend_from_slice(&bytes[i..end]);
+    }
+    i = end;
+  }
+  res.extend_from_slice(&bytes[i.min(bytes.len())..]);
+  (res, found)
+}
+
+/*
+ * the png without the exif and text chunks.
+ */
+fn strip_png(bytes: &[u8]) -> Vec<u8> {
+  static SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+  if !bytes.starts_with(SIGNATURE) {
+    return bytes.to_vec();
+  }
+  let mut res = SIGNATURE.to_vec();
+  let mut i = SIGNATURE.len();
+  while i + 12 <= bytes.len() {
+    let len = u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]) as usize;
+    let end = (i + 12 + len).min(bytes.len());
+    match &bytes[i + 4..i + 8] {
+      b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" => (),
+      _ => res.extend_from_slice(&bytes[i..end]),
+    }
+    i = end;
+  }
+  res.extend_from_slice(&bytes[i.min(bytes.len())..]);
+  res
+}
+
+fn upright(image: DynamicImage, orientation: u16) -> DynamicImage {
+  match orientation {
+    2 => image.fliph(),
+    3 => image.rotate180(),
+    4 => image.flipv(),
+    5 => image.rotate90().fliph(),
+    6 => image.rotate90(),
+    7 => image.rotate270().fliph(),
+    8 => image.rotate270(),
+    _ => image,
+  }
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat, quality: u8) -> image::ImageResult<Encoded> {
+  let mut bytes = vec![];
+  let ext = match format {
+    ImageFormat::Jpeg => {
+      image.write_to(&mut bytes, ImageOutputFormat::Jpeg(quality.clamp(1, 100)))?;
+      "jpg"
+    }
+    _ => {
+      image.write_to(&mut bytes, ImageOutputFormat::Png)?;
+      "png"
+    }
+  };
+  Ok(Encoded { bytes, ext })
+}
+
+pub fn process(
+  bytes: &[u8],
+  format: ImageFormat,
+  options: &ImageOptions,
+) -> image::ImageResult<Processed> {
+  let (stripped, rotation) = match format {
+    ImageFormat::Jpeg => strip_jpeg(bytes),
+    ImageFormat::Png => (strip_png(bytes), None),
+    _ => (bytes.to_vec(), None),
+  };
+  let image = image::load_from_memory_with_format(&stripped, format)?;
+  let image = upright(image, rotation.unwrap_or(1));
+  let original = if !options.strip_exif {
+    bytes.to_vec()
+  } else if 1 < rotation.unwrap_or(1) {
+    encode(&image, format, UPRIGHT_QUALITY)?.bytes
+  } else {
+    stripped
+  };
+
+  let (width, height) = image.dimensions();
+  let optimized = if options.optimize && format != ImageFormat::Gif {
+    let max = options.max_dimension;
+    let resized = if 0 < max && max < width.max(height) {
+      image.resize(max, max, FilterType::Lanczos3)
+    } else {
+      image.clone()
+    };
+    Some(encode(&resized, format, options.quality)?).filter(|e| e.bytes.len() < original.len())
+  } else {
+    None
+  };
+  let thumbnail = if 0 < options.thumbnail {
+    let format = if format == ImageFormat::Jpeg {
+      ImageFormat::Jpeg
+    } else {
+      ImageFormat::Png
+    };
+    Some(encode(
+      &image.thumbnail(options.thumbnail, options.thumbnail),
+      format,
+      THUMBNAIL_QUALITY,
+    )?)
+  } else {
+    None
+  };
+
+  Ok(Processed {
+    original,
+    width,
+    height,
+    optimized,
+    thumbnail,
+  })
+}
+
+#[cfg(test)]
+pub mod tests {
+  use super::*;
+  use image::{ImageBuffer, Rgb};
+
+  pub fn gradient(width: u32, height: u32, format: ImageFormat) -> Vec<u8> {
+    let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+      Rgb([(x * 7) as u8, (y * 13) as u8, ((x + y) * 3) as u8])
+    }));
+    encode(&image, format, 90).unwrap().bytes
+  }
+
+  // an app1 segment of exif with only the orientation in the ifd0
+  fn exif(orientation: u8) -> Vec<u8> {
+    let mut tiff = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+    tiff.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+    tiff.extend_from_slice(&[0, 0, 0, 0]);
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&((tiff.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(&tiff);
+    segment
+  }
+
+  #[test]
+  fn process_image_test() {
+    let options = ImageOptions {
+      max_dimension: 16,
+      thumbnail: 8,
+      ..ImageOptions::default()
+    };
+
+    let png = gradient(64, 32, ImageFormat::Png);
+    let res = process(&png, ImageFormat::Png, &options).unwrap();
+    assert_eq!((res.width, res.height), (64, 32));
+    let optimized = res.optimized.unwrap();
+    assert_eq!(optimized.ext, "png");
+    assert_eq!(
+      image::load_from_memory(&optimized.bytes)
+        .unwrap()
+        .dimensions(),
+      (16, 8)
+    );
+    assert_eq!(
+      image::load_from_memory(&res.thumbnail.unwrap().bytes)
+        .unwrap()
+        .dimensions(),
+      (8, 4)
+    );
+
+    // the exif is stripped, and the rotation is applied to the original
+    let jpeg = gradient(40, 20, ImageFormat::Jpeg);
+    let mut rotated = jpeg[..2].to_vec();
+    rotated.extend_from_slice(&exif(6));
+    rotated.extend_from_slice(&jpeg[2..]);
+    assert_eq!(strip_jpeg(&rotated), (jpeg.clone(), Some(6)));
+    // segments too short for their own length are left as they are
+    let zero = [0xFF, 0xD8, 0xFF, 0xE1, 0, 0];
+    assert_eq!(strip_jpeg(&zero), (zero.to_vec(), None));
+    let truncated = [0xFF, 0xD8, 0xFF, 0xE0, 0, 1, 0xFF];
+    assert_eq!(strip_jpeg(&truncated), (truncated.to_vec(), None));
+    let res = process(&rotated, ImageFormat::Jpeg, &options).unwrap();
+    assert_eq!((res.width, res.height), (20, 40));
+    assert!(!res.original.windows(4).any(|w| w == b"Exif"));
+    assert_eq!(
+      image::load_from_memory(&res.original).unwrap().dimensions(),
+      (20, 40)
+    );
+
+    let mut upright = jpeg[..2].to_vec();
+    upright.extend_from_slice(&exif(1));
+    upright.extend_from_slice(&jpeg[2..]);
+    let res = process(&upright, ImageFormat::Jpeg, &options).unwrap();
+    assert_eq!(res.original, jpeg);
+    let res = process(
+      &upright,
+      ImageFormat::Jpeg,
+      &ImageOptions {
+        strip_exif: false,
+        optimize: false,
+        ..options
+      },
+    )
+    .unwrap();
+    assert_eq!(res.original, upright);
+    assert_eq!(res.optimized, None);
+  }
+}
@@ -0,0 +1,265 @@
+use super::cmd::Response;
+use serde_json::{json, Value};
+use std::{
+  collections::VecDeque,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Condvar, Mutex,
+  },
+  thread,
+};
+
+/*
+ * queue of print and export jobs, run one by one on a worker thread
+ * so that they never clobber the hidden window or each other's files.
+ * a command pushes its job and returns the id at once, then the worker emits to the main window
+ * - "job_progress" => JobProgress
+ * - "job_done" => Response with the id
+ * - "job_failed" => Response with the id, also when the job is cancelled
+ * - the event of a task's own result with the id, sent before "job_done", e.g. "link_report" of check_links
+ *
+ * cancel_job drops a queued job, or marks a running one so that it stops at its next check.
+ * the release profile aborts on panic, which takes the app down with the job, so a task must return its failures.
+ */
+
+pub type JobId = u64;
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct JobProgress {
+  pub id: JobId,
+  pub done: usize,
+  pub total: usize,
+  pub message: String,
+}
+
+type Emit = Arc<dyn Fn(&str, Value) + Send + Sync>;
+type Task = Box<dyn FnOnce(&Job) -> Result<Response, Response> + Send>;
+
+/*
+ * handed to a running task.
+ */
+pub struct Job {
+  id: JobId,
+  cancelled: Arc<AtomicBool>,
+  emit: Emit,
+}
+impl Job {
+  pub fn progress<T: ToString>(&self, done: usize, total: usize, message: T) {
+    let progress = JobProgress {
+      id: self.id,
+      done,
+      total,
+      message: message.to_string(),
+    };
+    (self.emit)("job_progress", json!(progress));
+  }
+
+  /*
+   * a result which is more than the message of a Response.
+   */
+  pub fn report<T: serde::Serialize>(&self, event: &str, report: &T) {
+    let mut payload = json!(report);
+    payload["id"] = json!(self.id);
+    (self.emit)(event, payload);
+  }
+
+  pub fn is_cancelled(&self) -> bool {
+    self.cancelled.load(Ordering::SeqCst)
+  }
+
+  /*
+   * for `?` between the steps of a task.
+   */
+  pub fn check(&self) -> Result<(), Response> {
+    if self.is_cancelled() {
+      Err(cancelled())
+    } else {
+      Ok(())
+    }
+  }
+}
+
+fn cancelled() -> Response {
+  Response::client_error("job cancelled")
+}
+
+fn result_payload(id: JobId, res: &Response) -> Value {
+  let mut payload = json!(res);
+  payload["id"] = json!(id);
+  payload
+}
+
+struct Inner {
+  next_id: JobId,
+  queue: VecDeque<(JobId, Task)>,
+  running: Option<(JobId, Arc<AtomicBool>)>,
+}
+
+pub struct Jobs {
+  inner: Arc<(Mutex<Inner>, Condvar)>,
+  emit: Emit,
+}
+impl Jobs {
+  /*
+   * start the worker. emit sends an event with its payload to the frontend.
+   */
+  pub fn start<E: Fn(&str, Value) + Send + Sync + 'static>(emit: E) -> Jobs {
+    let jobs = Jobs {
+      inner: Arc::new((
+        Mutex::new(Inner {
+          next_id: 1,
+          queue: VecDeque::new(),
+          running: None,
+        }),
+        Condvar::new(),
+      )),
+      emit: Arc::new(emit),
+    };
+    let inner = jobs.inner.clone();
+    let emit = jobs.emit.clone();
+    thread::spawn(move || work(inner, emit));
+    jobs
+  }
+
+  pub fn push<F>(&self, task: F) -> Result<JobId, Response>
+  where
+    F: FnOnce(&Job) -> Result<Response, Response> + Send + 'static,
+  {
+    let (lock, cond) = &*self.inner;
+    let mut inner = lock.lock().map_err(Response::process_error)?;
+    let id = inner.next_id;
+    inner.next_id += 1;
+    inner.queue.push_back((id, Box::new(task)));
+    cond.notify_one();
+    Ok(id)
+  }
+
+  /*
+   * returns false if the job is already finished or unknown.
+   */
+  pub fn cancel(&self, id: JobId) -> Result<bool, Response> {
+    let (lock, _) = &*self.inner;
+    let mut inner = lock.lock().map_err(Response::process_error)?;
+    if let Some(i) = inner.queue.iter().position(|(x, _)| *x == id) {
+      inner.queue.remove(i);
+      drop(inner);
+      (self.emit)("job_failed", result_payload(id, &cancelled()));
+      return Ok(true);
+    }
+    match &inner.running {
+      Some((x, flag)) if *x == id => {
+        flag.store(true, Ordering::SeqCst);
+        Ok(true)
+      }
+      _ => Ok(false),
+    }
+  }
+}
+
+fn work(inner: Arc<(Mutex<Inner>, Condvar)>, emit: Emit) {
+  let (lock, cond) = &*inner;
+  loop {
+    let (job, task) = {
+      let mut guard = match lock.lock() {
+        Ok(guard) => guard,
+        Err(_) => return,
+      };
+      while guard.queue.is_empty() {
+        guard = match cond.wait(guard) {
+          Ok(guard) => guard,
+          Err(_) => return,
+        };
+      }
+      let (id, task) = guard.queue.pop_front().unwrap();
+      let cancelled = Arc::new(AtomicBool::new(false));
+      guard.running = Some((id, cancelled.clone()));
+      let job = Job {
+        id,
+        cancelled,
+        emit: emit.clone(),
+      };
+      (job, task)
+    };
+
+    let res = task(&job);
+    // finished before it is notified, so that it can't be cancelled any more
+    if let Ok(mut guard) = lock.lock() {
+      guard.running = None;
+    }
+    match res {
+      Ok(_) if job.is_cancelled() => emit("job_failed", result_payload(job.id, &cancelled())),
+      Ok(res) => emit("job_done", result_payload(job.id, &res)),
+      Err(err) => emit("job_failed", result_payload(job.id, &err)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::{
+    sync::mpsc::{self, Receiver},
+    time::Duration,
+  };
+
+  fn recorder() -> (Jobs, Receiver<(String, Value)>) {
+    let (tx, rx) = mpsc::channel();
+    let tx = Mutex::new(tx);
+    let jobs = Jobs::start(move |event, payload| {
+      tx.lock()
+        .unwrap()
+        .send((event.to_string(), payload))
+        .unwrap();
+    });
+    (jobs, rx)
+  }
+
+  fn next(rx: &Receiver<(String, Value)>) -> (String, JobId) {
+    let (event, payload) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    (event, payload["id"].as_u64().unwrap())
+  }
+
+  #[test]
+  fn job_queue_test() {
+    let (jobs, rx) = recorder();
+    let (gate, wait) = mpsc::channel::<()>();
+    let wait = Mutex::new(wait);
+
+    // the first job blocks the worker until the gate opens
+    let first = jobs
+      .push(move |job| {
+        job.progress(0, 1, "waiting");
+        wait.lock().unwrap().recv().unwrap();
+        job.check()?;
+        Ok(Response::new("first"))
+      })
+      .unwrap();
+    let second = jobs.push(|_| Ok(Response::new("second"))).unwrap();
+    let third = jobs
+      .push(|_| Err(Response::process_error("third")))
+      .unwrap();
+
+    assert_eq!(next(&rx), (String::from("job_progress"), first));
+    assert!(jobs.cancel(second).unwrap());
+    assert_eq!(next(&rx), (String::from("job_failed"), second));
+    assert!(jobs.cancel(first).unwrap());
+    gate.send(()).unwrap();
+    assert_eq!(next(&rx), (String::from("job_failed"), first));
+    assert_eq!(next(&rx), (String::from("job_failed"), third));
+
+    let fourth = jobs
+      .push(|job| {
+        job.report("fourth_report", &json!({ "found": 4 }));
+        Ok(Response::new("fourth"))
+      })
+      .unwrap();
+    let (event, payload) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(event, "fourth_report");
+    assert_eq!(payload, json!({ "found": 4, "id": fourth }));
+    let (event, payload) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(event, "job_done");
+    assert_eq!(payload["id"], json!(fourth));
+    assert_eq!(payload["message"], json!("fourth"));
+    assert!(!jobs.cancel(fourth).unwrap());
+  }
+}
@@ -0,0 +1,431 @@
+use super::{
+  constants::{PREAMBLE, TEMPLATES},
+  macros::Macro,
+  model::Meta,
+  render::{self, Math, Segment},
+  standalone,
+};
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Parser, Tag};
+use std::{
+  collections::HashMap,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+/*
+ * standalone .tex export of a document.
+ * - headings up to the third level are numbered sections, the deeper ones paragraphs.
+ * - math is passed through as it was written, in `$...$` or `\[...\]`. environments such as align stand alone.
+ * - local images are included by their absolute paths. remote ones can't be, so they are left as urls and reported as missing.
+ * - diagrams are left as their source in verbatim.
+ * - the macros of the document and the setting are defined by `\renewcommand`, instead of expanded as the other exports do.
+ *
+ * the preamble is `.templates/preamble.tex` in the target directory if it exists, otherwise the builtin one.
+ * it should load amsmath, amssymb, graphicx, ulem and hyperref, which the body uses.
+ */
+
+static BUILTIN_PREAMBLE: &str = include_str!("../assets/preamble.tex");
+// marks a footnote reference until the definitions are known
+static NOTE_MARK: char = '\u{E001}';
+static SECTIONS: [&str; 6] = [
+  "section",
+  "subsection",
+  "subsubsection",
+  "paragraph",
+  "subparagraph",
+  "subparagraph",
+];
+static COUNTERS: [&str; 4] = ["enumi", "enumii", "enumiii", "enumiv"];
+// display environments which can't be put in `\[...\]`
+static DISPLAY_ENVS: [&str; 8] = [
+  "equation",
+  "equation*",
+  "align",
+  "align*",
+  "gather",
+  "gather*",
+  "multline",
+  "multline*",
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Latex {
+  pub tex: String,
+  pub missing: Vec<String>, //image sources which couldn't be included
+}
+
+pub fn escape_latex(src: &str) -> String {
+  let mut res = String::with_capacity(src.len());
+  for c in src.chars() {
+    match c {
+      '\\' => res.push_str("\\textbackslash{}"),
+      '{' | '}' | '$' | '&' | '#' | '%' | '_' => {
+        res.push('\\');
+        res.push(c);
+      }
+      '^' => res.push_str("\\^{}"),
+      '~' => res.push_str("\\~{}"),
+      c => res.push(c),
+    }
+  }
+  res
+}
+
+fn escape_url(url: &str) -> String {
+  url
+    .replace('\\', "/")
+    .replace('%', "\\%")
+    .replace('#', "\\#")
+}
+
+fn math_to_latex(math: &Math) -> String {
+  let src = math.src.trim();
+  if !math.display {
+    return format!("${}$", src);
+  }
+  let stands_alone = DISPLAY_ENVS
+    .iter()
+    .any(|env| src.starts_with(&format!("\\begin{{{}}}", env)));
+  if stands_alone {
+    format!("\n{}\n", src)
+  } else {
+    format!("\n\\[\n{}\n\\]\n", src)
+  }
+}
+
+fn column(alignment: &Alignment) -> char {
+  match alignment {
+    Alignment::Center => 'c',
+    Alignment::Right => 'r',
+    _ => 'l',
+  }
+}
+
+/*
+ * definitions of the merged macros -- see macros.rs
+ * each is provided first, since the names such as `\det` or `\R` may be defined by latex or the preamble,
+ * where `\newcommand` fails, and may not, where `\renewcommand` alone fails.
+ */
+pub fn newcommands(macros: &[Macro]) -> String {
+  macros
+    .iter()
+    .map(|m| {
+      let expansion = m.expansion.replace('\n', " ");
+      let arity = match m.arity {
+        0 => String::new(),
+        n => format!("[{}]", n),
+      };
+      format!(
+        "\\providecommand{{\\{0}}}{{}}\\renewcommand{{\\{0}}}{1}{{{2}}}\n",
+        m.name, arity, expansion
+      )
+    })
+    .collect()
+}
+
+pub fn preamble(target_dir: &Path) -> io::Result<String> {
+  let path = target_dir.join(TEMPLATES).join(PREAMBLE);
+  if path.is_file() {
+    fs::read_to_string(path)
+  } else {
+    Ok(BUILTIN_PREAMBLE.to_string())
+  }
+}
+
+struct Writer<'a, G: Fn(&str) -> Option<String>> {
+  maths: &'a [Math],
+  image: G,
+  // the innermost is the last. a footnote definition is written into its own buffer
+  buffers: Vec<String>,
+  notes: HashMap<String, String>,
+  missing: Vec<String>,
+  lists: Vec<bool>, //ordered or not
+  cell: usize,
+  in_code: bool,
+  in_image: usize,
+}
+
+impl<'a, G: Fn(&str) -> Option<String>> Writer<'a, G> {
+  fn out(&mut self) -> &mut String {
+    self.buffers.last_mut().unwrap()
+  }
+
+  fn push(&mut self, s: &str) {
+    self.out().push_str(s);
+  }
+
+  fn text(&mut self, text: &str) {
+    if self.in_code {
+      return self.push(text);
+    }
+    let maths = self.maths;
+    let res = render::split_math(text, maths)
+      .into_iter()
+      .map(|segment| match segment {
+        Segment::Text(text) => escape_latex(text),
+        Segment::Math(math) => math_to_latex(math),
+      })
+      .collect::<String>();
+    self.push(&res);
+  }
+
+  fn start(&mut self, tag: Tag) {
+    match tag {
+      Tag::Paragraph => (),
+      Tag::Heading(level) => {
+        let section = SECTIONS[(level as usize).clamp(1, 6) - 1];
+        self.push(&format!("\\{}{{", section));
+      }
+      Tag::BlockQuote => self.push("\\begin{quote}\n"),
+      Tag::CodeBlock(kind) => {
+        if let CodeBlockKind::Fenced(lang) = kind {
+          if render::is_diagram(&lang) {
+            self.push(&format!("% {} diagram\n", lang));
+          }
+        }
+        self.in_code = true;
+        self.push("\\begin{verbatim}\n");
+      }
+      Tag::List(start) => {
+        match start {
+          Some(n) => {
+            let depth = self.lists.iter().filter(|&&ordered| ordered).count();
+            self.push("\\begin{enumerate}\n");
+            if let Some(counter) = COUNTERS.get(depth).filter(|_| n != 1) {
+              self.push(&format!(
+                "\\setcounter{{{}}}{{{}}}\n",
+                counter,
+                n.saturating_sub(1)
+              ));
+            }
+          }
+          None => self.push("\\begin{itemize}\n"),
+        }
+        self.lists.push(start.is_some());
+      }
+      Tag::Item => self.push("\\item "),
+      Tag::FootnoteDefinition(_) => self.buffers.push(String::new()),
+      Tag::Table(alignments) => {
+        let columns = alignments.iter().map(column).collect::<String>();
+        self.push(&format!(
+          "\\begin{{center}}\n\\begin{{tabular}}{{{}}}\n\\hline\n",
+          columns
+        ));
+      }
+      Tag::TableHead | Tag::TableRow => self.cell = 0,
+      Tag::TableCell => {
+        if 0 < self.cell {
+          self.push(" & ");
+        }
+        self.cell += 1;
+      }
+      Tag::Emphasis => self.push("\\emph{"),
+      Tag::Strong => self.push("\\textbf{"),
+      Tag::Strikethrough => self.push("\\sout{"),
+      Tag::Link(_, dest, _) => self.push(&format!("\\href{{{}}}{{", escape_url(&dest))),
+      Tag::Image(_, dest, _) => {
+        self.in_image += 1;
+        match (self.image)(&dest) {
+          Some(path) => self.push(&format!("\\includegraphics{{{}}}", path)),
+          None => {
+            self.missing.push(dest.to_string());
+            self.push(&format!("\\url{{{}}}", escape_url(&dest)));
+          }
+        }
+      }
+    }
+  }
+
+  fn end(&mut self, tag: Tag) {
+    match tag {
+      Tag::Paragraph => self.push("\n\n"),
+      Tag::Heading(_) => self.push("}\n\n"),
+      Tag::BlockQuote => self.push("\\end{quote}\n\n"),
+      Tag::CodeBlock(_) => {
+        self.in_code = false;
+        if !self.out().ends_with('\n') {
+          self.push("\n");
+        }
+        self.push("\\end{verbatim}\n\n");
+      }
+      Tag::List(start) => {
+        self.lists.pop();
+        match start {
+          Some(_) => self.push("\\end{enumerate}\n\n"),
+          None => self.push("\\end{itemize}\n\n"),
+        }
+      }
+      Tag::Item => {
+        if !self.out().ends_with('\n') {
+          self.push("\n");
+        }
+      }
+      Tag::FootnoteDefinition(label) => {
+        let note = self.buffers.pop().unwrap_or_default();
+        self
+          .notes
+          .insert(label.to_string(), note.trim().to_string());
+      }
+      Tag::Table(_) => self.push("\\end{tabular}\n\\end{center}\n\n"),
+      Tag::TableHead => self.push(" \\\\\n\\hline\n"),
+      Tag::TableRow => self.push(" \\\\\n"),
+      Tag::TableCell => (),
+      Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link(_, _, _) => self.push("}"),
+      Tag::Image(_, _, _) => self.in_image -= 1,
+    }
+  }
+
+  fn event(&mut self, event: Event) {
+    match event {
+      Event::Start(Tag::Image(kind, dest, title)) => self.start(Tag::Image(kind, dest, title)),
+      Event::End(Tag::Image(kind, dest, title)) => self.end(Tag::Image(kind, dest, title)),
+      // alt text of an image
+      _ if 0 < self.in_image => (),
+      Event::Start(tag) => self.start(tag),
+      Event::End(tag) => self.end(tag),
+      Event::Text(text) => self.text(&text),
+      Event::Code(code) => self.push(&format!("\\texttt{{{}}}", escape_latex(&code))),
+      Event::Html(_) => (),
+      Event::FootnoteReference(label) => {
+        self.push(&format!("{}{}{}", NOTE_MARK, label, NOTE_MARK));
+      }
+      Event::SoftBreak => self.push("\n"),
+      Event::HardBreak => self.push("\\\\\n"),
+      Event::Rule => self.push("\\noindent\\rule{\\linewidth}{0.4pt}\n\n"),
+      Event::TaskListMarker(checked) => {
+        let marker = if checked {
+          "$\\boxtimes$"
+        } else {
+          "$\\square$"
+        };
+        let out = self.out();
+        if out.ends_with("\\item ") {
+          out.truncate(out.len() - 1);
+          out.push_str(&format!("[{}] ", marker));
+        } else {
+          out.push_str(&format!("{} ", marker));
+        }
+      }
+    }
+  }
+
+  /*
+   * footnote references are replaced once every definition is read, since they may come after.
+   */
+  fn finish(mut self) -> (String, Vec<String>) {
+    let body = self.buffers.swap_remove(0);
+    let res = body
+      .split(NOTE_MARK)
+      .enumerate()
+      .map(|(i, part)| {
+        if i % 2 == 0 {
+          part.to_string()
+        } else {
+          match self.notes.get(part) {
+            Some(note) => format!("\\footnote{{{}}}", note),
+            None => format!("[{}]", escape_latex(part)),
+          }
+        }
+      })
+      .collect::<String>();
+    (res.trim_end().to_string(), self.missing)
+  }
+}
+
+/*
+ * the body of a document in latex, and the image sources which `image` couldn't resolve into local paths.
+ * front matter should be stripped before.
+ */
+pub fn body<G: Fn(&str) -> Option<String>>(md: &str, image: G) -> (String, Vec<String>) {
+  let (md, maths) = render::extract(md);
+  let mut writer = Writer {
+    maths: &maths,
+    image,
+    buffers: vec![String::new()],
+    notes: HashMap::new(),
+    missing: vec![],
+    lists: vec![],
+    cell: 0,
+    in_code: false,
+    in_image: 0,
+  };
+  for event in Parser::new_ext(&md, render::options()) {
+    writer.event(event);
+  }
+  writer.finish()
+}
+
+/*
+ * relative image paths are looked up in dirs in order, e.g. the directory of the body then the target directory.
+ */
+pub fn export(
+  meta: &Meta,
+  md: &str,
+  macros: &[Macro],
+  dirs: &[PathBuf],
+  target_dir: &Path,
+) -> io::Result<Latex> {
+  let (body, missing) = body(md, |src| {
+    standalone::local_file(src, dirs).map(|path| path.to_string_lossy().replace('\\', "/"))
+  });
+  let tex = format!(
+    "{}\n{}\n\\title{{{}}}\n\\author{{{}}}\n\\date{{{}}}\n\n\\begin{{document}}\n\\maketitle\n\n{}\n\n\\end{{document}}\n",
+    preamble(target_dir)?.trim_end(),
+    newcommands(macros),
+    escape_latex(meta.get_filename()),
+    escape_latex(meta.get_author()),
+    escape_latex(meta.get_created_at()),
+    body
+  );
+  Ok(Latex { tex, missing })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::macros;
+
+  #[test]
+  fn latex_body_test() {
+    let (tex, missing) = body(
+      "# Intro 50%\n\n### Case $n_1$\n\n- [x] done\n- item\n\n3. three\n4. four\n\n| a | b |\n|:-:|--:|\n| 1 | _2_ |\n\nnote[^1] with `a_b`\n\n$$\n\\sum_i x_i\n$$\n\n$$\\begin{align}a &= b\\end{align}$$\n\n```mermaid\ngraph TD\n```\n\n![dot](dot.png) ![web](https://example.com/a.png)\n\n[^1]: the **note**",
+      |src| Some(format!("/abs/{}", src)).filter(|_| !src.contains("://")),
+    );
+    assert!(tex.starts_with("\\section{Intro 50\\%}\n\n\\subsubsection{Case $n_1$}"));
+    assert!(
+      tex.contains("\\begin{itemize}\n\\item[$\\boxtimes$] done\n\\item item\n\\end{itemize}")
+    );
+    assert!(tex.contains("\\begin{enumerate}\n\\setcounter{enumi}{2}\n\\item three"));
+    assert!(tex.contains(
+      "\\begin{tabular}{cr}\n\\hline\na & b \\\\\n\\hline\n1 & \\emph{2} \\\\\n\\end{tabular}"
+    ));
+    assert!(tex.contains("note\\footnote{the \\textbf{note}} with \\texttt{a\\_b}"));
+    assert!(tex.contains("\\[\n\\sum_i x_i\n\\]"));
+    assert!(tex.contains("\n\\begin{align}a &= b\\end{align}\n"));
+    assert!(!tex.contains("\\[\n\\begin{align}"));
+    assert!(tex.contains("% mermaid diagram\n\\begin{verbatim}\ngraph TD\n\\end{verbatim}"));
+    assert!(tex.contains("\\includegraphics{/abs/dot.png} \\url{https://example.com/a.png}"));
+    assert!(!tex.contains("the \\textbf{note}\n"));
+    assert_eq!(missing, vec![String::from("https://example.com/a.png")]);
+  }
+
+  #[test]
+  fn latex_macros_test() {
+    let mut global = HashMap::new();
+    global.insert(String::from("\\R"), String::from("\\mathbb{R}"));
+    global.insert(String::from("det"), String::from("\\operatorname{Det}"));
+    let mut shortcut = HashMap::new();
+    shortcut.insert(
+      String::from("pair"),
+      String::from("\\langle #1, #2 \\rangle"),
+    );
+    assert_eq!(
+      newcommands(&macros::merge(&global, &shortcut)),
+      concat!(
+        "\\providecommand{\\R}{}\\renewcommand{\\R}{\\mathbb{R}}\n",
+        "\\providecommand{\\det}{}\\renewcommand{\\det}{\\operatorname{Det}}\n",
+        "\\providecommand{\\pair}{}\\renewcommand{\\pair}[2]{\\langle #1, #2 \\rangle}\n"
+      )
+    );
+  }
+}
@@ -0,0 +1,314 @@
+use super::{
+  constants::{TARGET_EXTENTION, TIME_FORMAT},
+  model::Meta,
+};
+use chrono::{offset::TimeZone, Local};
+use std::{
+  collections::HashSet,
+  fs, io,
+  path::{Path, PathBuf},
+};
+use unicode_normalization::UnicodeNormalization;
+
+/*
+ * how the bodies are placed in the target directory.
+ * the hashed layout is the original one, `sha256(filename).md` directly under the target directory.
+ * the readable ones use slugified titles, optionally grouped into per-tag or per-date (yyyy/mm) subfolders.
+ */
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Layout {
+  #[serde(rename = "hashed")]
+  Hashed,
+  #[serde(rename = "readable")]
+  Readable,
+  #[serde(rename = "readable_by_tag")]
+  ReadableByTag,
+  #[serde(rename = "readable_by_date")]
+  ReadableByDate,
+}
+impl Default for Layout {
+  fn default() -> Self {
+    Layout::Hashed
+  }
+}
+
+static UNTITLED: &str = "untitled";
+static MAX_SLUG_BYTES: usize = 120;
+static RESERVED: [&str; 22] = [
+  "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+  "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/*
+ * make a file name safe on every platform from a title.
+ * the title is NFKC normalized, so full-width alphanumerics become ascii and half-width kana become full-width,
+ * and letters of any script including kanji and kana are kept as they are.
+ * the others are replaced with '-'.
+ */
+pub fn slugify(title: &str) -> String {
+  let mut slug = String::new();
+  for c in title.nfkc() {
+    if c.is_alphanumeric() || c == '_' {
+      slug.extend(c.to_lowercase());
+    } else if !slug.ends_with('-') {
+      slug.push('-');
+    }
+  }
+  let mut slug = slug.trim_matches('-').to_string();
+
+  if MAX_SLUG_BYTES < slug.len() {
+    let mut end = MAX_SLUG_BYTES;
+    while !slug.is_char_boundary(end) {
+      end -= 1;
+    }
+    slug.truncate(end);
+    slug = slug.trim_end_matches('-').to_string();
+  }
+  if slug.is_empty() {
+    slug = String::from(UNTITLED);
+  }
+  if RESERVED.contains(&slug.as_str()) {
+    slug.push('-');
+  }
+  slug
+}
+
+fn folder(meta: &Meta, layout: Layout) -> Option<String> {
+  match layout {
+    Layout::Hashed | Layout::Readable => None,
+    Layout::ReadableByTag => Some(match meta.get_tags().first() {
+      Some(tag) => slugify(tag),
+      None => String::from(UNTITLED),
+    }),
+    Layout::ReadableByDate => Local
+      .datetime_from_str(meta.get_created_at(), TIME_FORMAT)
+      .map(|created| created.format("%Y/%m").to_string())
+      .ok()
+      .or_else(|| Some(Local::now().format("%Y/%m").to_string())),
+  }
+}
+
+fn first_free<F: Fn(&str) -> bool>(meta: &Meta, layout: Layout, is_taken: F) -> String {
+  let dir = folder(meta, layout)
+    .map(|d| format!("{}/", d))
+    .unwrap_or_default();
+  let slug = slugify(meta.get_filename());
+  (1..)
+    .map(|n| match n {
+      1 => format!("{}{}{}", dir, slug, TARGET_EXTENTION),
+      n => format!("{}{}-{}{}", dir, slug, n, TARGET_EXTENTION),
+    })
+    .find(|candidate| !is_taken(candidate))
+    .unwrap()
+}
+
+/*
+ * decide where the body of a new document is placed, relative to the target directory with '/' separators.
+ * None means the hashed name, which is what Meta::body_path falls back to.
+ * `taken` holds the lower cased paths used by the other documents, for case-insensitive file systems.
+ */
+pub fn assign(
+  meta: &Meta,
+  layout: Layout,
+  taken: &HashSet<String>,
+  target_dir: &Path,
+) -> Option<String> {
+  if layout == Layout::Hashed {
+    return None;
+  }
+  Some(first_free(meta, layout, |candidate| {
+    taken.contains(&candidate.to_lowercase()) || target_dir.join(candidate).exists()
+  }))
+}
+
+/*
+ * list the bodies under the target directory recursively, relative to it with '/' separators.
+ */
+pub fn list_bodies(target_dir: &Path) -> io::Result<Vec<String>> {
+  fn walk(dir: &Path, prefix: &str, res: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) if !name.starts_with('.') => name.to_string(),
+        _ => continue,
+      };
+      if path.is_dir() {
+        walk(&path, &format!("{}{}/", prefix, name), res)?;
+      } else if name.ends_with(TARGET_EXTENTION) {
+        res.push(format!("{}{}", prefix, name));
+      }
+    }
+    Ok(())
+  }
+  let mut res = vec![];
+  walk(target_dir, "", &mut res)?;
+  Ok(res)
+}
+
+/*
+ * a planned move of a body from one layout to another, relative to the target directory.
+ */
+#[derive(Debug, PartialEq, Clone)]
+pub struct Relocation {
+  pub from: String,
+  pub to: Option<String>,
+}
+impl Relocation {
+  pub fn to_path(&self, meta: &Meta) -> String {
+    self
+      .to
+      .clone()
+      .unwrap_or_else(|| meta.get_hashed_filename())
+  }
+}
+
+pub fn plan(metas: &[Meta], layout: Layout, target_dir: &Path) -> Vec<Relocation> {
+  /*
+   * the current paths are free to be reused, since every file is moved anyway.
+   * existence on disk is checked only for the files not managed by the index.
+   */
+  let managed = metas.iter().map(|m| m.body_path()).collect::<HashSet<_>>();
+  let mut taken = HashSet::new();
+  let mut res = vec![];
+  for meta in metas {
+    let to = if layout == Layout::Hashed {
+      None
+    } else {
+      Some(first_free(meta, layout, |candidate| {
+        taken.contains(&candidate.to_lowercase())
+          || (!managed.contains(candidate) && target_dir.join(candidate).exists())
+      }))
+    };
+    let relocation = Relocation {
+      from: meta.body_path(),
+      to,
+    };
+    taken.insert(relocation.to_path(meta).to_lowercase());
+    res.push(relocation);
+  }
+  res
+}
+
+fn remove_empty_dirs(dir: &Path, root: &Path) {
+  let mut dir = dir.to_path_buf();
+  while dir != root && fs::remove_dir(&dir).is_ok() {
+    dir = match dir.parent() {
+      Some(parent) => parent.to_path_buf(),
+      None => break,
+    };
+  }
+}
+
+/*
+ * move the bodies as planned.
+ * files are first renamed to temporary names, so that swapping paths between documents never overwrites one.
+ * on failure, every file already moved is put back.
+ */
+pub fn relocate(metas: &[Meta], plan: &[Relocation], target_dir: &Path) -> io::Result<()> {
+  let tmp = |i: usize| target_dir.join(format!(".relocating-{}{}", i, TARGET_EXTENTION));
+  let mut staged: Vec<(usize, PathBuf)> = vec![];
+  let mut placed: Vec<(usize, PathBuf)> = vec![];
+
+  let restore = |staged: &[(usize, PathBuf)], placed: &[(usize, PathBuf)]| {
+    for (i, to) in placed.iter().rev() {
+      if let Err(err) = fs::rename(to, tmp(*i)) {
+        println!("WARNING: failed to restore {:?}\n{}", to, err.to_string());
+      }
+    }
+    for (i, from) in staged.iter().rev() {
+      let res = from
+        .parent()
+        .map(fs::create_dir_all)
+        .unwrap_or(Ok(()))
+        .and_then(|_| fs::rename(tmp(*i), from));
+      if let Err(err) = res {
+        println!("WARNING: failed to restore {:?}\n{}", from, err.to_string());
+      }
+    }
+  };
+
+  for (i, relocation) in plan.iter().enumerate() {
+    let from = target_dir.join(&relocation.from);
+    if !from.is_file() {
+      continue;
+    }
+    if let Err(err) = fs::rename(&from, tmp(i)) {
+      restore(&staged, &placed);
+      return Err(err);
+    }
+    staged.push((i, from));
+  }
+
+  for (i, from) in staged.iter() {
+    let to = target_dir.join(plan[*i].to_path(&metas[*i]));
+    let res = to
+      .parent()
+      .map(fs::create_dir_all)
+      .unwrap_or(Ok(()))
+      .and_then(|_| fs::rename(tmp(*i), &to));
+    if let Err(err) = res {
+      restore(&staged, &placed);
+      return Err(err);
+    }
+    placed.push((*i, to));
+    if let Some(parent) = from.parent() {
+      remove_empty_dirs(parent, target_dir);
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::{get_random_key, Setup};
+
+  #[test]
+  fn slugify_test() {
+    assert_eq!(slugify("Hello, World!"), "hello-world");
+    assert_eq!(slugify("線形代数 第3回: 固有値"), "線形代数-第3回-固有値");
+    assert_eq!(slugify("ＡＢＣ　ｶﾀｶﾅ"), "abc-カタカナ");
+    assert_eq!(slugify("../../etc/passwd"), "etc-passwd");
+    assert_eq!(slugify("???"), UNTITLED);
+    assert_eq!(slugify("CON"), "con-");
+    assert!(slugify(&"あ".repeat(100)).len() <= MAX_SLUG_BYTES);
+  }
+
+  #[test]
+  fn relocate_round_trip_test() {
+    let target_dir = std::env::temp_dir().join(format!("notex-layout-{}", get_random_key()));
+    fs::create_dir_all(&target_dir).unwrap();
+    let mut setup = Setup::init();
+    let first = setup.get_meta().clone();
+    setup.move_filename_to("test-file");
+    let second = setup.get_meta().clone();
+    let mut metas = vec![first, second];
+    for meta in metas.iter() {
+      fs::write(target_dir.join(meta.body_path()), meta.get_filename()).unwrap();
+    }
+
+    let readable = plan(&metas, Layout::ReadableByTag, &target_dir);
+    assert_eq!(readable[0].to, Some(String::from("t1/test-file.md")));
+    assert_eq!(readable[1].to, Some(String::from("t1/test-file-2.md")));
+    relocate(&metas, &readable, &target_dir).unwrap();
+    for (meta, relocation) in metas.iter_mut().zip(readable.into_iter()) {
+      meta.set_path(relocation.to);
+      assert_eq!(
+        fs::read_to_string(target_dir.join(meta.body_path())).unwrap(),
+        meta.get_filename()
+      );
+    }
+
+    let hashed = plan(&metas, Layout::Hashed, &target_dir);
+    relocate(&metas, &hashed, &target_dir).unwrap();
+    for meta in metas.iter() {
+      assert_eq!(
+        fs::read_to_string(target_dir.join(meta.get_hashed_filename())).unwrap(),
+        meta.get_filename()
+      );
+    }
+    assert!(!target_dir.join("t1").exists());
+
+    fs::remove_dir_all(target_dir).unwrap();
+  }
+}
@@ -1,6 +1,31 @@
+pub mod asset;
+pub mod attachments;
+pub mod check;
 pub mod cmd;
 pub mod constants;
+pub mod daily;
+pub mod doctemplate;
+pub mod epub;
+pub mod frontmatter;
+pub mod graph;
+pub mod images;
+pub mod jobs;
+pub mod latex;
+pub mod layout;
+pub mod links;
+pub mod lock;
+pub mod macros;
+pub mod math;
+pub mod merge;
+pub mod migrate;
 pub mod model;
+pub mod pdf;
+pub mod render;
+pub mod site;
+pub mod standalone;
+pub mod stats;
+pub mod template;
+pub mod watcher;
 
 use std::{
   collections::{HashMap, HashSet},
@@ -31,7 +56,7 @@ impl ToString for Language {
 /**
  * should not have inner setting state if considering multiple running process.
  * get setting by every time with fs.
- * however, currently not considering such pattern and assuming only one process.
+ * currently, the inner state is kept and the edits made outside the app are reloaded by watcher.
  */
 #[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Setting {
@@ -43,6 +68,20 @@ pub struct Setting {
   language: Language,
   autosave: Option<usize>,
   key_bindings: HashMap<String, String>,
+  #[serde(default)]
+  front_matter: frontmatter::FrontMatter,
+  #[serde(default)]
+  layout: layout::Layout,
+  #[serde(default)]
+  site: site::SiteOptions,
+  #[serde(default)]
+  pdf: pdf::PdfOptions,
+  #[serde(default)]
+  images: images::ImageOptions,
+  #[serde(default)]
+  daily: daily::DailyOptions,
+  #[serde(default)]
+  macros: HashMap<String, String>, //math macros of the workspace, under the shortcut of each document -- see macros.rs
   #[serde(skip)]
   is_new: bool,
 }
@@ -54,6 +93,22 @@ impl Setting {
   fn path_to_file(&self, filename: &str) -> PathBuf {
     self.target_dir.join(filename)
   }
+
+  fn lang(&self) -> &'static str {
+    match self.language {
+      Language::English => "en",
+      Language::Japanese => "ja",
+    }
+  }
+
+  fn write_to_conf(&self) -> std::io::Result<()> {
+    let conf = OpenOptions::new()
+      .create(false)
+      .write(true)
+      .truncate(true)
+      .open(conf_path())?;
+    Ok(serde_json::to_writer(conf, self)?)
+  }
 }
 impl Default for Setting {
   fn default() -> Self {
@@ -74,25 +129,62 @@ impl Default for Setting {
         .into_iter()
         .zip(shortcuts.into_iter())
         .collect::<HashMap<String, String>>(),
+      front_matter: frontmatter::FrontMatter::default(),
+      layout: layout::Layout::default(),
+      site: site::SiteOptions::default(),
+      pdf: pdf::PdfOptions::default(),
+      images: images::ImageOptions::default(),
+      daily: daily::DailyOptions::default(),
+      macros: HashMap::new(),
       is_new: true,
     }
   }
 }
 
 pub struct Env(Mutex<Setting>);
+impl Env {
+  /*
+   * for the handlers outside of the commands, e.g. the asset protocol. None if the lock is poisoned.
+   */
+  pub fn target_dir(&self) -> Option<PathBuf> {
+    self.0.lock().ok().map(|setting| setting.target_dir.clone())
+  }
+}
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Memo {
   pub all_tags: HashSet<String>,
   pub page: usize,
 }
+impl Memo {
+  fn from_index() -> std::io::Result<Memo> {
+    let mut memo = Memo {
+      all_tags: HashSet::new(),
+      page: 0usize,
+    };
+    let mut buf = String::new();
+    File::open(index_path())?.read_to_string(&mut buf)?;
+    for line in buf.lines().filter(|line| !line.is_empty()) {
+      let meta = serde_json::from_str::<model::Meta>(line)?;
+      memo.all_tags.extend(meta.get_into_tag());
+      memo.page += 1;
+    }
+    Ok(memo)
+  }
+}
 pub struct Casher(Mutex<Memo>);
 impl Casher {
+  /*
+   * seeded from the index, so that the first change the watcher sees is compared with the index as it was.
+   */
   pub fn new() -> Casher {
-    Casher(Mutex::new(Memo {
-      all_tags: HashSet::new(),
-      page: 0usize,
-    }))
+    Casher(Mutex::new(Memo::from_index().unwrap_or_else(|err| {
+      println!("WARNING: failed to read index\n{}", err.to_string());
+      Memo {
+        all_tags: HashSet::new(),
+        page: 0usize,
+      }
+    })))
   }
 }
 
@@ -123,6 +215,16 @@ pub fn index_path() -> PathBuf {
   root_path().join(constants::INDEX)
 }
 
+pub fn links_path() -> PathBuf {
+  root_path().join(constants::LINKS)
+}
+
+fn read_setting() -> std::io::Result<Setting> {
+  let mut buf = String::new();
+  File::open(conf_path())?.read_to_string(&mut buf)?;
+  Ok(Setting::from_string(&buf)?)
+}
+
 pub fn initialize() -> Env {
   let root = root_path();
   let conf = conf_path();
@@ -0,0 +1,488 @@
+use super::render::{self, anchor, closes_fence, fence_of};
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fs, io,
+  path::Path,
+};
+
+/*
+ * wiki style links between notes, `[[title]]`, `[[title#heading]]` and `[[title|label]]`.
+ * `[[#heading]]` is a heading of the same note. links in code are not links.
+ *
+ * the renderers see them as usual markdown links to the title, which site.rs and epub.rs resolve as before.
+ * the link graph keeps the links and the heading anchors of every note, in links.json next to the index,
+ * so that backlinks and unresolved links are answered without reading the bodies.
+ * it is updated on save, delete and rename, and rebuilt with the index.
+ */
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct WikiLink {
+  pub target: String, //title. empty for the same note
+  pub heading: Option<String>,
+  pub label: Option<String>,
+}
+impl WikiLink {
+  fn parse(inner: &str) -> Option<WikiLink> {
+    let (link, label) = match inner.split_once('|') {
+      Some((link, label)) => (
+        link,
+        Some(label.trim().to_string()).filter(|l| !l.is_empty()),
+      ),
+      None => (inner, None),
+    };
+    let (target, heading) = match link.split_once('#') {
+      Some((target, heading)) => (target, Some(heading.trim().to_string())),
+      None => (link, None),
+    };
+    let target = target.trim().to_string();
+    if target.is_empty() && heading.as_deref().map(str::is_empty).unwrap_or(true) {
+      return None;
+    }
+    Some(WikiLink {
+      target,
+      heading: heading.filter(|h| !h.is_empty()),
+      label,
+    })
+  }
+
+  fn to_source(&self) -> String {
+    let mut res = self.target.clone();
+    if let Some(heading) = &self.heading {
+      res.push('#');
+      res.push_str(heading);
+    }
+    if let Some(label) = &self.label {
+      res.push('|');
+      res.push_str(label);
+    }
+    format!("[[{}]]", res)
+  }
+
+  fn text(&self) -> String {
+    match (&self.label, &self.heading) {
+      (Some(label), _) => label.clone(),
+      (None, Some(heading)) if self.target.is_empty() => heading.clone(),
+      (None, Some(heading)) => format!("{} > {}", self.target, heading),
+      (None, None) => self.target.clone(),
+    }
+  }
+
+  /*
+   * the destination of a markdown link, as site.rs and epub.rs resolve it.
+   */
+  fn destination(&self) -> String {
+    let target = self.target.replace(&['<', '>'][..], "");
+    match &self.heading {
+      Some(heading) => format!("{}#{}", target, anchor(heading)),
+      None => target,
+    }
+  }
+}
+
+/*
+ * the wiki links in the markdown with their byte ranges, outside of fenced and inline code.
+ */
+fn scan(md: &str) -> Vec<(usize, usize, WikiLink)> {
+  let mut res = vec![];
+  let mut fence: Option<(char, usize)> = None;
+  let mut offset = 0;
+  for line in md.split_inclusive('\n') {
+    let start = offset;
+    offset += line.len();
+    match (fence, fence_of(line)) {
+      (None, Some(opened)) => {
+        fence = Some(opened);
+        continue;
+      }
+      (Some(opened), Some(_)) if closes_fence(line, opened) => {
+        fence = None;
+        continue;
+      }
+      (Some(_), _) => continue,
+      _ => (),
+    }
+
+    let bytes = line.as_bytes();
+    let mut in_code = false;
+    let mut i = 0;
+    while i < bytes.len() {
+      match bytes[i] {
+        b'\\' => i += 1,
+        b'`' => in_code = !in_code,
+        b'[' if !in_code && bytes.get(i + 1) == Some(&b'[') => {
+          if let Some(len) = line[i + 2..].find("]]") {
+            let inner = &line[i + 2..i + 2 + len];
+            if !inner.contains(&['[', ']', '\n'][..]) {
+              if let Some(link) = WikiLink::parse(inner) {
+                res.push((start + i, start + i + len + 4, link));
+                i += len + 4;
+                continue;
+              }
+            }
+          }
+        }
+        _ => (),
+      }
+      i += 1;
+    }
+  }
+  res
+}
+
+pub fn parse(md: &str) -> Vec<WikiLink> {
+  scan(md).into_iter().map(|(_, _, link)| link).collect()
+}
+
+fn replace<F: Fn(&WikiLink) -> Option<String>>(md: &str, f: F) -> String {
+  let mut res = String::with_capacity(md.len());
+  let mut last = 0;
+  for (start, end, link) in scan(md) {
+    if let Some(replaced) = f(&link) {
+      res.push_str(&md[last..start]);
+      res.push_str(&replaced);
+      last = end;
+    }
+  }
+  res.push_str(&md[last..]);
+  res
+}
+
+/*
+ * the wiki links as markdown links for the renderers -- see render.rs
+ */
+pub fn to_markdown(md: &str) -> String {
+  replace(md, |link| {
+    let text = link
+      .text()
+      .replace('\\', "\\\\")
+      .replace('[', "\\[")
+      .replace(']', "\\]");
+    Some(format!("[{}](<{}>)", text, link.destination()))
+  })
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+  pub links: Vec<WikiLink>,
+  pub anchors: Vec<String>,
+}
+impl Entry {
+  pub fn of(md: &str) -> Entry {
+    Entry {
+      links: parse(md),
+      anchors: render::render(md)
+        .headings
+        .into_iter()
+        .map(|heading| heading.id)
+        .collect(),
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Backlink {
+  pub source: String, //title of the note which links
+  pub heading: Option<String>,
+  pub label: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+#[serde(tag = "reason")]
+pub enum Unresolved {
+  #[serde(rename = "missing_note")]
+  MissingNote { source: String, link: WikiLink },
+  #[serde(rename = "missing_heading")]
+  MissingHeading { source: String, link: WikiLink },
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct LinkGraph {
+  notes: BTreeMap<String, Entry>, //by title
+}
+impl LinkGraph {
+  /*
+   * the graph of the notes, pairs of title and body.
+   */
+  pub fn build(notes: &[(String, String)]) -> LinkGraph {
+    LinkGraph {
+      notes: notes
+        .iter()
+        .map(|(title, md)| (title.clone(), Entry::of(md)))
+        .collect(),
+    }
+  }
+
+  /*
+   * None if the graph is not made yet.
+   */
+  pub fn open(path: &Path) -> io::Result<Option<LinkGraph>> {
+    match fs::read_to_string(path) {
+      Ok(src) => Ok(Some(serde_json::from_str(&src)?)),
+      Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+      Err(err) => Err(err),
+    }
+  }
+
+  pub fn save(&self, path: &Path) -> io::Result<()> {
+    fs::write(path, serde_json::to_string(self)?)
+  }
+
+  pub fn update(&mut self, title: &str, md: &str) {
+    self.notes.insert(title.to_string(), Entry::of(md));
+  }
+
+  pub fn remove(&mut self, title: &str) {
+    self.notes.remove(title);
+  }
+
+  pub fn get(&self, title: &str) -> Option<&Entry> {
+    self.notes.get(title)
+  }
+
+  pub fn titles(&self) -> impl Iterator<Item = &String> {
+    self.notes.keys()
+  }
+
+  /*
+   * the title which the link points to from the source. the exact title first, then ignoring the case.
+   */
+  pub fn resolve<'a>(&'a self, source: &'a str, link: &WikiLink) -> Option<&'a str> {
+    if link.target.is_empty() {
+      return Some(source).filter(|s| self.notes.contains_key(*s));
+    }
+    if let Some((title, _)) = self.notes.get_key_value(&link.target) {
+      return Some(title);
+    }
+    let lower = link.target.to_lowercase();
+    self
+      .notes
+      .keys()
+      .find(|title| title.to_lowercase() == lower)
+      .map(String::as_str)
+  }
+
+  fn has_heading(&self, title: &str, heading: &str) -> bool {
+    let id = anchor(heading);
+    self
+      .notes
+      .get(title)
+      .map(|entry| entry.anchors.iter().any(|a| *a == id || a == heading))
+      .unwrap_or(false)
+  }
+
+  /*
+   * the links to the note from the others, in the order of the sources.
+   */
+  pub fn backlinks(&self, title: &str) -> Vec<Backlink> {
+    let mut res = vec![];
+    for (source, entry) in &self.notes {
+      for link in &entry.links {
+        if source != title && self.resolve(source, link) == Some(title) {
+          res.push(Backlink {
+            source: source.clone(),
+            heading: link.heading.clone(),
+            label: link.label.clone(),
+          });
+        }
+      }
+    }
+    res
+  }
+
+  /*
+   * the links of the note, or of every note if None, which point to no note or no heading.
+   */
+  pub fn unresolved(&self, title: Option<&str>) -> Vec<Unresolved> {
+    let mut res = vec![];
+    for (source, entry) in &self.notes {
+      if title.map(|t| t != source).unwrap_or(false) {
+        continue;
+      }
+      for link in &entry.links {
+        let unresolved = match self.resolve(source, link) {
+          None => Some(Unresolved::MissingNote {
+            source: source.clone(),
+            link: link.clone(),
+          }),
+          Some(target) => match &link.heading {
+            Some(heading) if !self.has_heading(target, heading) => {
+              Some(Unresolved::MissingHeading {
+                source: source.clone(),
+                link: link.clone(),
+              })
+            }
+            _ => None,
+          },
+        };
+        res.extend(unresolved);
+      }
+    }
+    res
+  }
+
+  /*
+   * the other notes which link to the old title, which need `rename_in` on their bodies.
+   * the graph itself is updated when they are saved.
+   */
+  pub fn linking_to(&self, old: &str) -> BTreeSet<String> {
+    self
+      .notes
+      .iter()
+      .filter(|(source, entry)| {
+        *source != old
+          && entry
+            .links
+            .iter()
+            .any(|link| self.resolve(source, link) == Some(old))
+      })
+      .map(|(source, _)| source.clone())
+      .collect()
+  }
+
+  /*
+   * the links of the source to the old title pointed at the new one, with the same heading and label.
+   * the source may be the old title itself, where the links without a title are left as they are.
+   */
+  pub fn rename_in(&self, source: &str, md: &str, old: &str, new: &str) -> String {
+    replace(md, |link| {
+      if !link.target.is_empty() && self.resolve(source, link) == Some(old) {
+        Some(
+          WikiLink {
+            target: new.to_string(),
+            ..link.clone()
+          }
+          .to_source(),
+        )
+      } else {
+        None
+      }
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn notes(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+    entries
+      .iter()
+      .map(|(t, b)| (t.to_string(), b.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn wiki_link_test() {
+    let md = "see [[Linear Algebra#Eigen Values|eigen]] and [[#Intro]].\n\n`[[not a link]]`\n\n```\n[[nor this]]\n```\n\\[[escaped]] [[Plain]]";
+    assert_eq!(
+      parse(md),
+      vec![
+        WikiLink {
+          target: String::from("Linear Algebra"),
+          heading: Some(String::from("Eigen Values")),
+          label: Some(String::from("eigen")),
+        },
+        WikiLink {
+          target: String::new(),
+          heading: Some(String::from("Intro")),
+          label: None,
+        },
+        WikiLink {
+          target: String::from("Plain"),
+          heading: None,
+          label: None,
+        },
+      ]
+    );
+    assert_eq!(
+      parse("```md\n```rust\n[[inside]]\n```\n[[After]]"),
+      vec![WikiLink {
+        target: String::from("After"),
+        heading: None,
+        label: None,
+      }]
+    );
+    assert_eq!(
+      to_markdown("[[Linear Algebra#Eigen Values]] [[#Intro|top]]"),
+      "[Linear Algebra > Eigen Values](<Linear Algebra#eigen-values>) [top](<#intro>)"
+    );
+    let graph = LinkGraph::build(&notes(&[("Linear Algebra", ""), ("Source", md)]));
+    assert_eq!(
+      graph.rename_in("Source", md, "Linear Algebra", "Matrices"),
+      md.replace("[[Linear Algebra#", "[[Matrices#")
+    );
+    assert!(render::render(&to_markdown("[[Plain]]"))
+      .html
+      .contains("<a href=\"Plain\">Plain</a>"));
+  }
+
+  #[test]
+  fn link_graph_test() {
+    let mut graph = LinkGraph::build(&notes(&[
+      (
+        "Matrices",
+        "# Eigen Values\n\n[[Vectors]] [[matrices#Rank]]",
+      ),
+      ("Vectors", "[[Matrices#Eigen Values]] [[Missing]] [[#Norm]]"),
+      ("Notes", "[[Matrices|the matrix note]] [[vectors]]"),
+    ]));
+    assert_eq!(
+      graph.backlinks("Matrices"),
+      vec![
+        Backlink {
+          source: String::from("Notes"),
+          heading: None,
+          label: Some(String::from("the matrix note")),
+        },
+        Backlink {
+          source: String::from("Vectors"),
+          heading: Some(String::from("Eigen Values")),
+          label: None,
+        },
+      ]
+    );
+    assert_eq!(graph.backlinks("Vectors").len(), 2);
+
+    let unresolved = graph.unresolved(None);
+    assert_eq!(unresolved.len(), 3);
+    assert!(matches!(
+      &unresolved[0],
+      Unresolved::MissingHeading { source, link } if source == "Matrices" && link.heading.as_deref() == Some("Rank")
+    ));
+    assert!(matches!(
+      &unresolved[1],
+      Unresolved::MissingNote { link, .. } if link.target == "Missing"
+    ));
+    assert_eq!(graph.unresolved(Some("Notes")), vec![]);
+
+    assert_eq!(
+      graph.linking_to("Vectors"),
+      vec![String::from("Matrices"), String::from("Notes")]
+        .into_iter()
+        .collect()
+    );
+    assert_eq!(
+      graph.rename_in(
+        "Notes",
+        "[[vectors]] [[Vectors#Norm|norm]]",
+        "Vectors",
+        "Vector"
+      ),
+      "[[Vector]] [[Vector#Norm|norm]]"
+    );
+    // links of the renamed note to itself
+    assert_eq!(
+      graph.rename_in(
+        "Matrices",
+        "[[matrices#Rank]] [[#Rank]]",
+        "Matrices",
+        "Matrix"
+      ),
+      "[[Matrix#Rank]] [[#Rank]]"
+    );
+    graph.update("Missing", "# Found");
+    assert_eq!(graph.unresolved(Some("Vectors")).len(), 1);
+    graph.remove("Missing");
+    assert_eq!(graph.unresolved(Some("Vectors")).len(), 2);
+  }
+}
@@ -0,0 +1,111 @@
+use super::constants;
+use fs2::FileExt;
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, Read, Write},
+  net::{Shutdown, TcpListener, TcpStream},
+  thread,
+};
+
+/*
+ * advisory lock on the workspace index.
+ * every command rewriting index.jsonl must hold this from reading the index to writing it back,
+ * or the changes by another process (e.g. another app instance or cli) between them are lost.
+ * the lock is released when dropped.
+ */
+pub struct IndexLock(File);
+impl IndexLock {
+  pub fn acquire() -> io::Result<IndexLock> {
+    let file = OpenOptions::new()
+      .create(true)
+      .write(true)
+      .open(crate::root_path().join(constants::INDEX_LOCK))?;
+    file.lock_exclusive()?;
+    Ok(IndexLock(file))
+  }
+}
+impl Drop for IndexLock {
+  fn drop(&mut self) {
+    if let Err(err) = self.0.unlock() {
+      println!("WARNING: failed to unlock index\n{}", err.to_string());
+    }
+  }
+}
+
+/*
+ * held by the first running instance for its whole lifetime.
+ * the lock is released by the os when the process exits, so a stale lock file never blocks launching.
+ * the port to reach the instance is written in a separate file, because locked files can't be read on windows.
+ */
+pub struct InstanceGuard {
+  _file: File,
+  listener: TcpListener,
+}
+impl InstanceGuard {
+  /*
+   * call `activate` every time another instance is launched.
+   */
+  pub fn on_activate<F: Fn() + Send + 'static>(&self, activate: F) -> io::Result<()> {
+    let listener = self.listener.try_clone()?;
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        match stream {
+          Ok(mut stream) => {
+            let mut buf = String::new();
+            if stream.read_to_string(&mut buf).is_ok() && buf == constants::ACTIVATE {
+              activate();
+            }
+          }
+          Err(err) => println!(
+            "WARNING: failed to accept another instance\n{}",
+            err.to_string()
+          ),
+        }
+      }
+    });
+    Ok(())
+  }
+}
+
+fn notify_primary() -> io::Result<()> {
+  let port = fs::read_to_string(crate::root_path().join(constants::INSTANCE_PORT))?;
+  let port = port
+    .trim()
+    .parse::<u16>()
+    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+  let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+  stream.write_all(constants::ACTIVATE.as_bytes())?;
+  stream.shutdown(Shutdown::Write)
+}
+
+/*
+ * returns None if another instance is already running, after asking it to focus its window.
+ */
+pub fn single_instance() -> io::Result<Option<InstanceGuard>> {
+  let root = crate::root_path();
+  fs::create_dir_all(&root)?;
+  let file = OpenOptions::new()
+    .create(true)
+    .write(true)
+    .open(root.join(constants::INSTANCE_LOCK))?;
+
+  if file.try_lock_exclusive().is_err() {
+    if let Err(err) = notify_primary() {
+      println!(
+        "WARNING: failed to reach running instance\n{}",
+        err.to_string()
+      );
+    }
+    return Ok(None);
+  }
+
+  let listener = TcpListener::bind(("127.0.0.1", 0))?;
+  fs::write(
+    root.join(constants::INSTANCE_PORT),
+    listener.local_addr()?.port().to_string(),
+  )?;
+  Ok(Some(InstanceGuard {
+    _file: file,
+    listener,
+  }))
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+
+/*
+ * math macros, which are the shortcut map of a document merged over the global ones in the setting.
+ * both are stored as `name => expansion` maps, where the name may be written with or without the backslash,
+ * and the arity is the largest `#n` in the expansion as katex infers it.
+ *
+ * the exports expand them in the math before rendering, so that katex and mathml see the same tex as the preview,
+ * where the frontend hands the merged map to katex. the latex export defines them with `\renewcommand` instead.
+ */
+
+// expansions of expansions, deep enough for real macros but finite for mutual recursion
+static MAX_DEPTH: usize = 16;
+// of the expanded tex, against macros which double their output at each depth
+static MAX_LENGTH: usize = 1 << 16;
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Macro {
+  pub name: String, //without the backslash
+  pub arity: usize,
+  pub expansion: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MacroError {
+  InvalidName(String),
+  EmptyExpansion(String),
+  UnbalancedBraces(String),
+  InvalidParameter(String),
+  Recursive(String),
+  TooLong(String),
+}
+impl ToString for MacroError {
+  fn to_string(&self) -> String {
+    match self {
+      MacroError::InvalidName(name) => {
+        format!("macro name must be of letters only: {}", name)
+      }
+      MacroError::EmptyExpansion(name) => format!("macro \\{} expands to nothing", name),
+      MacroError::UnbalancedBraces(name) => {
+        format!("braces in the expansion of \\{} are not balanced", name)
+      }
+      MacroError::InvalidParameter(name) => format!(
+        "`#` in the expansion of \\{} must be followed by 1 to 9",
+        name
+      ),
+      MacroError::Recursive(name) => format!("macro \\{} expands to itself", name),
+      MacroError::TooLong(name) => {
+        format!("expansion of \\{} exceeds {} characters", name, MAX_LENGTH)
+      }
+    }
+  }
+}
+
+/*
+ * the control sequence at the start of chars, e.g. `name` of `\name`, or a single character of `\{`.
+ */
+fn control_word(chars: &[char]) -> usize {
+  match chars.iter().take_while(|c| c.is_ascii_alphabetic()).count() {
+    0 => chars.len().min(1),
+    n => n,
+  }
+}
+
+fn arity(expansion: &str) -> usize {
+  expansion
+    .match_indices('#')
+    .filter_map(|(i, _)| expansion[i + 1..].chars().next()?.to_digit(10))
+    .max()
+    .unwrap_or(0) as usize
+}
+
+fn uses(expansion: &str, name: &str) -> bool {
+  let chars = expansion.chars().collect::<Vec<_>>();
+  let mut i = 0;
+  while i < chars.len() {
+    if chars[i] == '\\' {
+      let len = control_word(&chars[i + 1..]);
+      if chars[i + 1..i + 1 + len].iter().collect::<String>() == name {
+        return true;
+      }
+      i += 1 + len;
+    } else {
+      i += 1;
+    }
+  }
+  false
+}
+
+pub fn parse(name: &str, expansion: &str) -> Result<Macro, MacroError> {
+  let name = name.trim().trim_start_matches('\\');
+  if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+    return Err(MacroError::InvalidName(name.to_string()));
+  }
+  let expansion = expansion.trim();
+  if expansion.is_empty() {
+    return Err(MacroError::EmptyExpansion(name.to_string()));
+  }
+
+  let chars = expansion.chars().collect::<Vec<_>>();
+  let mut depth = 0i32;
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '\\' => i += 1,
+      '{' => depth += 1,
+      '}' => depth -= 1,
+      '#' => match chars.get(i + 1) {
+        Some('#') => i += 1,
+        Some(c) if ('1'..='9').contains(c) => (),
+        _ => return Err(MacroError::InvalidParameter(name.to_string())),
+      },
+      _ => (),
+    }
+    if depth < 0 {
+      return Err(MacroError::UnbalancedBraces(name.to_string()));
+    }
+    i += 1;
+  }
+  if depth != 0 {
+    return Err(MacroError::UnbalancedBraces(name.to_string()));
+  }
+  if uses(expansion, name) {
+    return Err(MacroError::Recursive(name.to_string()));
+  }
+
+  Ok(Macro {
+    name: name.to_string(),
+    arity: arity(expansion),
+    expansion: expansion.to_string(),
+  })
+}
+
+/*
+ * every entry of a stored map, sorted by name. fails on the first invalid one.
+ */
+pub fn table(map: &HashMap<String, String>) -> Result<Vec<Macro>, MacroError> {
+  let mut res = map
+    .iter()
+    .map(|(name, expansion)| parse(name, expansion))
+    .collect::<Result<Vec<_>, _>>()?;
+  res.sort_by(|a, b| a.name.cmp(&b.name));
+  Ok(res)
+}
+
+/*
+ * the document's macros over the global ones. both are validated on save,
+ * so an invalid entry which was written by hand is skipped with a warning.
+ */
+pub fn merge(global: &HashMap<String, String>, local: &HashMap<String, String>) -> Vec<Macro> {
+  let mut merged = HashMap::new();
+  for (name, expansion) in global.iter().chain(local.iter()) {
+    match parse(name, expansion) {
+      Ok(m) => {
+        merged.insert(m.name.clone(), m);
+      }
+      Err(err) => println!("WARNING: a macro is skipped\n{}", err.to_string()),
+    }
+  }
+  let mut res = merged.into_values().collect::<Vec<_>>();
+  res.sort_by(|a, b| a.name.cmp(&b.name));
+  res
+}
+
+/*
+ * an argument at the start of chars after spaces: a braced group without the braces,
+ * a control sequence or a single character. returns it and the number of chars consumed.
+ */
+fn argument(chars: &[char]) -> Option<(String, usize)> {
+  let skip = chars.iter().take_while(|c| c.is_whitespace()).count();
+  let rest = &chars[skip..];
+  match rest.first()? {
+    '{' => {
+      let mut depth = 0;
+      let mut i = 0;
+      while i < rest.len() {
+        match rest[i] {
+          '\\' => i += 1,
+          '{' => depth += 1,
+          '}' => {
+            depth -= 1;
+            if depth == 0 {
+              return Some((rest[1..i].iter().collect(), skip + i + 1));
+            }
+          }
+          _ => (),
+        }
+        i += 1;
+      }
+      None
+    }
+    '\\' => {
+      let len = 1 + control_word(&rest[1..]);
+      Some((rest[..len].iter().collect(), skip + len))
+    }
+    '}' => None,
+    c => Some((c.to_string(), skip + 1)),
+  }
+}
+
+fn substitute(expansion: &str, args: &[String]) -> String {
+  let chars = expansion.chars().collect::<Vec<_>>();
+  let mut res = String::new();
+  let mut i = 0;
+  while i < chars.len() {
+    match (chars[i], chars.get(i + 1)) {
+      ('\\', Some(&c)) => {
+        res.push('\\');
+        res.push(c);
+        i += 2;
+      }
+      ('#', Some('#')) => {
+        res.push('#');
+        i += 2;
+      }
+      ('#', Some(c)) if c.is_ascii_digit() => {
+        let n = c.to_digit(10).unwrap_or(0) as usize;
+        if let Some(arg) = n.checked_sub(1).and_then(|n| args.get(n)) {
+          res.push_str(arg);
+        }
+        i += 2;
+      }
+      (c, _) => {
+        res.push(c);
+        i += 1;
+      }
+    }
+  }
+  res
+}
+
+fn expand_with(
+  tex: &str,
+  macros: &HashMap<&str, &Macro>,
+  depth: usize,
+) -> Result<String, MacroError> {
+  let chars = tex.chars().collect::<Vec<_>>();
+  let mut res = String::new();
+  let mut i = 0;
+  while i < chars.len() {
+    if chars[i] != '\\' {
+      res.push(chars[i]);
+      i += 1;
+      continue;
+    }
+    let len = control_word(&chars[i + 1..]);
+    let name = chars[i + 1..i + 1 + len].iter().collect::<String>();
+    let end = i + 1 + len;
+    let found = macros.get(name.as_str()).filter(|_| depth < MAX_DEPTH);
+    if let Some(m) = found {
+      let mut args = vec![];
+      let mut j = end;
+      while args.len() < m.arity {
+        match argument(&chars[j..]) {
+          Some((arg, consumed)) => {
+            args.push(arg);
+            j += consumed;
+          }
+          None => break,
+        }
+      }
+      if args.len() == m.arity {
+        res.push_str(&expand_with(
+          &substitute(&m.expansion, &args),
+          macros,
+          depth + 1,
+        )?);
+        if res.len() > MAX_LENGTH {
+          return Err(MacroError::TooLong(name));
+        }
+        i = j;
+        continue;
+      }
+    }
+    res.push('\\');
+    res.push_str(&name);
+    i = end;
+  }
+  Ok(res)
+}
+
+/*
+ * expand the macros in tex. a macro short of arguments is left as it is.
+ * fails if the expansions grow the tex beyond MAX_LENGTH.
+ */
+pub fn expand(tex: &str, macros: &[Macro]) -> Result<String, MacroError> {
+  if macros.is_empty() {
+    return Ok(tex.to_string());
+  }
+  let macros = macros
+    .iter()
+    .map(|m| (m.name.as_str(), m))
+    .collect::<HashMap<_, _>>();
+  expand_with(tex, &macros, 0)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn map(entries: &[(&str, &str)]) -> HashMap<String, String> {
+    entries
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect()
+  }
+
+  #[test]
+  fn macro_parse_test() {
+    assert_eq!(
+      parse("\\pair", "\\langle #1, #2 \\rangle"),
+      Ok(Macro {
+        name: String::from("pair"),
+        arity: 2,
+        expansion: String::from("\\langle #1, #2 \\rangle"),
+      })
+    );
+    assert_eq!(parse("R", "\\mathbb{R}").unwrap().arity, 0);
+    assert_eq!(
+      parse("a1", "x"),
+      Err(MacroError::InvalidName(String::from("a1")))
+    );
+    assert_eq!(
+      parse("f", "{x"),
+      Err(MacroError::UnbalancedBraces(String::from("f")))
+    );
+    assert_eq!(
+      parse("f", "x_#"),
+      Err(MacroError::InvalidParameter(String::from("f")))
+    );
+    assert_eq!(
+      parse("f", "\\f{x}"),
+      Err(MacroError::Recursive(String::from("f")))
+    );
+    assert!(parse("f", "\\frac{1}{#1}").is_ok());
+    assert!(table(&map(&[("R", "\\mathbb{R}"), ("1", "x")])).is_err());
+  }
+
+  #[test]
+  fn macro_expand_test() {
+    let merged = merge(
+      &map(&[("\\R", "\\mathbb{R}"), ("norm", "|#1|")]),
+      &map(&[
+        ("norm", "\\lVert #1 \\rVert"),
+        ("pair", "\\langle #1, #2 \\rangle"),
+      ]),
+    );
+    assert_eq!(merged.len(), 3);
+    assert_eq!(
+      expand("x \\in \\R, \\norm{\\pair a{b_1}} \\Rel \\pair{x}", &merged).unwrap(),
+      "x \\in \\mathbb{R}, \\lVert \\langle a, b_1 \\rangle \\rVert \\Rel \\pair{x}"
+    );
+
+    // mutual recursion stops at the depth
+    let looped = merge(&map(&[("a", "\\b"), ("b", "\\a")]), &HashMap::new());
+    assert!(expand("\\a", &looped).unwrap().starts_with('\\'));
+
+    // so does doubling, but the output is too long by then
+    let doubled = merge(&map(&[("a", "\\b\\b"), ("b", "\\a\\a")]), &HashMap::new());
+    assert_eq!(
+      expand("\\a", &doubled),
+      Err(MacroError::TooLong(String::from("b")))
+    );
+  }
+}
@@ -6,37 +6,22 @@
 extern crate app;
 
 use app::{
-  cmd::{self, Response},
-  Casher, HiddenWindow, MainWindow,
+  asset, cmd,
+  jobs::Jobs,
+  lock::{self, InstanceGuard},
+  watcher, Casher, Env, HiddenWindow, MainWindow,
 };
-use std::{fs, thread, time};
-use tauri::{CustomMenuItem, Manager, Menu, Window, WindowEvent};
-
-/*
-* event "success", "fail" is setup on frontend -- see /src/pages/Top.tsx
-*/
-fn emit_success(main_window: &Window, msg: Response) {
-  match main_window.emit("success", msg.clone()) {
-    Ok(()) => (),
-    Err(_) => emit_success(main_window, msg),
-  }
-}
-fn emit_fail(main_window: &Window, err: Response) {
-  match main_window.emit("fail", err.clone()) {
-    Ok(()) => (),
-    Err(_) => emit_fail(main_window, err.clone()),
-  }
-}
-
-#[derive(Debug, PartialEq, serde::Serialize)]
-struct Template<'a> {
-  html: &'a str,
-  css: &'a str,
-  js: &'a str,
-}
+use tauri::{http::ResponseBuilder, CustomMenuItem, Manager, Menu, WindowEvent};
 
 fn main() {
   let fail_msg = "error while running tauri application";
+  let instance = match lock::single_instance().expect(fail_msg) {
+    Some(instance) => instance,
+    None => {
+      println!("another instance is already running");
+      return;
+    }
+  };
   let main_menu = {
     let new = CustomMenuItem::new(String::from("new"), "New");
     let browse = CustomMenuItem::new(String::from("browse"), "Browse");
@@ -70,73 +55,6 @@ fn main() {
       });
 
       let hidden_window = app.get_window("hidden").expect(fail_msg);
-      let main_window_ = main_window.clone();
-      let hidden_window_ = hidden_window.clone();
-      /*
-       * event "ready" is emitted when emitting event "print" and the setup finish,
-       * that is, the hidden web page's js loading and executing finish.
-       * see, cmd::print and /src/page/Listner.tsx
-       */
-      hidden_window.listen("ready", move |e| {
-        if let Some(status) = e.payload() {
-          if status == "success" {
-            emit_success(&main_window_, Response::new("preparing printer..."));
-            thread::sleep(time::Duration::from_millis(2000));
-            /*
-             * if hidden window would not be showed once, the page execution is still not complete
-             * though the reason is unknown.
-             * the problem may be tauri's hidden window processing but not sure (also may be frontend implementation).
-             * need splitting process more to get what the problem is.
-             */
-            match hidden_window_
-              .show()
-              .and_then(|_| hidden_window_.print())
-              .and_then(|_| hidden_window_.hide())
-            {
-              Ok(()) => (),
-              Err(err) => emit_fail(&main_window_, Response::process_error(err)),
-            }
-          } else {
-            emit_fail(&main_window_, Response::process_error(status))
-          }
-        }
-      });
-
-      let html = fs::read_to_string(
-        app
-          .path_resolver()
-          .resource_dir()
-          .expect("unknown error")
-          .join("assets/template.html"),
-      )
-      .unwrap();
-      let css = fs::read_to_string(
-        app
-          .path_resolver()
-          .resource_dir()
-          .expect("unknown error")
-          .join("assets/template.css"),
-      )
-      .unwrap()
-      .replace("\n", "");
-      let js = fs::read_to_string(
-        app
-          .path_resolver()
-          .resource_dir()
-          .expect("unknown error")
-          .join("assets/template.js"),
-      )
-      .unwrap()
-      .replace("\n", "");
-      let main_window_ = main_window.clone();
-      main_window.listen("template", move |_| {
-        let html = &html;
-        let css = &css;
-        let js = &js;
-        main_window_
-          .emit("return_template", Template { html, css, js })
-          .unwrap();
-      });
 
       let main_window_ = main_window.clone();
       let hidden_window_ = hidden_window.clone();
@@ -149,23 +67,105 @@ fn main() {
         _ => (),
       });
 
+      watcher::spawn(app.handle(), main_window.clone());
+
+      /*
+       * a second launch only asks this instance to come to the front -- see lock::single_instance
+       */
+      let main_window_ = main_window.clone();
+      app
+        .state::<InstanceGuard>()
+        .on_activate(move || {
+          match main_window_
+            .unminimize()
+            .and_then(|_| main_window_.show())
+            .and_then(|_| main_window_.set_focus())
+          {
+            Ok(()) => (),
+            Err(err) => println!("{}", err),
+          }
+        })
+        .expect(fail_msg);
+
+      /*
+       * print and export jobs report to the main window -- see jobs.rs
+       */
+      let main_window_ = main_window.clone();
+      app.manage(Jobs::start(move |event, payload| {
+        if let Err(err) = main_window_.emit(event, payload) {
+          println!("{}", err);
+        }
+      }));
       app.manage(HiddenWindow::new(hidden_window.clone()));
       app.manage(MainWindow::new(main_window.clone()));
 
       Ok(())
     })
+    .manage(instance)
     .manage(app::initialize())
     .manage(Casher::new())
+    /*
+     * attachments of the current workspace -- see asset.rs
+     */
+    .register_uri_scheme_protocol(asset::SCHEME, |app, request| {
+      let target_dir = app
+        .state::<Env>()
+        .target_dir()
+        .ok_or("setting is unavailable")?;
+      let header = |name: &str| {
+        request
+          .headers()
+          .get(name)
+          .and_then(|value| value.to_str().ok())
+      };
+      let asset = asset::serve(
+        &target_dir,
+        request.uri(),
+        header("range"),
+        header("if-none-match"),
+      )?;
+      let mut response = ResponseBuilder::new()
+        .status(asset.status)
+        .mimetype(&asset.mime);
+      for (name, value) in &asset.headers {
+        response = response.header(*name, value.as_str());
+      }
+      response.body(asset.body)
+    })
     .invoke_handler(tauri::generate_handler![
       cmd::get_setting,
       cmd::update_setting,
+      cmd::change_target_dir,
       cmd::save_document,
       cmd::delete_file,
       cmd::get_documents_by_filter,
       cmd::get_document,
+      cmd::render_document,
+      cmd::rebuild_index,
+      cmd::change_layout,
       cmd::ls_dir,
       cmd::print,
-      cmd::html
+      cmd::html,
+      cmd::export_html,
+      cmd::export_pdf,
+      cmd::export_latex,
+      cmd::export_epub,
+      cmd::build_site,
+      cmd::cancel_job,
+      cmd::list_export_templates,
+      cmd::list_templates,
+      cmd::create_from_template,
+      cmd::open_daily_note,
+      cmd::daily_calendar,
+      cmd::add_attachment,
+      cmd::list_attachments,
+      cmd::gc_attachments,
+      cmd::get_backlinks,
+      cmd::get_graph,
+      cmd::check_links,
+      cmd::get_outline,
+      cmd::workspace_stats,
+      cmd::rename_document
     ])
     .run(context)
     .expect(fail_msg);
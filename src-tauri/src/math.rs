@@ -0,0 +1,760 @@
+use super::render::escape_html;
+
+/*
+ * tex math to mathml, for the exports which must be readable without katex -- see render.rs
+ * it covers what notes usually use: scripts, fractions, roots, greek letters and symbols, functions,
+ * big operators, \left \right, accents, fonts, text and the matrix like environments.
+ * unknown commands are shown as <merror>, and the source is kept as the annotation.
+ */
+
+struct Node {
+  xml: String,
+  limits: bool, //scripts of this are placed under and over it in display mode
+}
+impl Node {
+  fn new(xml: String) -> Node {
+    Node { xml, limits: false }
+  }
+
+  fn limits(xml: String) -> Node {
+    Node { xml, limits: true }
+  }
+}
+
+enum Stop {
+  Eof,
+  Close,
+  Cell,
+  Row,
+  End,
+  Right(String),
+}
+
+fn mrow(nodes: Vec<Node>) -> String {
+  if nodes.len() == 1 {
+    return nodes.into_iter().next().unwrap().xml;
+  }
+  format!(
+    "<mrow>{}</mrow>",
+    nodes.into_iter().map(|n| n.xml).collect::<String>()
+  )
+}
+
+fn mo(op: &str) -> Node {
+  Node::new(format!("<mo>{}</mo>", escape_html(op)))
+}
+
+fn mi(id: &str) -> Node {
+  Node::new(format!("<mi>{}</mi>", escape_html(id)))
+}
+
+fn fence(delim: &str) -> String {
+  if delim.is_empty() {
+    String::new()
+  } else {
+    format!(
+      "<mo fence=\"true\" stretchy=\"true\">{}</mo>",
+      escape_html(delim)
+    )
+  }
+}
+
+fn identifier(name: &str) -> Option<&'static str> {
+  Some(match name {
+    "alpha" => "α",
+    "beta" => "β",
+    "gamma" => "γ",
+    "delta" => "δ",
+    "epsilon" => "ϵ",
+    "varepsilon" => "ε",
+    "zeta" => "ζ",
+    "eta" => "η",
+    "theta" => "θ",
+    "vartheta" => "ϑ",
+    "iota" => "ι",
+    "kappa" => "κ",
+    "lambda" => "λ",
+    "mu" => "μ",
+    "nu" => "ν",
+    "xi" => "ξ",
+    "pi" => "π",
+    "varpi" => "ϖ",
+    "rho" => "ρ",
+    "varrho" => "ϱ",
+    "sigma" => "σ",
+    "varsigma" => "ς",
+    "tau" => "τ",
+    "upsilon" => "υ",
+    "phi" => "ϕ",
+    "varphi" => "φ",
+    "chi" => "χ",
+    "psi" => "ψ",
+    "omega" => "ω",
+    "infty" => "∞",
+    "partial" => "∂",
+    "nabla" => "∇",
+    "emptyset" | "varnothing" => "∅",
+    "hbar" => "ℏ",
+    "ell" => "ℓ",
+    "Re" => "ℜ",
+    "Im" => "ℑ",
+    "aleph" => "ℵ",
+    _ => return None,
+  })
+}
+
+fn upright_identifier(name: &str) -> Option<&'static str> {
+  Some(match name {
+    "Gamma" => "Γ",
+    "Delta" => "Δ",
+    "Theta" => "Θ",
+    "Lambda" => "Λ",
+    "Xi" => "Ξ",
+    "Pi" => "Π",
+    "Sigma" => "Σ",
+    "Upsilon" => "Υ",
+    "Phi" => "Φ",
+    "Psi" => "Ψ",
+    "Omega" => "Ω",
+    _ => return None,
+  })
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+  Some(match name {
+    "pm" => "±",
+    "mp" => "∓",
+    "times" => "×",
+    "div" => "÷",
+    "cdot" => "⋅",
+    "ast" => "∗",
+    "star" => "⋆",
+    "circ" => "∘",
+    "bullet" => "∙",
+    "oplus" => "⊕",
+    "otimes" => "⊗",
+    "leq" | "le" => "≤",
+    "geq" | "ge" => "≥",
+    "neq" | "ne" => "≠",
+    "approx" => "≈",
+    "equiv" => "≡",
+    "sim" => "∼",
+    "simeq" => "≃",
+    "cong" => "≅",
+    "propto" => "∝",
+    "ll" => "≪",
+    "gg" => "≫",
+    "in" => "∈",
+    "notin" => "∉",
+    "ni" => "∋",
+    "subset" => "⊂",
+    "supset" => "⊃",
+    "subseteq" => "⊆",
+    "supseteq" => "⊇",
+    "cup" => "∪",
+    "cap" => "∩",
+    "setminus" => "∖",
+    "wedge" | "land" => "∧",
+    "vee" | "lor" => "∨",
+    "neg" | "lnot" => "¬",
+    "forall" => "∀",
+    "exists" => "∃",
+    "to" | "rightarrow" => "→",
+    "leftarrow" | "gets" => "←",
+    "Rightarrow" => "⇒",
+    "Leftarrow" => "⇐",
+    "Leftrightarrow" => "⇔",
+    "leftrightarrow" => "↔",
+    "iff" => "⟺",
+    "implies" => "⟹",
+    "mapsto" => "↦",
+    "uparrow" => "↑",
+    "downarrow" => "↓",
+    "mid" => "∣",
+    "parallel" => "∥",
+    "perp" => "⊥",
+    "angle" => "∠",
+    "ldots" | "dots" => "…",
+    "cdots" => "⋯",
+    "vdots" => "⋮",
+    "ddots" => "⋱",
+    "langle" => "⟨",
+    "rangle" => "⟩",
+    "lfloor" => "⌊",
+    "rfloor" => "⌋",
+    "lceil" => "⌈",
+    "rceil" => "⌉",
+    "vert" | "lvert" | "rvert" => "|",
+    "Vert" | "lVert" | "rVert" => "‖",
+    "colon" => ":",
+    "prime" => "′",
+    "{" => "{",
+    "}" => "}",
+    "|" => "‖",
+    "%" => "%",
+    "$" => "$",
+    "&" => "&",
+    "#" => "#",
+    "_" => "_",
+    _ => return None,
+  })
+}
+
+fn big_operator(name: &str) -> Option<(&'static str, bool)> {
+  Some(match name {
+    "sum" => ("∑", true),
+    "prod" => ("∏", true),
+    "coprod" => ("∐", true),
+    "bigcup" => ("⋃", true),
+    "bigcap" => ("⋂", true),
+    "bigoplus" => ("⨁", true),
+    "bigotimes" => ("⨂", true),
+    "int" => ("∫", false),
+    "iint" => ("∬", false),
+    "iiint" => ("∭", false),
+    "oint" => ("∮", false),
+    _ => return None,
+  })
+}
+
+fn function(name: &str) -> Option<bool> {
+  match name {
+    "lim" | "max" | "min" | "sup" | "inf" | "det" | "gcd" | "Pr" | "limsup" | "liminf" => {
+      Some(true)
+    }
+    "sin" | "cos" | "tan" | "sec" | "csc" | "cot" | "sinh" | "cosh" | "tanh" | "arcsin"
+    | "arccos" | "arctan" | "log" | "ln" | "lg" | "exp" | "arg" | "deg" | "dim" | "ker" | "hom" => {
+      Some(false)
+    }
+    _ => None,
+  }
+}
+
+fn accent(name: &str) -> Option<(&'static str, bool)> {
+  Some(match name {
+    "hat" | "widehat" => ("^", true),
+    "bar" | "overline" => ("¯", true),
+    "vec" | "overrightarrow" => ("→", true),
+    "dot" => ("˙", true),
+    "ddot" => ("¨", true),
+    "tilde" | "widetilde" => ("~", true),
+    "overbrace" => ("⏞", true),
+    "underline" => ("_", false),
+    "underbrace" => ("⏟", false),
+    _ => return None,
+  })
+}
+
+fn space(name: &str) -> Option<&'static str> {
+  Some(match name {
+    "," => "0.1667em",
+    ":" | ">" => "0.2222em",
+    ";" => "0.2778em",
+    " " => "0.3333em",
+    "quad" => "1em",
+    "qquad" => "2em",
+    "!" => "-0.1667em",
+    _ => return None,
+  })
+}
+
+fn variant(name: &str) -> Option<&'static str> {
+  Some(match name {
+    "mathrm" | "operatorname" => "normal",
+    "mathbf" | "boldsymbol" => "bold",
+    "mathit" => "italic",
+    "mathbb" => "double-struck",
+    "mathcal" => "script",
+    "mathfrak" => "fraktur",
+    "mathsf" => "sans-serif",
+    "mathtt" => "monospace",
+    _ => return None,
+  })
+}
+
+fn environment(name: &str) -> (&'static str, &'static str) {
+  match name {
+    "pmatrix" => ("(", ")"),
+    "bmatrix" => ("[", "]"),
+    "Bmatrix" | "cases" => ("{", ""),
+    "vmatrix" => ("|", "|"),
+    "Vmatrix" => ("‖", "‖"),
+    _ => ("", ""),
+  }
+}
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+  display: bool,
+}
+impl Parser {
+  fn new(src: &str, display: bool) -> Parser {
+    Parser {
+      chars: src.chars().collect(),
+      pos: 0,
+      display,
+    }
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).cloned()
+  }
+
+  fn skip_whitespace(&mut self) {
+    while self.peek().map_or(false, char::is_whitespace) {
+      self.pos += 1;
+    }
+  }
+
+  /*
+   * the name after '\', which is letters or a single other character.
+   */
+  fn command_name(&mut self) -> String {
+    let start = self.pos;
+    while self.peek().map_or(false, |c| c.is_ascii_alphabetic()) {
+      self.pos += 1;
+    }
+    if start == self.pos && self.pos < self.chars.len() {
+      self.pos += 1;
+    }
+    self.chars[start..self.pos].iter().collect()
+  }
+
+  /*
+   * the content of a braced argument as is, or the next character.
+   */
+  fn raw_arg(&mut self) -> String {
+    self.skip_whitespace();
+    if self.peek() != Some('{') {
+      return self
+        .peek()
+        .map(|c| {
+          self.pos += 1;
+          c.to_string()
+        })
+        .unwrap_or_default();
+    }
+    self.pos += 1;
+    let start = self.pos;
+    let mut depth = 0;
+    while let Some(c) = self.peek() {
+      match c {
+        '{' => depth += 1,
+        '}' if depth == 0 => break,
+        '}' => depth -= 1,
+        '\\' => self.pos += 1,
+        _ => (),
+      }
+      self.pos += 1;
+    }
+    let raw = self.chars[start..self.pos.min(self.chars.len())]
+      .iter()
+      .collect();
+    self.pos += 1;
+    raw
+  }
+
+  fn delimiter(&mut self) -> String {
+    self.skip_whitespace();
+    match self.peek() {
+      Some('\\') => {
+        self.pos += 1;
+        let name = self.command_name();
+        operator(&name).unwrap_or_default().to_string()
+      }
+      Some('.') | None => {
+        self.pos += 1;
+        String::new()
+      }
+      Some(c) => {
+        self.pos += 1;
+        c.to_string()
+      }
+    }
+  }
+
+  /*
+   * the next argument of a command or a script, which is a group, a command or a character.
+   */
+  fn arg(&mut self) -> Node {
+    self.skip_whitespace();
+    match self.peek() {
+      Some('\\') => {
+        self.pos += 1;
+        let name = self.command_name();
+        self
+          .command(&name)
+          .unwrap_or_else(|| Node::new(String::from("<mrow></mrow>")))
+      }
+      Some(_) => self.token(),
+      None => Node::new(String::from("<mrow></mrow>")),
+    }
+  }
+
+  fn token(&mut self) -> Node {
+    let c = self.chars[self.pos];
+    self.pos += 1;
+    match c {
+      '{' => {
+        let (nodes, _) = self.seq();
+        Node::new(format!(
+          "<mrow>{}</mrow>",
+          nodes.into_iter().map(|n| n.xml).collect::<String>()
+        ))
+      }
+      c if c.is_ascii_digit() => {
+        let mut num = c.to_string();
+        while let Some(d) = self.peek() {
+          let is_decimal = d == '.'
+            && self
+              .chars
+              .get(self.pos + 1)
+              .map_or(false, |c| c.is_ascii_digit());
+          if !d.is_ascii_digit() && !is_decimal {
+            break;
+          }
+          num.push(d);
+          self.pos += 1;
+        }
+        Node::new(format!("<mn>{}</mn>", num))
+      }
+      c if c.is_alphabetic() => mi(&c.to_string()),
+      '\'' => mo("′"),
+      '~' => Node::new(String::from("<mspace width=\"0.3333em\"></mspace>")),
+      c => mo(&c.to_string()),
+    }
+  }
+
+  fn scripts(&mut self, base: Node) -> Node {
+    let (mut sub, mut sup) = (None, None);
+    while let Some(c) = self.peek() {
+      match c {
+        '_' if sub.is_none() => {
+          self.pos += 1;
+          sub = Some(self.arg().xml);
+        }
+        '^' if sup.is_none() => {
+          self.pos += 1;
+          sup = Some(self.arg().xml);
+        }
+        _ => break,
+      }
+      self.skip_whitespace();
+    }
+    let under = base.limits && self.display;
+    let xml = match (sub, sup) {
+      (Some(sub), Some(sup)) if under => {
+        format!("<munderover>{}{}{}</munderover>", base.xml, sub, sup)
+      }
+      (Some(sub), None) if under => format!("<munder>{}{}</munder>", base.xml, sub),
+      (None, Some(sup)) if under => format!("<mover>{}{}</mover>", base.xml, sup),
+      (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base.xml, sub, sup),
+      (Some(sub), None) => format!("<msub>{}{}</msub>", base.xml, sub),
+      (None, Some(sup)) => format!("<msup>{}{}</msup>", base.xml, sup),
+      (None, None) => base.xml,
+    };
+    Node::new(xml)
+  }
+
+  fn seq(&mut self) -> (Vec<Node>, Stop) {
+    let mut nodes = vec![];
+    loop {
+      self.skip_whitespace();
+      let c = match self.peek() {
+        Some(c) => c,
+        None => return (nodes, Stop::Eof),
+      };
+      match c {
+        '}' => {
+          self.pos += 1;
+          return (nodes, Stop::Close);
+        }
+        '&' => {
+          self.pos += 1;
+          return (nodes, Stop::Cell);
+        }
+        '^' | '_' => {
+          let base = nodes
+            .pop()
+            .unwrap_or_else(|| Node::new(String::from("<mrow></mrow>")));
+          nodes.push(self.scripts(base));
+        }
+        '\\' => {
+          self.pos += 1;
+          let name = self.command_name();
+          match name.as_str() {
+            "\\" | "cr" => return (nodes, Stop::Row),
+            "end" => {
+              self.raw_arg();
+              return (nodes, Stop::End);
+            }
+            "right" => {
+              let delim = self.delimiter();
+              return (nodes, Stop::Right(delim));
+            }
+            _ => {
+              if let Some(node) = self.command(&name) {
+                nodes.push(node);
+              }
+            }
+          }
+        }
+        _ => nodes.push(self.token()),
+      }
+    }
+  }
+
+  fn table(&mut self, env: &str) -> Node {
+    if env == "array" {
+      self.raw_arg();
+    }
+    let mut rows: Vec<Vec<String>> = vec![vec![]];
+    loop {
+      let (nodes, stop) = self.seq();
+      rows.last_mut().unwrap().push(mrow(nodes));
+      match stop {
+        Stop::Cell => (),
+        Stop::Row => rows.push(vec![]),
+        _ => break,
+      }
+    }
+    // the row after the trailing \\ is empty
+    if rows.len() > 1
+      && rows
+        .last()
+        .map_or(false, |r| r.iter().all(|c| c.is_empty()))
+    {
+      rows.pop();
+    }
+    let align = match env {
+      "aligned" | "align" | "align*" | "split" => " columnalign=\"right left\"",
+      "cases" => " columnalign=\"left left\"",
+      _ => "",
+    };
+    let table = format!(
+      "<mtable{}>{}</mtable>",
+      align,
+      rows
+        .into_iter()
+        .map(|row| format!(
+          "<mtr>{}</mtr>",
+          row
+            .into_iter()
+            .map(|cell| format!("<mtd>{}</mtd>", cell))
+            .collect::<String>()
+        ))
+        .collect::<String>()
+    );
+    let (open, close) = environment(env);
+    Node::new(format!(
+      "<mrow>{}{}{}</mrow>",
+      fence(open),
+      table,
+      fence(close)
+    ))
+  }
+
+  fn command(&mut self, name: &str) -> Option<Node> {
+    if let Some(id) = identifier(name) {
+      return Some(mi(id));
+    }
+    if let Some(id) = upright_identifier(name) {
+      return Some(Node::new(format!("<mi mathvariant=\"normal\">{}</mi>", id)));
+    }
+    if let Some(op) = operator(name) {
+      return Some(mo(op));
+    }
+    if let Some((op, limits)) = big_operator(name) {
+      let xml = format!("<mo largeop=\"true\" movablelimits=\"true\">{}</mo>", op);
+      return Some(if limits {
+        Node::limits(xml)
+      } else {
+        Node::new(xml)
+      });
+    }
+    if let Some(limits) = function(name) {
+      let xml = format!("<mi>{}</mi>", name);
+      return Some(if limits {
+        Node::limits(xml)
+      } else {
+        Node::new(xml)
+      });
+    }
+    if let Some(width) = space(name) {
+      return Some(Node::new(format!("<mspace width=\"{}\"></mspace>", width)));
+    }
+    if let Some(mathvariant) = variant(name) {
+      let raw = self.raw_arg();
+      return Some(Node::new(format!(
+        "<mi mathvariant=\"{}\">{}</mi>",
+        mathvariant,
+        escape_html(&raw)
+      )));
+    }
+    if let Some((mark, over)) = accent(name) {
+      let base = self.arg().xml;
+      return Some(Node::new(if over {
+        format!("<mover accent=\"true\">{}<mo>{}</mo></mover>", base, mark)
+      } else {
+        format!(
+          "<munder accentunder=\"true\">{}<mo>{}</mo></munder>",
+          base, mark
+        )
+      }));
+    }
+    Some(match name {
+      "frac" | "dfrac" | "tfrac" | "cfrac" => {
+        let (num, den) = (self.arg().xml, self.arg().xml);
+        Node::new(format!("<mfrac>{}{}</mfrac>", num, den))
+      }
+      "binom" => {
+        let (n, k) = (self.arg().xml, self.arg().xml);
+        Node::new(format!(
+          "<mrow>{}<mfrac linethickness=\"0\">{}{}</mfrac>{}</mrow>",
+          fence("("),
+          n,
+          k,
+          fence(")")
+        ))
+      }
+      "sqrt" => {
+        self.skip_whitespace();
+        if self.peek() == Some('[') {
+          let start = self.pos + 1;
+          while self.peek().map_or(false, |c| c != ']') {
+            self.pos += 1;
+          }
+          let index = self.chars[start..self.pos].iter().collect::<String>();
+          self.pos += 1;
+          let (index, _) = Parser::new(&index, self.display).seq();
+          let base = self.arg().xml;
+          Node::new(format!("<mroot>{}{}</mroot>", base, mrow(index)))
+        } else {
+          Node::new(format!("<msqrt>{}</msqrt>", self.arg().xml))
+        }
+      }
+      "text" | "textrm" | "textit" | "textbf" | "mbox" => {
+        let raw = self.raw_arg();
+        Node::new(format!("<mtext>{}</mtext>", escape_html(&raw)))
+      }
+      "left" => {
+        let open = self.delimiter();
+        let (mut nodes, mut stop) = self.seq();
+        while let Stop::Cell | Stop::Row = stop {
+          let (more, next) = self.seq();
+          nodes.extend(more);
+          stop = next;
+        }
+        let close = match stop {
+          Stop::Right(close) => close,
+          _ => String::new(),
+        };
+        Node::new(format!(
+          "<mrow>{}{}{}</mrow>",
+          fence(&open),
+          nodes.into_iter().map(|n| n.xml).collect::<String>(),
+          fence(&close)
+        ))
+      }
+      "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl" | "biggr"
+      | "Biggl" | "Biggr" => {
+        let delim = self.delimiter();
+        Node::new(fence(&delim))
+      }
+      "begin" => {
+        let env = self.raw_arg();
+        self.table(&env)
+      }
+      "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" | "not" | "nonumber" => {
+        return None
+      }
+      name => Node::new(format!(
+        "<merror><mtext>\\{}</mtext></merror>",
+        escape_html(name)
+      )),
+    })
+  }
+
+  fn parse(&mut self) -> String {
+    let mut nodes = vec![];
+    loop {
+      let (more, stop) = self.seq();
+      nodes.extend(more);
+      if let Stop::Eof = stop {
+        break;
+      }
+    }
+    nodes.into_iter().map(|n| n.xml).collect()
+  }
+}
+
+pub fn to_mathml(tex: &str, display: bool) -> String {
+  let body = Parser::new(tex, display).parse();
+  format!(
+    "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"{}><semantics><mrow>{}</mrow>\
+     <annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+    if display { " display=\"block\"" } else { "" },
+    body,
+    escape_html(tex)
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn body(tex: &str, display: bool) -> String {
+    let xml = to_mathml(tex, display);
+    let start = xml.find("<semantics><mrow>").unwrap() + "<semantics><mrow>".len();
+    let end = xml.find("</mrow><annotation").unwrap();
+    xml[start..end].to_string()
+  }
+
+  #[test]
+  fn mathml_basic_test() {
+    assert_eq!(
+      body("x_1^2 + \\frac{a}{2\\pi}", false),
+      "<msubsup><mi>x</mi><mn>1</mn><mn>2</mn></msubsup><mo>+</mo>\
+       <mfrac><mrow><mi>a</mi></mrow><mrow><mn>2</mn><mi>π</mi></mrow></mfrac>"
+    );
+    assert_eq!(
+      body("\\sqrt[3]{x}", false),
+      "<mroot><mrow><mi>x</mi></mrow><mn>3</mn></mroot>"
+    );
+    assert_eq!(
+      body("\\text{if } x \\leq 0.5", false),
+      "<mtext>if </mtext><mi>x</mi><mo>≤</mo><mn>0.5</mn>"
+    );
+  }
+
+  #[test]
+  fn mathml_limits_test() {
+    assert_eq!(
+      body("\\sum_{i}^n", true),
+      "<munderover><mo largeop=\"true\" movablelimits=\"true\">∑</mo>\
+       <mrow><mi>i</mi></mrow><mi>n</mi></munderover>"
+    );
+    assert_eq!(
+      body("\\sum_{i}^n", false),
+      "<msubsup><mo largeop=\"true\" movablelimits=\"true\">∑</mo>\
+       <mrow><mi>i</mi></mrow><mi>n</mi></msubsup>"
+    );
+  }
+
+  #[test]
+  fn mathml_environment_test() {
+    assert_eq!(
+      body("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}", false),
+      "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mtable>\
+       <mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+       <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr>\
+       </mtable><mo fence=\"true\" stretchy=\"true\">)</mo></mrow>"
+    );
+    assert_eq!(
+      body("\\left( x \\right.", false),
+      "<mrow><mo fence=\"true\" stretchy=\"true\">(</mo><mi>x</mi></mrow>"
+    );
+    assert!(body("\\unknown", false).contains("<merror>"));
+  }
+}
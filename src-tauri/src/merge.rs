@@ -0,0 +1,167 @@
+/*
+ * line based three-way merge used to propose a resolution when a body is edited outside the app.
+ * see, cmd::save_document
+ */
+
+pub static OURS_MARKER: &str = "<<<<<<< editing";
+pub static SEPARATOR: &str = "=======";
+pub static THEIRS_MARKER: &str = ">>>>>>> on disk";
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Merged {
+  pub body: String,
+  pub conflicts: usize,
+}
+
+/*
+ * returns the pairs of matched line indices of the longest common subsequence.
+ * common prefix and suffix are trimmed before the dp so that small edits to large bodies stay cheap.
+ */
+fn lcs(x: &[&str], y: &[&str]) -> Vec<(usize, usize)> {
+  let prefix = x.iter().zip(y.iter()).take_while(|(a, b)| a == b).count();
+  let suffix = x[prefix..]
+    .iter()
+    .rev()
+    .zip(y[prefix..].iter().rev())
+    .take_while(|(a, b)| a == b)
+    .count();
+  let (xs, ys) = (&x[prefix..x.len() - suffix], &y[prefix..y.len() - suffix]);
+  let (n, m) = (xs.len(), ys.len());
+
+  let mut table = vec![0u32; (n + 1) * (m + 1)];
+  for i in (0..n).rev() {
+    for j in (0..m).rev() {
+      table[i * (m + 1) + j] = if xs[i] == ys[j] {
+        table[(i + 1) * (m + 1) + j + 1] + 1
+      } else {
+        table[(i + 1) * (m + 1) + j].max(table[i * (m + 1) + j + 1])
+      };
+    }
+  }
+
+  let mut pairs = (0..prefix).map(|i| (i, i)).collect::<Vec<_>>();
+  let (mut i, mut j) = (0, 0);
+  while i < n && j < m {
+    if xs[i] == ys[j] {
+      pairs.push((prefix + i, prefix + j));
+      i += 1;
+      j += 1;
+    } else if table[(i + 1) * (m + 1) + j] >= table[i * (m + 1) + j + 1] {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+  pairs.extend((0..suffix).map(|k| (x.len() - suffix + k, y.len() - suffix + k)));
+  pairs
+}
+
+fn matches(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+  let mut res = vec![None; base.len()];
+  for (i, j) in lcs(base, other) {
+    res[i] = Some(j);
+  }
+  res
+}
+
+/*
+ * diff3 style merge of `ours` and `theirs` against their common ancestor `base`.
+ * the stable chunks are kept as is, a chunk changed on only one side takes that side,
+ * and a chunk changed differently on both sides is emitted with conflict markers.
+ */
+pub fn merge3(base: &str, ours: &str, theirs: &str) -> Merged {
+  let base = base.split('\n').collect::<Vec<_>>();
+  let ours = ours.split('\n').collect::<Vec<_>>();
+  let theirs = theirs.split('\n').collect::<Vec<_>>();
+  let match_ours = matches(&base, &ours);
+  let match_theirs = matches(&base, &theirs);
+
+  let mut lines: Vec<&str> = vec![];
+  let mut conflicts = 0;
+  let (mut o, mut a, mut b) = (0, 0, 0);
+  while o < base.len() || a < ours.len() || b < theirs.len() {
+    let mut stable = 0;
+    while o + stable < base.len()
+      && match_ours[o + stable] == Some(a + stable)
+      && match_theirs[o + stable] == Some(b + stable)
+    {
+      stable += 1;
+    }
+    if 0 < stable {
+      lines.extend_from_slice(&base[o..o + stable]);
+      o += stable;
+      a += stable;
+      b += stable;
+      continue;
+    }
+
+    let next = (o..base.len()).find(|&i| match_ours[i].is_some() && match_theirs[i].is_some());
+    let (no, na, nb) = match next {
+      Some(i) => (i, match_ours[i].unwrap(), match_theirs[i].unwrap()),
+      None => (base.len(), ours.len(), theirs.len()),
+    };
+    let (base_chunk, ours_chunk, theirs_chunk) = (&base[o..no], &ours[a..na], &theirs[b..nb]);
+    if ours_chunk == base_chunk || ours_chunk == theirs_chunk {
+      lines.extend_from_slice(theirs_chunk);
+    } else if theirs_chunk == base_chunk {
+      lines.extend_from_slice(ours_chunk);
+    } else {
+      conflicts += 1;
+      lines.push(OURS_MARKER);
+      lines.extend_from_slice(ours_chunk);
+      lines.push(SEPARATOR);
+      lines.extend_from_slice(theirs_chunk);
+      lines.push(THEIRS_MARKER);
+    }
+    o = no;
+    a = na;
+    b = nb;
+  }
+
+  Merged {
+    body: lines.join("\n"),
+    conflicts,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn merge_disjoint_edits_test() {
+    let base = "# title\nfirst\nsecond\nthird";
+    let ours = "# title\nfirst edited\nsecond\nthird";
+    let theirs = "# title\nfirst\nsecond\nthird\nappended in vim";
+    assert_eq!(
+      merge3(base, ours, theirs),
+      Merged {
+        body: String::from("# title\nfirst edited\nsecond\nthird\nappended in vim"),
+        conflicts: 0,
+      }
+    );
+  }
+
+  #[test]
+  fn merge_conflict_test() {
+    let base = "# title\nline";
+    let ours = "# title\nline from app";
+    let theirs = "# title\nline from vim";
+    let merged = merge3(base, ours, theirs);
+    assert_eq!(merged.conflicts, 1);
+    assert_eq!(
+      merged.body,
+      format!(
+        "# title\n{}\nline from app\n{}\nline from vim\n{}",
+        OURS_MARKER, SEPARATOR, THEIRS_MARKER
+      )
+    );
+  }
+
+  #[test]
+  fn merge_same_edit_test() {
+    let merged = merge3("a\nb", "a\nc", "a\nc");
+    assert_eq!(merged.body, "a\nc");
+    assert_eq!(merged.conflicts, 0);
+  }
+}
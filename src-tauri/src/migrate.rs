@@ -0,0 +1,372 @@
+use super::constants::{ATTACHMENTS, TEMPLATES};
+use super::model::{digest, Meta};
+use std::{
+  collections::BTreeSet,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Transfer {
+  #[serde(rename = "move")]
+  Move,
+  #[serde(rename = "copy")]
+  Copy,
+}
+
+/*
+ * payload of event "migrate_progress".
+ * phase is one of "copy", "verify", "cleanup" and "rollback".
+ */
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Progress {
+  phase: &'static str,
+  done: usize,
+  total: usize,
+  file: String,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Report {
+  pub transferred: usize,
+  pub missing: Vec<String>,
+  pub left: Vec<String>,
+  #[serde(skip)]
+  dirs: Vec<PathBuf>, //created in the new target directory, the directory itself first if it was new
+}
+
+#[derive(Debug)]
+pub enum MigrateError {
+  SameDirectory,
+  NotADirectory(PathBuf),
+  AlreadyExists(PathBuf),
+  HashMismatch(PathBuf),
+  Io(io::Error),
+}
+impl ToString for MigrateError {
+  fn to_string(&self) -> String {
+    match self {
+      MigrateError::SameDirectory => {
+        String::from("new target directory is the same as current one")
+      }
+      MigrateError::NotADirectory(path) => format!("{:?} is not a directory", path),
+      MigrateError::AlreadyExists(path) => {
+        format!("{:?} already exists in new target directory", path)
+      }
+      MigrateError::HashMismatch(path) => {
+        format!("hash of {:?} does not match after transfer", path)
+      }
+      MigrateError::Io(err) => err.to_string(),
+    }
+  }
+}
+impl From<io::Error> for MigrateError {
+  fn from(err: io::Error) -> Self {
+    MigrateError::Io(err)
+  }
+}
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+  Ok(digest(&fs::read(path)?))
+}
+
+/*
+ * the files under `from/dir`, relative to `from`.
+ */
+fn files_under(from: &Path, dir: &Path, files: &mut Vec<String>) {
+  let entries = match fs::read_dir(from.join(dir)) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+  for entry in entries.flatten() {
+    let path = dir.join(entry.file_name());
+    if from.join(&path).is_dir() {
+      files_under(from, &path, files);
+    } else {
+      files.push(path.to_string_lossy().to_string());
+    }
+  }
+}
+
+/*
+ * list the files in `from` owned by the workspace, that is, bodies and html sources exported into the target directory
+ * with the attachments and the templates. files not found are returned as the second element.
+ */
+fn owned_files(metas: &[Meta], from: &Path) -> (Vec<String>, Vec<String>) {
+  let mut files = vec![];
+  let mut missing = vec![];
+  for dir in [ATTACHMENTS, TEMPLATES].iter() {
+    files_under(from, Path::new(dir), &mut files);
+  }
+  for meta in metas {
+    let body = meta.body_path();
+    if from.join(&body).is_file() {
+      files.push(body);
+    } else {
+      missing.push(body);
+    }
+    let html = meta.get_hashed_html_name();
+    if meta.is_html_src() && from.join(&html).is_file() {
+      files.push(html);
+    }
+  }
+  (files, missing)
+}
+
+/*
+ * remove the files copied, then the directories created for them, the deepest first.
+ */
+fn rollback<F: FnMut(Progress)>(created: &[PathBuf], dirs: &[PathBuf], on_progress: &mut F) {
+  for (i, path) in created.iter().enumerate() {
+    if let Err(err) = fs::remove_file(path) {
+      println!(
+        "WARNING: failed to rollback {:?}\n{}",
+        path,
+        err.to_string()
+      );
+    }
+    on_progress(Progress {
+      phase: "rollback",
+      done: i + 1,
+      total: created.len(),
+      file: path.to_string_lossy().to_string(),
+    });
+  }
+  for dir in dirs.iter().rev() {
+    if let Err(err) = fs::remove_dir(dir) {
+      println!("WARNING: failed to rollback {:?}\n{}", dir, err.to_string());
+    }
+  }
+}
+
+/*
+ * create the directory with its parents, keeping the ones which did not exist.
+ */
+fn create_dir(dir: &Path, dirs: &mut Vec<PathBuf>) -> io::Result<()> {
+  let mut new = dir
+    .ancestors()
+    .take_while(|dir| !dir.exists())
+    .map(Path::to_path_buf)
+    .collect::<Vec<_>>();
+  fs::create_dir_all(dir)?;
+  new.reverse();
+  dirs.extend(new);
+  Ok(())
+}
+
+/*
+ * copy every file owned by the documents from `from` to `to`, and verify them with sha256.
+ * if something fails partway, all the copied files are removed and `from` is left untouched.
+ * the caller is expected to switch the target directory after this returns Ok and then call `cleanup` on Transfer::Move.
+ */
+pub fn transfer<F: FnMut(Progress)>(
+  metas: &[Meta],
+  from: &Path,
+  to: &Path,
+  mut on_progress: F,
+) -> Result<Report, MigrateError> {
+  if from == to {
+    return Err(MigrateError::SameDirectory);
+  }
+  if to.exists() && !to.is_dir() {
+    return Err(MigrateError::NotADirectory(to.to_path_buf()));
+  }
+  let (files, missing) = owned_files(metas, from);
+  for file in files.iter() {
+    if to.join(file).exists() {
+      return Err(MigrateError::AlreadyExists(to.join(file)));
+    }
+  }
+
+  let mut dirs = vec![];
+  create_dir(to, &mut dirs)?;
+
+  let total = files.len();
+  let mut created = vec![];
+  let mut hashes = vec![];
+  for (i, file) in files.iter().enumerate() {
+    let (src, dst) = (from.join(file), to.join(file));
+    let res = hash_file(&src).and_then(|hash| {
+      dst
+        .parent()
+        .map_or(Ok(()), |parent| create_dir(parent, &mut dirs))
+        .and_then(|_| fs::copy(&src, &dst))
+        .map(|_| hash)
+    });
+    match res {
+      Ok(hash) => {
+        created.push(dst);
+        hashes.push(hash);
+      }
+      Err(err) => {
+        rollback(&created, &dirs, &mut on_progress);
+        return Err(MigrateError::Io(err));
+      }
+    }
+    on_progress(Progress {
+      phase: "copy",
+      done: i + 1,
+      total,
+      file: file.clone(),
+    });
+  }
+
+  for (i, (dst, hash)) in created.iter().zip(hashes.iter()).enumerate() {
+    match hash_file(dst) {
+      Ok(ref h) if h == hash => (),
+      Ok(_) => {
+        rollback(&created, &dirs, &mut on_progress);
+        return Err(MigrateError::HashMismatch(dst.clone()));
+      }
+      Err(err) => {
+        rollback(&created, &dirs, &mut on_progress);
+        return Err(MigrateError::Io(err));
+      }
+    }
+    on_progress(Progress {
+      phase: "verify",
+      done: i + 1,
+      total,
+      file: files[i].clone(),
+    });
+  }
+
+  Ok(Report {
+    transferred: total,
+    missing,
+    left: vec![],
+    dirs,
+  })
+}
+
+/*
+ * undo a successful `transfer`. used when the setting can't be switched after copying.
+ */
+pub fn revert(metas: &[Meta], from: &Path, to: &Path, report: &Report) {
+  let (files, _) = owned_files(metas, from);
+  let created = files.iter().map(|f| to.join(f)).collect::<Vec<_>>();
+  rollback(&created, &report.dirs, &mut |_| ());
+}
+
+/*
+ * remove the sources after the target directory is switched, then the directories left empty, e.g. by the layouts.
+ * all the files are already verified in the new directory, so failures here are reported but not rolled back.
+ */
+pub fn cleanup<F: FnMut(Progress)>(
+  metas: &[Meta],
+  from: &Path,
+  report: &mut Report,
+  mut on_progress: F,
+) {
+  let (files, _) = owned_files(metas, from);
+  let total = files.len();
+  let mut dirs = BTreeSet::new();
+  for (i, file) in files.into_iter().enumerate() {
+    if let Err(err) = fs::remove_file(from.join(&file)) {
+      println!("WARNING: failed to remove {}\n{}", file, err.to_string());
+      report.left.push(file.clone());
+    }
+    dirs.extend(
+      Path::new(&file)
+        .ancestors()
+        .skip(1)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(Path::to_path_buf),
+    );
+    on_progress(Progress {
+      phase: "cleanup",
+      done: i + 1,
+      total,
+      file,
+    });
+  }
+  // the deepest first, and the root is never one of them. the ones not empty are kept
+  let mut dirs = dirs.into_iter().collect::<Vec<_>>();
+  dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+  for dir in dirs {
+    let _ = fs::remove_dir(from.join(dir));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::{get_random_key, Setup};
+
+  fn tmp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("notex-{}-{}", name, get_random_key()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn transfer_and_cleanup_test() {
+    let from = tmp_dir("cleanup");
+    let to = from.join("to");
+    let setup = Setup::init();
+    let meta = setup.get_meta();
+    fs::write(from.join(meta.get_hashed_filename()), "# body").unwrap();
+    let mut dated = setup.get_meta().clone();
+    dated.set_filename(String::from("dated"));
+    dated.set_path(Some(String::from("2021/10/dated.md")));
+    fs::create_dir_all(from.join("2021").join("10")).unwrap();
+    fs::write(from.join("2021/10/dated.md"), "# dated").unwrap();
+    fs::write(from.join("2021").join("kept.txt"), "not owned").unwrap();
+    let metas = [meta.clone(), dated];
+    fs::create_dir_all(from.join(ATTACHMENTS).join("ab")).unwrap();
+    fs::write(from.join(ATTACHMENTS).join("ab").join("abcd.png"), "png").unwrap();
+    fs::create_dir_all(from.join(TEMPLATES)).unwrap();
+    fs::write(from.join(TEMPLATES).join("minutes.md"), "# {{title}}").unwrap();
+
+    // a reverted transfer leaves nothing, not even the new directory
+    let reverted = from.join("reverted");
+    let report = transfer(&metas, &from, &reverted, |_| ()).unwrap();
+    revert(&metas, &from, &reverted, &report);
+    assert!(!reverted.exists());
+
+    let mut phases = vec![];
+    let mut report = transfer(&metas, &from, &to, |p| phases.push(p.phase)).unwrap();
+    assert_eq!(report.transferred, 4);
+    assert_eq!(phases.iter().filter(|p| **p == "copy").count(), 4);
+    assert_eq!(
+      fs::read_to_string(to.join(meta.get_hashed_filename())).unwrap(),
+      "# body"
+    );
+    assert!(to.join(ATTACHMENTS).join("ab").join("abcd.png").is_file());
+    assert!(to.join(TEMPLATES).join("minutes.md").is_file());
+
+    cleanup(&metas, &from, &mut report, |_| ());
+    assert!(!from.join(meta.get_hashed_filename()).exists());
+    assert!(!from.join(TEMPLATES).exists());
+    assert!(!from.join(ATTACHMENTS).exists());
+    // the directories left empty are removed, but not the ones with other files nor the root
+    assert!(!from.join("2021").join("10").exists());
+    assert!(from.join("2021").join("kept.txt").is_file());
+    assert!(from.is_dir());
+    assert!(report.left.is_empty());
+
+    fs::remove_dir_all(from).unwrap();
+  }
+
+  #[test]
+  fn transfer_conflict_test() {
+    let from = tmp_dir("conflict-from");
+    let to = tmp_dir("conflict-to");
+    let setup = Setup::init();
+    let meta = setup.get_meta();
+    fs::write(from.join(meta.get_hashed_filename()), "# body").unwrap();
+    fs::write(to.join(meta.get_hashed_filename()), "# other").unwrap();
+
+    match transfer(&[meta.clone()], &from, &to, |_| ()) {
+      Err(MigrateError::AlreadyExists(_)) => (),
+      res => panic!("unexpected {:?}", res),
+    }
+    assert_eq!(
+      fs::read_to_string(to.join(meta.get_hashed_filename())).unwrap(),
+      "# other"
+    );
+
+    fs::remove_dir_all(from).unwrap();
+    fs::remove_dir_all(to).unwrap();
+  }
+}
@@ -1,4 +1,6 @@
 use super::constants::{TARGET_EXTENTION, TIME_FORMAT};
+use super::render::Section;
+use super::stats::Stats;
 use chrono::{offset::TimeZone, Duration, Local};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
@@ -12,10 +14,46 @@ pub struct Meta {
   updated_at: Option<TimeStamp>,
   author: String,
   tags: Vec<Tag>,
-  shortcut: HashMap<String, String>,
+  shortcut: HashMap<String, String>, //math macros, name => expansion. validated on save -- see macros.rs
   #[serde(default)]
-  html_src: bool
+  html_src: bool,
+  #[serde(default)]
+  digest: Option<String>, //sha256 of the body when last saved by the app
+  #[serde(default)]
+  mtime: Option<u64>, //modified time of the body in millis when last saved by the app
+  #[serde(default)]
+  path: Option<String>, //relative to the target directory. if None, the hashed filename -- see layout.rs
+  #[serde(default)]
+  template: Option<String>, //name of the template used to export. if None, the builtin one -- see template.rs
+  #[serde(default)]
+  outline: Vec<Section>, //headings of the body when last saved by the app -- see render::outline
+  #[serde(default)]
+  stats: Stats, //of the body when last saved by the app -- see stats.rs
+}
+
+/*
+ * the part of Meta written as front matter at the top of the body -- see frontmatter.rs
+ * the order of fields matters for toml, where tables must come after values.
+ */
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Matter {
+  pub title: String,
+  pub author: String,
+  #[serde(default)]
+  pub tags: Vec<Tag>,
+  pub created_at: TimeStamp,
+  #[serde(default)]
+  pub updated_at: Option<TimeStamp>,
+  #[serde(default)]
+  pub shortcut: HashMap<String, String>,
 }
+
+pub fn digest(src: &[u8]) -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(src);
+  format!("{:x}", hasher.finalize())
+}
+
 impl Meta {
   pub fn get_hashed_filename(&self) -> String {
     let mut hasher = Sha256::new();
@@ -23,12 +61,122 @@ impl Meta {
     format!("{:x}{}", hasher.finalize(), TARGET_EXTENTION)
   }
 
+  /*
+   * where the body is stored, relative to the target directory.
+   */
+  pub fn body_path(&self) -> String {
+    self
+      .path
+      .clone()
+      .unwrap_or_else(|| self.get_hashed_filename())
+  }
+
+  pub fn get_path(&self) -> Option<String> {
+    self.path.clone()
+  }
+
+  pub fn set_path(&mut self, path: Option<String>) {
+    self.path = path;
+  }
+
+  pub fn get_filename(&self) -> &str {
+    &self.filename
+  }
+
+  /*
+   * the hashed names follow the title, so the body and html source have to be moved with it -- see rename_document
+   */
+  pub fn set_filename(&mut self, filename: String) {
+    self.filename = filename;
+  }
+
+  pub fn get_created_at(&self) -> &str {
+    &self.created_at
+  }
+
+  pub fn get_tags(&self) -> &[Tag] {
+    &self.tags
+  }
+
+  pub fn get_author(&self) -> &str {
+    &self.author
+  }
+
+  pub fn get_shortcut(&self) -> &HashMap<String, String> {
+    &self.shortcut
+  }
+
+  pub fn get_updated_at(&self) -> Option<&str> {
+    self.updated_at.as_deref()
+  }
+
+  pub fn get_template(&self) -> Option<&str> {
+    self.template.as_deref()
+  }
+
+  pub fn set_template(&mut self, template: Option<String>) {
+    self.template = template;
+  }
+
+  pub fn get_outline(&self) -> &[Section] {
+    &self.outline
+  }
+
+  pub fn set_outline(&mut self, outline: Vec<Section>) {
+    self.outline = outline;
+  }
+
+  pub fn get_stats(&self) -> &Stats {
+    &self.stats
+  }
+
+  pub fn set_stats(&mut self, stats: Stats) {
+    self.stats = stats;
+  }
+
   pub fn get_hashed_html_name(&self) -> String {
     let mut hasher = Sha256::new();
     hasher.update(self.filename.as_bytes());
     format!("{:x}{}", hasher.finalize(), ".html")
   }
 
+  pub fn from_matter(matter: Matter) -> Meta {
+    Meta {
+      filename: matter.title,
+      created_at: matter.created_at,
+      updated_at: matter.updated_at,
+      author: matter.author,
+      tags: matter.tags,
+      shortcut: matter.shortcut,
+      html_src: false,
+      digest: None,
+      mtime: None,
+      path: None,
+      template: None,
+      outline: vec![],
+      stats: Stats::default(),
+    }
+  }
+
+  pub fn to_matter(&self) -> Matter {
+    Matter {
+      title: self.filename.clone(),
+      author: self.author.clone(),
+      tags: self.tags.clone(),
+      created_at: self.created_at.clone(),
+      updated_at: self.updated_at.clone(),
+      shortcut: self.shortcut.clone(),
+    }
+  }
+
+  /*
+   * keep the states which can't be recovered from front matter when rebuilding the index.
+   */
+  pub fn inherit(&mut self, old: &Meta) {
+    self.html_src = old.html_src;
+    self.template = old.template.clone();
+  }
+
   pub fn get_into_tag(self) -> Vec<String> {
     self.tags
   }
@@ -46,6 +194,27 @@ impl Meta {
     self.html_src = true;
   }
 
+  pub fn is_html_src(&self) -> bool {
+    self.html_src
+  }
+
+  pub fn set_version(&mut self, digest: String, mtime: Option<u64>) {
+    self.digest = Some(digest);
+    self.mtime = mtime;
+  }
+
+  /*
+   * whether the body on disk differs from the version the client started editing from.
+   * the base body is preferred to the digest in the index, because the client may hold an older meta.
+   * old index entries without digest are never regarded as modified.
+   */
+  pub fn is_modified_outside(&self, base: Option<&str>, on_disk: &str) -> bool {
+    match base.map(|b| digest(b.as_bytes())).or_else(|| self.digest.clone()) {
+      Some(expected) => expected != digest(on_disk.as_bytes()),
+      None => false,
+    }
+  }
+
   pub fn filter_by_filename(&self, start: &str, contain: &str) -> bool {
     if start.is_empty() && contain.is_empty() {
       true
@@ -105,6 +274,21 @@ impl Meta {
     }
   }
 
+  /*
+   * any heading of the outline contains it, ignoring the case.
+   */
+  pub fn filter_by_heading(&self, contain: &str) -> bool {
+    if contain.is_empty() {
+      true
+    } else {
+      let contain = contain.to_lowercase();
+      self
+        .outline
+        .iter()
+        .any(|section| section.text.to_lowercase().contains(&contain))
+    }
+  }
+
   pub fn filter_by_html_src(&self, is_html_src_exists: Option<bool>) -> bool {
     match is_html_src_exists {
       None => true,
@@ -113,6 +297,36 @@ impl Meta {
   }
 }
 
+/*
+ * conditions to select documents, shared by the listing and the exports.
+ * each empty condition is ignored -- see the filter_by_* methods.
+ */
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Filter {
+  pub filename_start: String,
+  pub filename_contain: String,
+  pub created_at: (String, String), //left is min, right is max. if invalid format, the one is ignored
+  pub updated_at: (String, String), //left is min, right is max. if invalid format, the one is ignored
+  pub tags: Vec<String>,
+  pub author: String,
+  pub is_html_src_exists: Option<bool>,
+  pub heading_contain: String,
+}
+impl Filter {
+  pub fn matches(&self, meta: &Meta) -> chrono::ParseResult<bool> {
+    Ok(
+      meta.filter_by_filename(&self.filename_start, &self.filename_contain)
+        && meta.filter_by_created(&self.created_at.0, &self.created_at.1)?
+        && meta.filter_by_updated(&self.updated_at.0, &self.updated_at.1)?
+        && meta.filter_by_tags(&self.tags)
+        && meta.filter_by_author(&self.author)
+        && meta.filter_by_html_src(self.is_html_src_exists)
+        && meta.filter_by_heading(&self.heading_contain),
+    )
+  }
+}
+
 #[cfg(test)]
 pub mod tests {
   use super::*;
@@ -125,6 +339,20 @@ pub mod tests {
     format!("{:x}", hasher.finalize())
   }
 
+  /*
+   * a meta by "author", for the tests which need many notes.
+   */
+  pub fn meta(title: &str, tags: &[&str], created_at: &str) -> Meta {
+    Meta::from_matter(Matter {
+      title: title.to_string(),
+      author: String::from("author"),
+      tags: tags.iter().map(|t| t.to_string()).collect(),
+      created_at: created_at.to_string(),
+      updated_at: None,
+      shortcut: HashMap::new(),
+    })
+  }
+
   pub struct Setup {
     meta: Meta,
   }
@@ -138,7 +366,13 @@ pub mod tests {
           author: String::from("me"),
           tags: vec![String::from("t1"), String::from("t2"), String::from("t3")],
           shortcut: HashMap::new(),
-          html_src: false
+          html_src: false,
+          digest: None,
+          mtime: None,
+          path: None,
+          template: None,
+          outline: vec![],
+          stats: Default::default(),
         },
       }
     }
@@ -155,7 +389,7 @@ pub mod tests {
       self.meta.shortcut.insert(id, target);
     }
 
-    fn move_filename_to(&mut self, target: &str) {
+    pub fn move_filename_to(&mut self, target: &str) {
       self.meta.filename = String::from(target);
     }
 
@@ -168,7 +402,13 @@ pub mod tests {
           \"author\":\"{}\",\
           \"tags\":[{}],\
           \"shortcut\":{{{}}},\
-          \"html_src\":{}\
+          \"html_src\":{},\
+          \"digest\":{},\
+          \"mtime\":{},\
+          \"path\":{},\
+          \"template\":{},\
+          \"outline\":{},\
+          \"stats\":{}\
         }}",
         meta.filename,
         meta.created_at,
@@ -191,7 +431,25 @@ pub mod tests {
           });
           String::from(&fmt[..if fmt.len() > 0 { fmt.len() - 1 } else { 0 }])
         },
-        meta.html_src
+        meta.html_src,
+        match &meta.digest {
+          Some(d) => format!("\"{}\"", d),
+          None => String::from("null"),
+        },
+        match meta.mtime {
+          Some(t) => t.to_string(),
+          None => String::from("null"),
+        },
+        match &meta.path {
+          Some(p) => format!("\"{}\"", p),
+          None => String::from("null"),
+        },
+        match &meta.template {
+          Some(t) => format!("\"{}\"", t),
+          None => String::from("null"),
+        },
+        serde_json::to_string(&meta.outline).unwrap(),
+        serde_json::to_string(&meta.stats).unwrap()
       )
     }
   }
@@ -249,6 +507,47 @@ pub mod tests {
     );
   }
 
+  #[test]
+  fn meta_version_test() {
+    let mut setup = Setup::init();
+    let meta = setup.get_meta_mut();
+    assert!(!meta.is_modified_outside(None, "anything"));
+
+    meta.set_version(digest(b"# saved"), Some(0));
+    assert!(!meta.is_modified_outside(None, "# saved"));
+    assert!(meta.is_modified_outside(None, "# edited in vim"));
+    assert!(!meta.is_modified_outside(Some("# edited in vim"), "# edited in vim"));
+    assert!(meta.is_modified_outside(Some("# saved"), "# edited in vim"));
+
+    assert_eq!(
+      serde_json::from_str::<Meta>(&Setup::build_raw_meta(meta)).unwrap(),
+      *meta
+    );
+  }
+
+  #[test]
+  fn meta_outline_test() {
+    let mut setup = Setup::init();
+    let meta = setup.get_meta_mut();
+    assert!(meta.filter_by_heading(""));
+    assert!(!meta.filter_by_heading("eigen"));
+
+    meta.set_outline(crate::render::outline("# Linear Algebra\n\n## Eigen Values\n"));
+    assert!(meta.filter_by_heading("eigen"));
+    assert!(!meta.filter_by_heading("rank"));
+    assert_eq!(meta.get_outline()[1].line, 3);
+    assert_eq!(
+      serde_json::from_str::<Meta>(&Setup::build_raw_meta(meta)).unwrap(),
+      *meta
+    );
+    // the index written before outlines
+    let old = Setup::build_raw_meta(meta).replace(
+      &format!(",\"outline\":{}", serde_json::to_string(meta.get_outline()).unwrap()),
+      "",
+    );
+    assert!(serde_json::from_str::<Meta>(&old).unwrap().get_outline().is_empty());
+  }
+
   #[test]
   fn meta_filename_hash_test() {
     let mut setup = Setup::init();
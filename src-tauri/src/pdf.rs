@@ -0,0 +1,461 @@
+use super::render::escape_html;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde_json::{json, Value};
+use std::{
+  env, fs,
+  io::{self, BufRead, BufReader},
+  net::TcpStream,
+  path::{Path, PathBuf},
+  process::{Child, Command, Stdio},
+  sync::mpsc,
+  thread,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tungstenite::{Message, WebSocket};
+
+/*
+ * pdf without the print dialog, by a headless chrome, edge or chromium installed on the machine.
+ * the browser is driven through the devtools protocol and prints with Page.printToPDF,
+ * which takes the paper size, the margins and the header and footer templates.
+ * one browser is kept for a batch, and every page is a single file html -- see standalone.rs
+ *
+ * in the header and footer, `{{title}}`, `{{date}}`, `{{page}}` and `{{pages}}` are replaced.
+ */
+
+static DEVTOOLS_PREFIX: &str = "DevTools listening on ";
+static LAUNCH_TIMEOUT: Duration = Duration::from_secs(20);
+static PRINT_TIMEOUT: Duration = Duration::from_secs(60);
+static MM_PER_INCH: f64 = 25.4;
+// what can't be in a path of a file url
+static PATH_SET: &AsciiSet = &CONTROLS
+  .add(b' ')
+  .add(b'"')
+  .add(b'#')
+  .add(b'%')
+  .add(b'<')
+  .add(b'>')
+  .add(b'?')
+  .add(b'`')
+  .add(b'{')
+  .add(b'}');
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum PageSize {
+  #[serde(rename = "A4")]
+  A4,
+  #[serde(rename = "A5")]
+  A5,
+  #[serde(rename = "B5")]
+  B5,
+  #[serde(rename = "Letter")]
+  Letter,
+  #[serde(rename = "Legal")]
+  Legal,
+}
+impl PageSize {
+  // width and height in inches
+  fn inches(&self) -> (f64, f64) {
+    match self {
+      PageSize::A4 => (8.27, 11.69),
+      PageSize::A5 => (5.83, 8.27),
+      PageSize::B5 => (7.17, 10.12), // JIS
+      PageSize::Letter => (8.5, 11.0),
+      PageSize::Legal => (8.5, 14.0),
+    }
+  }
+}
+impl Default for PageSize {
+  fn default() -> Self {
+    PageSize::A4
+  }
+}
+
+// in millimeters
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Margin {
+  pub top: f64,
+  pub bottom: f64,
+  pub left: f64,
+  pub right: f64,
+}
+impl Default for Margin {
+  fn default() -> Self {
+    Margin {
+      top: 20.0,
+      bottom: 20.0,
+      left: 15.0,
+      right: 15.0,
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PdfOptions {
+  pub page_size: PageSize,
+  pub landscape: bool,
+  pub margin: Margin,
+  pub header: String,
+  pub footer: String,
+  pub page_numbers: bool, //if footer is empty, "{{page}} / {{pages}}" is the footer
+  pub browser: String,    //path to the executable. if empty, searched in the usual places
+}
+
+#[derive(Debug)]
+pub enum PdfError {
+  BrowserNotFound,
+  Launch(String),
+  Protocol(String),
+  Io(io::Error),
+}
+impl ToString for PdfError {
+  fn to_string(&self) -> String {
+    match self {
+      PdfError::BrowserNotFound => {
+        String::from("chrome, edge or chromium is not found. set the path in the setting.")
+      }
+      PdfError::Launch(err) => format!("failed to launch the browser: {}", err),
+      PdfError::Protocol(err) => format!("failed to print: {}", err),
+      PdfError::Io(err) => err.to_string(),
+    }
+  }
+}
+impl From<io::Error> for PdfError {
+  fn from(err: io::Error) -> Self {
+    PdfError::Io(err)
+  }
+}
+impl From<tungstenite::Error> for PdfError {
+  fn from(err: tungstenite::Error) -> Self {
+    match err {
+      tungstenite::Error::Io(err) => PdfError::Io(err),
+      err => PdfError::Protocol(err.to_string()),
+    }
+  }
+}
+
+fn candidates() -> Vec<PathBuf> {
+  if cfg!(target_os = "windows") {
+    let mut dirs = vec![];
+    for var in &["ProgramFiles(x86)", "ProgramFiles", "LocalAppData"] {
+      if let Some(dir) = env::var_os(var) {
+        dirs.push(PathBuf::from(dir));
+      }
+    }
+    dirs
+      .iter()
+      .flat_map(|dir| {
+        vec![
+          dir.join("Microsoft/Edge/Application/msedge.exe"),
+          dir.join("Google/Chrome/Application/chrome.exe"),
+          dir.join("Chromium/Application/chrome.exe"),
+        ]
+      })
+      .collect()
+  } else if cfg!(target_os = "macos") {
+    vec![
+      PathBuf::from("/Applications/Google Chrome.app/Contents/MacOS/Google Chrome"),
+      PathBuf::from("/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge"),
+      PathBuf::from("/Applications/Chromium.app/Contents/MacOS/Chromium"),
+    ]
+  } else {
+    let names = [
+      "google-chrome",
+      "google-chrome-stable",
+      "chromium",
+      "chromium-browser",
+      "microsoft-edge",
+    ];
+    env::var_os("PATH")
+      .map(|paths| {
+        env::split_paths(&paths)
+          .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+          .collect()
+      })
+      .unwrap_or_default()
+  }
+}
+
+pub fn find_browser(configured: &str) -> Option<PathBuf> {
+  if !configured.is_empty() {
+    return Some(PathBuf::from(configured)).filter(|path| path.is_file());
+  }
+  candidates().into_iter().find(|path| path.is_file())
+}
+
+fn file_url(path: &Path) -> String {
+  let path = path.to_string_lossy().replace('\\', "/");
+  format!(
+    "file:///{}",
+    utf8_percent_encode(path.trim_start_matches('/'), PATH_SET)
+  )
+}
+
+/*
+ * header or footer template of chrome, which needs its own font size since it doesn't inherit the page style.
+ */
+fn page_template(text: &str) -> String {
+  if text.is_empty() {
+    // chrome prints the date and the title if the template is empty
+    return String::from("<span></span>");
+  }
+  let html = escape_html(text)
+    .replace("{{title}}", "<span class=\"title\"></span>")
+    .replace("{{date}}", "<span class=\"date\"></span>")
+    .replace("{{pages}}", "<span class=\"totalPages\"></span>")
+    .replace("{{page}}", "<span class=\"pageNumber\"></span>");
+  format!(
+    "<div style=\"width: 100%; margin: 0 1cm; font-size: 9px; text-align: center;\">{}</div>",
+    html
+  )
+}
+
+fn print_params(options: &PdfOptions) -> Value {
+  let (width, height) = options.page_size.inches();
+  let footer = if options.footer.is_empty() && options.page_numbers {
+    "{{page}} / {{pages}}"
+  } else {
+    options.footer.as_str()
+  };
+  json!({
+    "landscape": options.landscape,
+    "displayHeaderFooter": !options.header.is_empty() || !footer.is_empty(),
+    "headerTemplate": page_template(&options.header),
+    "footerTemplate": page_template(footer),
+    "printBackground": true,
+    "paperWidth": width,
+    "paperHeight": height,
+    "marginTop": options.margin.top / MM_PER_INCH,
+    "marginBottom": options.margin.bottom / MM_PER_INCH,
+    "marginLeft": options.margin.left / MM_PER_INCH,
+    "marginRight": options.margin.right / MM_PER_INCH,
+  })
+}
+
+pub struct Browser {
+  child: Child,
+  socket: WebSocket<TcpStream>,
+  profile: PathBuf, //temporary user data directory, also holding the pages to print
+  id: u64,
+  events: Vec<Value>,
+  pages: usize,
+}
+impl Browser {
+  pub fn launch(configured: &str) -> Result<Browser, PdfError> {
+    let path = find_browser(configured).ok_or(PdfError::BrowserNotFound)?;
+    let stamp = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|d| d.as_millis())
+      .unwrap_or_default();
+    let profile = env::temp_dir().join(format!("notex-pdf-{}-{}", std::process::id(), stamp));
+    fs::create_dir_all(&profile)?;
+
+    let mut child = Command::new(&path)
+      .arg("--headless")
+      .arg("--disable-gpu")
+      .arg("--no-first-run")
+      .arg("--no-default-browser-check")
+      .arg("--remote-debugging-port=0")
+      .arg(format!("--user-data-dir={}", profile.to_string_lossy()))
+      .arg("about:blank")
+      .stdout(Stdio::null())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|err| PdfError::Launch(err.to_string()))?;
+
+    // the browser tells the endpoint on stderr. the rest of stderr is drained so that the pipe never fills.
+    let stderr = child.stderr.take();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+      if let Some(stderr) = stderr {
+        for line in BufReader::new(stderr).lines().flatten() {
+          if let Some(url) = line.trim().strip_prefix(DEVTOOLS_PREFIX) {
+            let _ = tx.send(url.to_string());
+          }
+        }
+      }
+    });
+    let url = match rx.recv_timeout(LAUNCH_TIMEOUT) {
+      Ok(url) => url,
+      Err(_) => {
+        let _ = child.kill();
+        let _ = fs::remove_dir_all(&profile);
+        return Err(PdfError::Launch(String::from(
+          "the browser didn't open the devtools endpoint",
+        )));
+      }
+    };
+
+    let host = url
+      .trim_start_matches("ws://")
+      .split('/')
+      .next()
+      .unwrap_or_default()
+      .to_string();
+    let socket = TcpStream::connect(&host)
+      .and_then(|stream| {
+        stream.set_read_timeout(Some(PRINT_TIMEOUT))?;
+        Ok(stream)
+      })
+      .map_err(PdfError::from)
+      .and_then(|stream| {
+        tungstenite::client(url.as_str(), stream)
+          .map(|(socket, _)| socket)
+          .map_err(|err| PdfError::Launch(err.to_string()))
+      });
+    match socket {
+      Ok(socket) => Ok(Browser {
+        child,
+        socket,
+        profile,
+        id: 0,
+        events: vec![],
+        pages: 0,
+      }),
+      Err(err) => {
+        let _ = child.kill();
+        let _ = fs::remove_dir_all(&profile);
+        Err(err)
+      }
+    }
+  }
+
+  fn receive(&mut self) -> Result<Value, PdfError> {
+    loop {
+      if let Message::Text(text) = self.socket.read_message()? {
+        return serde_json::from_str(&text).map_err(|err| PdfError::Protocol(err.to_string()));
+      }
+    }
+  }
+
+  /*
+   * send a command and wait for its result. events arriving meanwhile are kept for wait_for.
+   */
+  fn call(
+    &mut self,
+    session: Option<&str>,
+    method: &str,
+    params: Value,
+  ) -> Result<Value, PdfError> {
+    self.id += 1;
+    let mut msg = json!({ "id": self.id, "method": method, "params": params });
+    if let Some(session) = session {
+      msg["sessionId"] = json!(session);
+    }
+    self.socket.write_message(Message::Text(msg.to_string()))?;
+    loop {
+      let res = self.receive()?;
+      if res["id"] == json!(self.id) {
+        if let Some(err) = res.get("error") {
+          return Err(PdfError::Protocol(format!(
+            "{}: {}",
+            method, err["message"]
+          )));
+        }
+        return Ok(res["result"].clone());
+      }
+      if res.get("method").is_some() {
+        self.events.push(res);
+      }
+    }
+  }
+
+  fn wait_for(&mut self, session: &str, method: &str) -> Result<(), PdfError> {
+    let is_target = |event: &Value| event["method"] == method && event["sessionId"] == session;
+    if self.events.iter().any(is_target) {
+      self.events.clear();
+      return Ok(());
+    }
+    loop {
+      if is_target(&self.receive()?) {
+        return Ok(());
+      }
+    }
+  }
+
+  pub fn print(&mut self, html: &str, options: &PdfOptions) -> Result<Vec<u8>, PdfError> {
+    self.pages += 1;
+    let page = self.profile.join(format!("page-{}.html", self.pages));
+    fs::write(&page, html)?;
+    self.events.clear();
+
+    let target = self.call(None, "Target.createTarget", json!({ "url": "about:blank" }))?;
+    let target_id = target["targetId"].as_str().unwrap_or_default().to_string();
+    let attached = self.call(
+      None,
+      "Target.attachToTarget",
+      json!({ "targetId": target_id, "flatten": true }),
+    )?;
+    let session = attached["sessionId"]
+      .as_str()
+      .unwrap_or_default()
+      .to_string();
+
+    let res = self
+      .call(Some(&session), "Page.enable", json!({}))
+      .and_then(|_| {
+        self.call(
+          Some(&session),
+          "Page.navigate",
+          json!({ "url": file_url(&page) }),
+        )
+      })
+      .and_then(|_| self.wait_for(&session, "Page.loadEventFired"))
+      .and_then(|_| self.call(Some(&session), "Page.printToPDF", print_params(options)))
+      .and_then(|res| {
+        base64::decode(res["data"].as_str().unwrap_or_default())
+          .map_err(|err| PdfError::Protocol(err.to_string()))
+      });
+    if let Err(err) = self.call(None, "Target.closeTarget", json!({ "targetId": target_id })) {
+      println!("WARNING: failed to close the page\n{}", err.to_string());
+    }
+    let _ = fs::remove_file(page);
+    res
+  }
+}
+impl Drop for Browser {
+  fn drop(&mut self) {
+    let _ = self.socket.close(None);
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+    let _ = fs::remove_dir_all(&self.profile);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn print_params_test() {
+    let mut options = PdfOptions {
+      page_numbers: true,
+      header: String::from("{{title}} <draft>"),
+      ..PdfOptions::default()
+    };
+    let params = print_params(&options);
+    assert_eq!(params["paperWidth"], json!(8.27));
+    assert_eq!(params["marginTop"], json!(20.0 / MM_PER_INCH));
+    assert_eq!(params["displayHeaderFooter"], json!(true));
+    assert!(params["headerTemplate"]
+      .as_str()
+      .unwrap()
+      .contains("<span class=\"title\"></span> &lt;draft&gt;"));
+    assert!(params["footerTemplate"]
+      .as_str()
+      .unwrap()
+      .contains("<span class=\"pageNumber\"></span> / <span class=\"totalPages\"></span>"));
+
+    options.header = String::new();
+    options.page_numbers = false;
+    let params = print_params(&options);
+    assert_eq!(params["displayHeaderFooter"], json!(false));
+    assert_eq!(params["footerTemplate"], json!("<span></span>"));
+
+    assert_eq!(
+      file_url(Path::new("C:\\notes\\my note#1.html")),
+      "file:///C:/notes/my%20note%231.html"
+    );
+  }
+}
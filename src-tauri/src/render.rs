@@ -0,0 +1,659 @@
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use percent_encoding::percent_decode_str;
+use super::macros::{self, Macro};
+use super::links;
+use super::math;
+use std::collections::HashMap;
+
+/*
+ * markdown to html on the rust side, so that exporting doesn't need the webview.
+ * commonmark with gfm tables, footnotes, strikethrough and task lists.
+ *
+ * math and diagrams are not rendered here but passed through in the same shape as the frontend produces,
+ * then template.js renders them on load. only a standalone export has math in mathml instead -- see math.rs
+ * - `$...$` => <span class="math math-inline">
+ * - `$$...$$` => <div class="math math-display">
+ * - fenced code or container directive of mermaid => <div class="mermaid">
+ * - fenced code or container directive of d3 => <div class="d3">
+ */
+
+// private use characters, which never appear in notes and pass through the markdown parser as text
+static PLACEHOLDER: char = '\u{E000}';
+static DIAGRAMS: [&str; 2] = ["mermaid", "d3"];
+
+pub fn escape_html(src: &str) -> String {
+  let mut res = String::with_capacity(src.len());
+  for c in src.chars() {
+    match c {
+      '&' => res.push_str("&amp;"),
+      '<' => res.push_str("&lt;"),
+      '>' => res.push_str("&gt;"),
+      '"' => res.push_str("&quot;"),
+      '\'' => res.push_str("&#39;"),
+      c => res.push(c),
+    }
+  }
+  res
+}
+
+/*
+ * github style anchor of a heading, which rehype-slug gives in the frontend.
+ */
+pub fn anchor(text: &str) -> String {
+  text
+    .trim()
+    .to_lowercase()
+    .chars()
+    .filter_map(|c| match c {
+      ' ' => Some('-'),
+      c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+      _ => None,
+    })
+    .collect()
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Math {
+  pub src: String,
+  pub display: bool,
+}
+
+pub fn fence_of(line: &str) -> Option<(char, usize)> {
+  let trimmed = line.trim_start();
+  let c = trimmed.chars().next()?;
+  if c != '`' && c != '~' {
+    return None;
+  }
+  let len = trimmed.chars().take_while(|&x| x == c).count();
+  if 3 <= len && line.len() - trimmed.len() < 4 {
+    Some((c, len))
+  } else {
+    None
+  }
+}
+
+/*
+ * whether the line closes the fence opened with `opened`: the same character, at least as long and no info string.
+ */
+pub fn closes_fence(line: &str, (c, len): (char, usize)) -> bool {
+  fence_of(line).map_or(false, |(x, l)| x == c && len <= l)
+    && line.trim_start().trim_start_matches(c).trim().is_empty()
+}
+
+fn placeholder(maths: &mut Vec<Math>, src: &str, display: bool) -> String {
+  maths.push(Math {
+    src: src.to_string(),
+    display,
+  });
+  format!("{}{}{}", PLACEHOLDER, maths.len() - 1, PLACEHOLDER)
+}
+
+fn extract_inline(line: &str, maths: &mut Vec<Math>) -> String {
+  let chars = line.chars().collect::<Vec<_>>();
+  let mut res = String::new();
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '\\' if i + 1 < chars.len() => {
+        res.push(chars[i]);
+        res.push(chars[i + 1]);
+        i += 2;
+      }
+      '`' => {
+        // code span, skipped as is until the backtick run of the same length
+        let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+        let mut j = i + run;
+        let mut end = None;
+        while j < chars.len() {
+          let r = chars[j..].iter().take_while(|&&c| c == '`').count();
+          if r == run {
+            end = Some(j + r);
+            break;
+          }
+          j += r.max(1);
+        }
+        let end = end.unwrap_or(i + run);
+        res.extend(chars[i..end].iter());
+        i = end;
+      }
+      '$' => {
+        let delim = if chars.get(i + 1) == Some(&'$') { 2 } else { 1 };
+        let start = i + delim;
+        let close = (start..chars.len()).find(|&j| {
+          chars[j..].iter().take(delim).all(|&c| c == '$')
+            && chars[j - 1] != '\\'
+            && (delim == 2 || !chars[j - 1].is_whitespace())
+            && (delim == 2 || !chars.get(j + 1).map_or(false, |c| c.is_ascii_digit()))
+        });
+        match close {
+          Some(end) if start < end && (delim == 2 || !chars[start].is_whitespace()) => {
+            let src = chars[start..end].iter().collect::<String>();
+            res.push_str(&placeholder(maths, &src, false));
+            i = end + delim;
+          }
+          _ => {
+            res.extend(chars[i..start].iter());
+            i = start;
+          }
+        }
+      }
+      c => {
+        res.push(c);
+        i += 1;
+      }
+    }
+  }
+  res
+}
+
+/*
+ * replace math outside of code with placeholders, so that the markdown parser doesn't touch `_`, `*` and `\` in it.
+ */
+fn extract_math(md: &str) -> (String, Vec<Math>) {
+  let (md, maths, _) = extract_math_lines(md);
+  (md, maths)
+}
+
+/*
+ * extract_math, along with the 1-based line in `md` of each line in the result.
+ */
+fn extract_math_lines(md: &str) -> (String, Vec<Math>, Vec<usize>) {
+  let mut maths = vec![];
+  let mut lines = vec![];
+  let mut origins = vec![];
+  let mut fence: Option<(char, usize)> = None;
+  let mut display: Option<(usize, Vec<&str>)> = None;
+
+  for (i, line) in md.lines().enumerate() {
+    let n = i + 1;
+    if let Some(opened) = fence {
+      if closes_fence(line, opened) {
+        fence = None;
+      }
+      lines.push(line.to_string());
+      origins.push(n);
+      continue;
+    }
+    if let Some((_, block)) = display.as_mut() {
+      if line.trim() == "$$" {
+        let src = block.join("\n");
+        display = None;
+        lines.push(String::new());
+        lines.push(placeholder(&mut maths, &src, true));
+        lines.push(String::new());
+        origins.extend(&[n, n, n]);
+      } else {
+        block.push(line);
+      }
+      continue;
+    }
+
+    let trimmed = line.trim();
+    if let Some(f) = fence_of(line) {
+      fence = Some(f);
+      lines.push(line.to_string());
+      origins.push(n);
+    } else if trimmed == "$$" {
+      display = Some((n, vec![]));
+    } else if 4 < trimmed.len() && trimmed.starts_with("$$") && trimmed.ends_with("$$") {
+      lines.push(String::new());
+      lines.push(placeholder(
+        &mut maths,
+        &trimmed[2..trimmed.len() - 2],
+        true,
+      ));
+      lines.push(String::new());
+      origins.extend(&[n, n, n]);
+    } else {
+      lines.push(extract_inline(line, &mut maths));
+      origins.push(n);
+    }
+  }
+  // unclosed display math is left as it was written
+  if let Some((n, block)) = display {
+    lines.push(String::from("$$"));
+    origins.push(n);
+    for (i, l) in block.into_iter().enumerate() {
+      lines.push(extract_inline(l, &mut maths));
+      origins.push(n + 1 + i);
+    }
+  }
+  (lines.join("\n"), maths, origins)
+}
+
+fn restore_math(html: &str, maths: &[Math], mathml: bool) -> String {
+  let mut res = html.to_string();
+  for (i, math) in maths.iter().enumerate() {
+    let key = format!("{}{}{}", PLACEHOLDER, i, PLACEHOLDER);
+    let content = if mathml {
+      math::to_mathml(&math.src, math.display)
+    } else {
+      escape_html(&math.src)
+    };
+    if math.display {
+      res = res.replace(
+        &format!("<p>{}</p>", key),
+        &format!("<div class=\"math math-display\">{}</div>", content),
+      );
+    }
+    res = res.replace(
+      &key,
+      &format!(
+        "<span class=\"math math-{}\">{}</span>",
+        if math.display { "display" } else { "inline" },
+        content
+      ),
+    );
+  }
+  res
+}
+
+fn restore_plain(text: &str, maths: &[Math]) -> String {
+  let mut res = text.to_string();
+  for (i, math) in maths.iter().enumerate() {
+    res = res.replace(&format!("{}{}{}", PLACEHOLDER, i, PLACEHOLDER), &math.src);
+  }
+  res
+}
+
+/*
+ * `:::mermaid` and `:::d3` container directives used in the editor are rewritten into fenced code.
+ */
+fn rewrite_directives(md: &str) -> String {
+  let mut lines = vec![];
+  let mut in_directive = false;
+  for line in md.lines() {
+    let trimmed = line.trim();
+    if !in_directive && trimmed.starts_with(":::") {
+      let name = trimmed.trim_start_matches(':').trim();
+      if DIAGRAMS.contains(&name) {
+        in_directive = true;
+        lines.push(format!("```{}", name));
+        continue;
+      }
+    } else if in_directive
+      && trimmed.starts_with(":::")
+      && trimmed.trim_start_matches(':').is_empty()
+    {
+      in_directive = false;
+      lines.push(String::from("```"));
+      continue;
+    }
+    lines.push(line.to_string());
+  }
+  lines.join("\n")
+}
+
+/*
+ * for outputs other than html: the markdown with math replaced by placeholders, and the math in order.
+ * the placeholders are found in text events, and `split_math` takes them apart -- see latex.rs
+ */
+pub fn extract(md: &str) -> (String, Vec<Math>) {
+  extract_math(&links::to_markdown(&rewrite_directives(md)))
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Segment<'a> {
+  Text(&'a str),
+  Math(&'a Math),
+}
+
+pub fn split_math<'a>(text: &'a str, maths: &'a [Math]) -> Vec<Segment<'a>> {
+  text
+    .split(PLACEHOLDER)
+    .enumerate()
+    .filter_map(|(i, part)| {
+      if i % 2 == 0 {
+        Some(Segment::Text(part)).filter(|_| !part.is_empty())
+      } else {
+        match part.parse::<usize>().ok().and_then(|n| maths.get(n)) {
+          Some(math) => Some(Segment::Math(math)),
+          None => Some(Segment::Text(part)),
+        }
+      }
+    })
+    .collect()
+}
+
+pub fn is_diagram(lang: &str) -> bool {
+  DIAGRAMS.contains(&lang)
+}
+
+pub fn options() -> Options {
+  let mut options = Options::empty();
+  options.insert(Options::ENABLE_TABLES);
+  options.insert(Options::ENABLE_FOOTNOTES);
+  options.insert(Options::ENABLE_STRIKETHROUGH);
+  options.insert(Options::ENABLE_TASKLISTS);
+  options
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Heading {
+  pub level: u32,
+  pub id: String,
+  pub text: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rendered {
+  pub html: String,
+  pub headings: Vec<Heading>,
+}
+
+/*
+ * give ids to headings, turn diagram code blocks into divs,
+ * and rewrite link and image destinations resolved by `resolve` and `embed`.
+ */
+fn transform<'a, F: Fn(&str) -> Option<String>, G: Fn(&str) -> Option<String>>(
+  events: Vec<Event<'a>>,
+  maths: &[Math],
+  resolve: &F,
+  embed: &G,
+) -> (Vec<Event<'a>>, Vec<Heading>) {
+  let mut res: Vec<Event> = vec![];
+  let mut headings = vec![];
+  let mut used = HashMap::<String, usize>::new();
+  let mut heading: Option<(u32, usize, String)> = None;
+  let mut diagram: Option<(String, String)> = None;
+
+  for event in events {
+    match event {
+      Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref lang)))
+        if DIAGRAMS.contains(&lang.as_ref()) =>
+      {
+        diagram = Some((lang.to_string(), String::new()));
+      }
+      Event::Text(ref text) if diagram.is_some() => {
+        if let Some((_, src)) = diagram.as_mut() {
+          src.push_str(text);
+        }
+      }
+      Event::End(Tag::CodeBlock(_)) if diagram.is_some() => {
+        if let Some((class, src)) = diagram.take() {
+          res.push(Event::Html(CowStr::from(format!(
+            "<div class=\"{}\">{}</div>\n",
+            class,
+            escape_html(src.trim_end())
+          ))));
+        }
+      }
+      Event::Start(Tag::Link(kind, dest, title)) => {
+        let dest = match resolve_link(&dest, resolve) {
+          Some(resolved) => CowStr::from(resolved),
+          None => dest,
+        };
+        res.push(Event::Start(Tag::Link(kind, dest, title)));
+      }
+      Event::Start(Tag::Image(kind, dest, title)) => {
+        let dest = match resolve_link(&dest, embed) {
+          Some(resolved) => CowStr::from(resolved),
+          None => dest,
+        };
+        res.push(Event::Start(Tag::Image(kind, dest, title)));
+      }
+      Event::Start(Tag::Heading(level)) => {
+        heading = Some((level, res.len(), String::new()));
+        res.push(Event::Start(Tag::Heading(level)));
+      }
+      Event::Text(ref text) | Event::Code(ref text) if heading.is_some() => {
+        if let Some((_, _, acc)) = heading.as_mut() {
+          acc.push_str(text);
+        }
+        res.push(event.clone());
+      }
+      Event::End(Tag::Heading(level)) => {
+        if let Some((_, start, text)) = heading.take() {
+          let text = restore_plain(&text, maths);
+          let base = anchor(&text);
+          let count = used.entry(base.clone()).or_insert(0);
+          let id = if *count == 0 {
+            base
+          } else {
+            format!("{}-{}", base, count)
+          };
+          *count += 1;
+          res[start] = Event::Html(CowStr::from(format!(
+            "<h{} id=\"{}\">",
+            level,
+            escape_html(&id)
+          )));
+          res.push(Event::Html(CowStr::from(format!("</h{}>\n", level))));
+          headings.push(Heading { level, id, text });
+        } else {
+          res.push(Event::End(Tag::Heading(level)));
+        }
+      }
+      event => res.push(event),
+    }
+  }
+  (res, headings)
+}
+
+/*
+ * a link destination is passed to `resolve` without its fragment and percent decoded,
+ * and the fragment is appended again to the resolved one.
+ */
+fn resolve_link<F: Fn(&str) -> Option<String>>(dest: &str, resolve: &F) -> Option<String> {
+  let (path, fragment) = match dest.find('#') {
+    Some(i) => (&dest[..i], &dest[i..]),
+    None => (dest, ""),
+  };
+  if path.is_empty() {
+    return None;
+  }
+  let path = percent_decode_str(path).decode_utf8_lossy();
+  resolve(path.trim_start_matches("./")).map(|resolved| format!("{}{}", resolved, fragment))
+}
+
+/*
+ * render the body of a document into an html fragment with its headings.
+ * front matter should be stripped before.
+ */
+pub fn render(md: &str) -> Rendered {
+  render_with(md, |_| None, &[])
+}
+
+/*
+ * same as render, but the links to other notes are rewritten by `resolve` -- see site.rs
+ * and the macros are expanded in math -- see macros.rs
+ */
+pub fn render_with<F: Fn(&str) -> Option<String>>(
+  md: &str,
+  resolve: F,
+  macros: &[Macro],
+) -> Rendered {
+  render_inner(md, &resolve, &|_: &str| None, macros, false)
+}
+
+/*
+ * for a static site, where the links to other notes point to the pages and the images are files of the site -- see site.rs
+ */
+pub fn render_site<F: Fn(&str) -> Option<String>, G: Fn(&str) -> Option<String>>(
+  md: &str,
+  resolve: F,
+  embed: G,
+  macros: &[Macro],
+) -> Rendered {
+  render_inner(md, &resolve, &embed, macros, false)
+}
+
+/*
+ * for a single file export, which must be readable offline.
+ * math is rendered into mathml, and image sources are rewritten by `embed`, e.g. into data uris -- see standalone.rs
+ */
+pub fn render_standalone<G: Fn(&str) -> Option<String>>(
+  md: &str,
+  embed: G,
+  macros: &[Macro],
+) -> Rendered {
+  render_inner(md, &|_: &str| None, &embed, macros, true)
+}
+
+/*
+ * for an epub, where the links to other notes point to chapters and the images are files in the book -- see epub.rs
+ * math is rendered into mathml as for a standalone export.
+ */
+pub fn render_book<F: Fn(&str) -> Option<String>, G: Fn(&str) -> Option<String>>(
+  md: &str,
+  resolve: F,
+  embed: G,
+  macros: &[Macro],
+) -> Rendered {
+  render_inner(md, &resolve, &embed, macros, true)
+}
+
+fn render_inner<F: Fn(&str) -> Option<String>, G: Fn(&str) -> Option<String>>(
+  md: &str,
+  resolve: &F,
+  embed: &G,
+  macros: &[Macro],
+  mathml: bool,
+) -> Rendered {
+  let (md, mut maths) = extract_math(&links::to_markdown(&rewrite_directives(md)));
+  for math in maths.iter_mut() {
+    match macros::expand(&math.src, macros) {
+      Ok(expanded) => math.src = expanded,
+      Err(err) => println!("WARNING: macros are not expanded\n{}", err.to_string()),
+    }
+  }
+  let (events, headings) = transform(
+    Parser::new_ext(&md, options()).collect(),
+    &maths,
+    resolve,
+    embed,
+  );
+  let mut html = String::new();
+  html::push_html(&mut html, events.into_iter());
+  Rendered {
+    html: restore_math(&html, &maths, mathml),
+    headings,
+  }
+}
+
+/*
+ * a heading in the outline of a document, which is kept in the index -- see model.rs
+ */
+#[derive(Debug, PartialEq, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Section {
+  pub level: u32,
+  pub text: String,
+  pub anchor: String,
+  pub line: usize, //1-based, in the body without front matter
+}
+
+/*
+ * the headings of a body with their lines.
+ * the lines come from parsing the same markdown as render does, mapped back to the lines of the body,
+ * so the text and the anchor are those of the rendered headings.
+ */
+pub fn outline(md: &str) -> Vec<Section> {
+  let (src, _, origins) = extract_math_lines(&links::to_markdown(&rewrite_directives(md)));
+  let lines = Parser::new_ext(&src, options())
+    .into_offset_iter()
+    .filter_map(|(event, range)| match event {
+      Event::Start(Tag::Heading(_)) => origins
+        .get(src[..range.start].matches('\n').count())
+        .copied(),
+      _ => None,
+    })
+    .collect::<Vec<_>>();
+  render(md)
+    .headings
+    .into_iter()
+    .enumerate()
+    .map(|(i, heading)| Section {
+      level: heading.level,
+      text: heading.text,
+      anchor: heading.id,
+      line: lines.get(i).copied().unwrap_or(0),
+    })
+    .collect()
+}
+
+pub fn markdown_to_html(md: &str) -> String {
+  render(md).html
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn render_gfm_test() {
+    let html = markdown_to_html(
+      "# Title\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n- [ ] todo\n\nnote[^1]\n\n[^1]: footnote",
+    );
+    assert!(html.contains("<h1 id=\"title\">Title</h1>"));
+    assert!(html.contains("<table>"));
+    assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+    assert!(html.contains("class=\"footnote-definition\""));
+  }
+
+  #[test]
+  fn render_math_test() {
+    let html = markdown_to_html(
+      "inline $a_1 * b_1$ and `$not math$`, costs \\$5 or $5\n\n$$\n\\sum_{i} x_i\n$$",
+    );
+    assert!(html.contains("<span class=\"math math-inline\">a_1 * b_1</span>"));
+    assert!(html.contains("<code>$not math$</code>"));
+    assert!(html.contains("<div class=\"math math-display\">\\sum_{i} x_i</div>"));
+    assert!(html.contains("$5"));
+  }
+
+  #[test]
+  fn render_diagram_test() {
+    let html = markdown_to_html(
+      "```mermaid\ngraph TD\nA-->B\n```\n\n:::d3\nfunction\nx^2\n:::\n\n```rust\nlet a = 1;\n```",
+    );
+    assert!(html.contains("<div class=\"mermaid\">graph TD\nA--&gt;B</div>"));
+    assert!(html.contains("<div class=\"d3\">function\nx^2</div>"));
+    assert!(html.contains("<code class=\"language-rust\">"));
+  }
+
+  #[test]
+  fn heading_anchor_test() {
+    let html = markdown_to_html("## Hello, World!\n\n## Hello, World!\n\n### 固有値 `λ`");
+    assert!(html.contains("<h2 id=\"hello-world\">"));
+    assert!(html.contains("<h2 id=\"hello-world-1\">"));
+    assert!(html.contains("<h3 id=\"固有値-λ\">"));
+  }
+
+  #[test]
+  fn headings_test() {
+    let rendered = render("# Intro\n\ntext\n\n## Case $n_1$\n\n```\n# not heading\n```");
+    assert_eq!(
+      rendered.headings,
+      vec![
+        Heading {
+          level: 1,
+          id: String::from("intro"),
+          text: String::from("Intro"),
+        },
+        Heading {
+          level: 2,
+          id: String::from("case-n_1"),
+          text: String::from("Case n_1"),
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn outline_test() {
+    let sections = outline(
+      "# Intro\n\n:::mermaid\n# not heading\n:::\n\nSetext [[Other]]\n---\n\n$$\n# x\n---\n$$\n\n## Case $n_1$\n",
+    );
+    assert_eq!(
+      sections
+        .iter()
+        .map(|s| (s.level, s.text.as_str(), s.anchor.as_str(), s.line))
+        .collect::<Vec<_>>(),
+      vec![
+        (1, "Intro", "intro", 1),
+        (2, "Setext Other", "setext-other", 7),
+        (2, "Case n_1", "case-n_1", 15),
+      ]
+    );
+  }
+}
@@ -0,0 +1,482 @@
+use super::{
+  constants::{TARGET_EXTENTION, TIME_FORMAT},
+  layout::slugify,
+  macros,
+  model::Meta,
+  render::{self, escape_html},
+  standalone::local_file,
+  template::{self, Context, Value},
+};
+use chrono::{offset::TimeZone, DateTime, Local};
+use std::{
+  cell::RefCell,
+  collections::{BTreeMap, HashMap},
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+/*
+ * static site from the documents.
+ * every page is `<dir>/index.html` so that the urls end with the directory.
+ * - index.html => all the notes, tags and authors
+ * - notes/<slug>/ => a note rendered with its template
+ * - assets/<n>.<ext> => local images and attachments of the notes. remote ones are left as they are
+ * - tags/<slug>/, authors/<slug>/ => the notes with the tag or by the author
+ * - sitemap.xml, feed.xml (atom) => only when base_url is given, since both need absolute urls
+ *
+ * links between notes written with the title, the body path or the hashed names are resolved to the pretty urls.
+ */
+
+static NOTES: &str = "notes";
+static TAGS: &str = "tags";
+static AUTHORS: &str = "authors";
+static ASSETS: &str = "assets";
+static SITEMAP: &str = "sitemap.xml";
+static FEED: &str = "feed.xml";
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SiteOptions {
+  pub title: String,
+  pub base_url: String, //e.g. https://example.com/notes. if empty, sitemap and feed are not generated
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Report {
+  pub notes: usize,
+  pub pages: usize,
+}
+
+#[derive(Debug)]
+pub enum SiteError {
+  NotADirectory,
+  Template(String),
+  Io(io::Error),
+}
+impl ToString for SiteError {
+  fn to_string(&self) -> String {
+    match self {
+      SiteError::NotADirectory => String::from("given path is not a directory"),
+      SiteError::Template(err) => err.clone(),
+      SiteError::Io(err) => err.to_string(),
+    }
+  }
+}
+impl From<io::Error> for SiteError {
+  fn from(err: io::Error) -> Self {
+    SiteError::Io(err)
+  }
+}
+
+/*
+ * slugs unique among the names, numbered in order of appearance like layout.rs does.
+ */
+pub fn unique_slugs<'a, I: Iterator<Item = &'a str>>(names: I) -> HashMap<String, String> {
+  let mut res = HashMap::new();
+  let mut used = HashMap::<String, usize>::new();
+  for name in names {
+    if res.contains_key(name) {
+      continue;
+    }
+    let base = slugify(name);
+    let count = used.entry(base.clone()).or_insert(0);
+    *count += 1;
+    let slug = match *count {
+      1 => base,
+      n => format!("{}-{}", base, n),
+    };
+    res.insert(name.to_string(), slug);
+  }
+  res
+}
+
+pub fn parse_time(time: &str) -> Option<DateTime<Local>> {
+  Local.datetime_from_str(time, TIME_FORMAT).ok()
+}
+
+pub fn last_modified(meta: &Meta) -> Option<DateTime<Local>> {
+  meta
+    .get_updated_at()
+    .and_then(parse_time)
+    .or_else(|| parse_time(meta.get_created_at()))
+}
+
+fn write_page(out: &Path, dir: &str, html: &str) -> io::Result<()> {
+  let dir = if dir.is_empty() {
+    out.to_path_buf()
+  } else {
+    out.join(dir)
+  };
+  fs::create_dir_all(&dir)?;
+  fs::write(dir.join("index.html"), html)
+}
+
+struct Site<'a> {
+  notes: &'a [(Meta, String)],
+  options: &'a SiteOptions,
+  note_slugs: HashMap<String, String>,
+  tag_slugs: HashMap<String, String>,
+  author_slugs: HashMap<String, String>,
+  links: HashMap<String, String>,
+}
+impl<'a> Site<'a> {
+  fn new(notes: &'a [(Meta, String)], options: &'a SiteOptions) -> Site<'a> {
+    let note_slugs = unique_slugs(notes.iter().map(|(m, _)| m.get_filename()));
+    let tag_slugs = unique_slugs(
+      notes
+        .iter()
+        .flat_map(|(m, _)| m.get_tags().iter().map(String::as_str)),
+    );
+    let author_slugs = unique_slugs(
+      notes
+        .iter()
+        .map(|(m, _)| m.get_author())
+        .filter(|a| !a.is_empty()),
+    );
+
+    let mut links = HashMap::new();
+    for (meta, _) in notes {
+      let dir = format!("{}/{}/", NOTES, note_slugs[meta.get_filename()]);
+      for key in vec![
+        meta.get_filename().to_string(),
+        format!("{}{}", meta.get_filename(), TARGET_EXTENTION),
+        meta.body_path(),
+        meta.get_hashed_filename(),
+        meta.get_hashed_html_name(),
+      ] {
+        links.entry(key).or_insert_with(|| dir.clone());
+      }
+    }
+
+    Site {
+      notes,
+      options,
+      note_slugs,
+      tag_slugs,
+      author_slugs,
+      links,
+    }
+  }
+
+  fn note_dir(&self, meta: &Meta) -> String {
+    format!("{}/{}", NOTES, self.note_slugs[meta.get_filename()])
+  }
+
+  fn with_site(&self, mut context: Context, root: &str) -> Context {
+    if !self.options.title.is_empty() {
+      context.insert("site", Value::Text(self.options.title.clone()));
+    }
+    context.insert("root", Value::Text(root.to_string()));
+    context
+  }
+
+  fn list_notes(&self, metas: &[&Meta], root: &str) -> String {
+    let mut res = String::from("<ul class=\"notes\">");
+    for meta in metas {
+      res.push_str(&format!(
+        "<li><a href=\"{}{}/\">{}</a> <time>{}</time></li>",
+        root,
+        escape_html(&self.note_dir(meta)),
+        escape_html(meta.get_filename()),
+        escape_html(meta.get_created_at())
+      ));
+    }
+    res.push_str("</ul>");
+    res
+  }
+
+  fn list_groups(&self, title: &str, dir: &str, slugs: &HashMap<String, String>) -> String {
+    let sorted = slugs.iter().collect::<BTreeMap<_, _>>();
+    let mut res = format!("<h2>{}</h2><ul class=\"{}\">", escape_html(title), dir);
+    for (name, slug) in sorted {
+      res.push_str(&format!(
+        "<li><a href=\"{}/{}/\">{}</a></li>",
+        dir,
+        escape_html(slug),
+        escape_html(name)
+      ));
+    }
+    res.push_str("</ul>");
+    res
+  }
+
+  /*
+   * notes in the newest first order.
+   */
+  fn sorted(&self) -> Vec<&'a Meta> {
+    let mut metas = self.notes.iter().map(|(m, _)| m).collect::<Vec<_>>();
+    metas.sort_by_key(|m| std::cmp::Reverse(parse_time(m.get_created_at())));
+    metas
+  }
+
+  fn build(
+    &self,
+    target_dir: &Path,
+    global: &HashMap<String, String>,
+    lang: &str,
+    out: &Path,
+  ) -> Result<usize, SiteError> {
+    let mut pages = 0;
+    let root = "../../";
+
+    // source path => name under assets, copied once however many notes show it
+    let assets = RefCell::new(HashMap::<PathBuf, String>::new());
+    for (meta, body) in self.notes {
+      let engine = template::load(target_dir, meta.get_template()).map_err(SiteError::Template)?;
+      let body_path = target_dir.join(meta.body_path());
+      let dirs = body_path
+        .parent()
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(std::iter::once(target_dir.to_path_buf()))
+        .collect::<Vec<_>>();
+      let rendered = render::render_site(
+        body,
+        |dest| self.links.get(dest).map(|dir| format!("{}{}", root, dir)),
+        |src| {
+          let path = local_file(src, &dirs)?;
+          let mut assets = assets.borrow_mut();
+          let n = assets.len() + 1;
+          let name = assets.entry(path.clone()).or_insert_with(|| {
+            let ext = path
+              .extension()
+              .map(|ext| ext.to_string_lossy().to_lowercase())
+              .unwrap_or_else(|| String::from("bin"));
+            format!("{}.{}", n, ext)
+          });
+          Some(format!("{}{}/{}", root, ASSETS, name))
+        },
+        &macros::merge(global, meta.get_shortcut()),
+      );
+      let context = self.with_site(template::context(meta, &rendered, lang), root);
+      write_page(out, &self.note_dir(meta), &engine.render(&context))?;
+      pages += 1;
+    }
+
+    let assets = assets.into_inner();
+    if !assets.is_empty() {
+      fs::create_dir_all(out.join(ASSETS))?;
+    }
+    for (path, name) in assets {
+      fs::copy(path, out.join(ASSETS).join(name))?;
+    }
+
+    let engine = template::load(target_dir, None).map_err(SiteError::Template)?;
+    let sorted = self.sorted();
+    let groups: [(
+      &str,
+      &str,
+      &HashMap<String, String>,
+      fn(&Meta, &str) -> bool,
+    ); 2] = [
+      (TAGS, "Tag", &self.tag_slugs, |m, tag| {
+        m.get_tags().iter().any(|t| t == tag)
+      }),
+      (AUTHORS, "Author", &self.author_slugs, |m, author| {
+        m.get_author() == author
+      }),
+    ];
+    for (dir, label, slugs, is_member) in groups.iter() {
+      for (name, slug) in slugs.iter() {
+        let members = sorted
+          .iter()
+          .filter(|m| is_member(m, name))
+          .cloned()
+          .collect::<Vec<_>>();
+        let title = format!("{}: {}", label, name);
+        let context = self.with_site(
+          template::page_context(&title, self.list_notes(&members, root), lang),
+          root,
+        );
+        write_page(out, &format!("{}/{}", dir, slug), &engine.render(&context))?;
+        pages += 1;
+      }
+    }
+
+    let mut body = self.list_notes(&sorted, "");
+    body.push_str(&self.list_groups("Tags", TAGS, &self.tag_slugs));
+    body.push_str(&self.list_groups("Authors", AUTHORS, &self.author_slugs));
+    let title = if self.options.title.is_empty() {
+      "Notes"
+    } else {
+      &self.options.title
+    };
+    let context = self.with_site(template::page_context(title, body, lang), "./");
+    write_page(out, "", &engine.render(&context))?;
+    pages += 1;
+
+    if !self.options.base_url.is_empty() {
+      fs::write(out.join(SITEMAP), self.sitemap())?;
+      fs::write(out.join(FEED), self.feed())?;
+    }
+    Ok(pages)
+  }
+
+  fn url(&self, dir: &str) -> String {
+    format!("{}/{}", self.options.base_url.trim_end_matches('/'), dir)
+  }
+
+  fn sitemap(&self) -> String {
+    let mut urls = vec![(self.url(""), None)];
+    for meta in self.sorted() {
+      urls.push((
+        self.url(&format!("{}/", self.note_dir(meta))),
+        last_modified(meta),
+      ));
+    }
+    for (dir, slugs) in [(TAGS, &self.tag_slugs), (AUTHORS, &self.author_slugs)].iter() {
+      for slug in slugs.values() {
+        urls.push((self.url(&format!("{}/{}/", dir, slug)), None));
+      }
+    }
+
+    let mut res = String::from(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+       <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for (loc, lastmod) in urls {
+      res.push_str(&format!("  <url><loc>{}</loc>", escape_html(&loc)));
+      if let Some(lastmod) = lastmod {
+        res.push_str(&format!("<lastmod>{}</lastmod>", lastmod.to_rfc3339()));
+      }
+      res.push_str("</url>\n");
+    }
+    res.push_str("</urlset>\n");
+    res
+  }
+
+  fn feed(&self) -> String {
+    let mut entries = self
+      .notes
+      .iter()
+      .map(|(m, _)| (m, last_modified(m).unwrap_or_else(Local::now)))
+      .collect::<Vec<_>>();
+    entries.sort_by_key(|(_, updated)| std::cmp::Reverse(*updated));
+    let updated = entries
+      .first()
+      .map(|(_, updated)| *updated)
+      .unwrap_or_else(Local::now);
+
+    let mut res = format!(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+       <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+       \x20 <title>{}</title>\n\
+       \x20 <link href=\"{}\"/>\n\
+       \x20 <link rel=\"self\" href=\"{}\"/>\n\
+       \x20 <id>{}</id>\n\
+       \x20 <updated>{}</updated>\n",
+      escape_html(&self.options.title),
+      escape_html(&self.url("")),
+      escape_html(&self.url(FEED)),
+      escape_html(&self.url("")),
+      updated.to_rfc3339()
+    );
+    for (meta, updated) in entries {
+      let url = escape_html(&self.url(&format!("{}/", self.note_dir(meta))));
+      res.push_str(&format!(
+        "  <entry>\n    <title>{}</title>\n    <link href=\"{}\"/>\n    <id>{}</id>\n    <updated>{}</updated>\n",
+        escape_html(meta.get_filename()),
+        url,
+        url,
+        updated.to_rfc3339()
+      ));
+      if !meta.get_author().is_empty() {
+        res.push_str(&format!(
+          "    <author><name>{}</name></author>\n",
+          escape_html(meta.get_author())
+        ));
+      }
+      for tag in meta.get_tags() {
+        res.push_str(&format!("    <category term=\"{}\"/>\n", escape_html(tag)));
+      }
+      res.push_str("  </entry>\n");
+    }
+    res.push_str("</feed>\n");
+    res
+  }
+}
+
+/*
+ * write the site of the notes, pairs of meta and body without front matter, into `out`.
+ * the existing files in `out` are overwritten but never removed.
+ * global is the macros in the setting, under the ones of each note.
+ */
+pub fn build(
+  notes: &[(Meta, String)],
+  options: &SiteOptions,
+  target_dir: &Path,
+  global: &HashMap<String, String>,
+  lang: &str,
+  out: &Path,
+) -> Result<Report, SiteError> {
+  if !out.is_dir() {
+    return Err(SiteError::NotADirectory);
+  }
+  let pages = Site::new(notes, options).build(target_dir, global, lang, out)?;
+  Ok(Report {
+    notes: notes.len(),
+    pages,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::{get_random_key, Setup};
+
+  #[test]
+  fn build_site_test() {
+    let out = std::env::temp_dir().join(format!("notex-site-{}", get_random_key()));
+    fs::create_dir_all(&out).unwrap();
+    let mut setup = Setup::init();
+    let first = setup.get_meta().clone();
+    setup.move_filename_to("Second Note");
+    let second = setup.get_meta().clone();
+    let notes = vec![
+      (
+        first.clone(),
+        String::from(
+          "[next](Second%20Note.md#part) and [web](https://example.com) ![fig](fig.png) ![remote](https://example.com/a.png)",
+        ),
+      ),
+      (
+        second,
+        format!("[back]({}) ![again](./fig.png)\n\n## part", first.get_hashed_filename()),
+      ),
+    ];
+    let options = SiteOptions {
+      title: String::from("team notes"),
+      base_url: String::from("https://example.com/site/"),
+    };
+
+    fs::write(out.join("fig.png"), "png").unwrap();
+    let report = build(&notes, &options, &out, &HashMap::new(), "en", &out).unwrap();
+    // 2 notes, 3 tags, 1 author and the index
+    assert_eq!(report, Report { notes: 2, pages: 7 });
+
+    let first_page = fs::read_to_string(out.join("notes/test-file/index.html")).unwrap();
+    assert!(first_page.contains("<a href=\"../../notes/second-note/#part\">next</a>"));
+    assert!(first_page.contains("<a href=\"https://example.com\">web</a>"));
+    assert!(first_page.contains("<a href=\"../../\">team notes</a>"));
+    assert!(first_page.contains("<img src=\"../../assets/1.png\" alt=\"fig\" />"));
+    assert!(first_page.contains("<img src=\"https://example.com/a.png\""));
+    assert_eq!(fs::read_to_string(out.join("assets/1.png")).unwrap(), "png");
+    let second_page = fs::read_to_string(out.join("notes/second-note/index.html")).unwrap();
+    assert!(second_page.contains("<a href=\"../../notes/test-file/\">back</a>"));
+    assert!(second_page.contains("<img src=\"../../assets/1.png\""));
+    assert!(!out.join("assets/2.png").exists());
+
+    let tag_page = fs::read_to_string(out.join("tags/t1/index.html")).unwrap();
+    assert!(tag_page.contains("../../notes/second-note/"));
+    assert!(out.join("authors/me/index.html").is_file());
+    let index = fs::read_to_string(out.join("index.html")).unwrap();
+    assert!(index.contains("<a href=\"tags/t2/\">t2</a>"));
+
+    let sitemap = fs::read_to_string(out.join(SITEMAP)).unwrap();
+    assert!(sitemap.contains("<loc>https://example.com/site/notes/test-file/</loc>"));
+    let feed = fs::read_to_string(out.join(FEED)).unwrap();
+    assert_eq!(feed.matches("<entry>").count(), 2);
+
+    fs::remove_dir_all(out).unwrap();
+  }
+}
@@ -0,0 +1,186 @@
+use super::{
+  attachments,
+  constants::{ATTACHMENTS, TEMPLATES},
+  macros::Macro,
+  model::Meta,
+  render,
+  template::{self, Template, Value},
+};
+use regex::{Captures, Regex};
+use std::{
+  cell::RefCell,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+/*
+ * single file html export, which can be mailed or archived and still be read offline.
+ * - math is rendered into mathml, which browsers display without katex -- see math.rs
+ * - local images are embedded as data uris. blob and remote urls can't be, so they are reported as missing.
+ * - the builtin css and js are inlined, and the cdn scripts are dropped by `{{^standalone}}` in the template.
+ *   local stylesheets and scripts of a user template are inlined from `.templates`.
+ * - mermaid needs its script, so diagrams are left as their source.
+ */
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Standalone {
+  pub html: String,
+  pub missing: Vec<String>, //image sources which couldn't be embedded
+}
+
+pub fn data_uri(path: &Path) -> io::Result<String> {
+  let bytes = fs::read(path)?;
+  let mime = mime_guess::from_path(path).first_or_octet_stream();
+  Ok(format!(
+    "data:{};base64,{}",
+    mime.essence_str(),
+    base64::encode(bytes)
+  ))
+}
+
+/*
+ * an absolute path, a file url, or a path relative to one of the dirs.
+ * attachment links are looked up in the store of the dirs. urls of other schemes are not local.
+ */
+pub fn local_file(src: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+  if let Some(name) = src.strip_prefix(attachments::SCHEME) {
+    attachments::hash_of(src)?;
+    return dirs
+      .iter()
+      .map(|dir| dir.join(ATTACHMENTS).join(name))
+      .find(|p| p.is_file());
+  }
+  let src = match src.strip_prefix("file://") {
+    Some(path) => path,
+    None if src.contains("://") || src.starts_with("blob:") || src.starts_with("data:") => {
+      return None
+    }
+    None => src,
+  };
+  let path = Path::new(src);
+  if path.is_absolute() {
+    return Some(path.to_path_buf()).filter(|p| p.is_file());
+  }
+  dirs.iter().map(|dir| dir.join(path)).find(|p| p.is_file())
+}
+
+/*
+ * replace stylesheet links and script sources found in dir with their contents.
+ * the others are left as they are.
+ */
+pub fn inline_assets(html: &str, dir: &Path) -> String {
+  let link = Regex::new(r#"<link\s[^>]*?href="([^"]+)"[^>]*>"#).unwrap();
+  let script = Regex::new(r#"<script\s[^>]*?src="([^"]+)"[^>]*>\s*</script>"#).unwrap();
+  let dirs = [dir.to_path_buf()];
+
+  let html = link.replace_all(html, |caps: &Captures| {
+    match local_file(&caps[1], &dirs).filter(|_| caps[0].contains("stylesheet")) {
+      Some(path) => match fs::read_to_string(path) {
+        Ok(css) => format!("<style>\n{}\n</style>", css),
+        Err(_) => caps[0].to_string(),
+      },
+      None => caps[0].to_string(),
+    }
+  });
+  script
+    .replace_all(&html, |caps: &Captures| {
+      match local_file(&caps[1], &dirs).and_then(|path| fs::read_to_string(path).ok()) {
+        Some(js) => format!(
+          "<script>\n{}\n</script>",
+          js.replace("</script", "<\\/script")
+        ),
+        None => caps[0].to_string(),
+      }
+    })
+    .to_string()
+}
+
+/*
+ * relative image paths are looked up in dirs in order, e.g. the directory of the body then the target directory.
+ */
+pub fn export(
+  meta: &Meta,
+  md: &str,
+  macros: &[Macro],
+  engine: &Template,
+  dirs: &[PathBuf],
+  target_dir: &Path,
+  lang: &str,
+) -> Standalone {
+  let missing = RefCell::new(vec![]);
+  let rendered = render::render_standalone(
+    md,
+    |src| {
+      if src.starts_with("data:") {
+        return None;
+      }
+      let embedded = local_file(src, dirs).and_then(|path| data_uri(&path).ok());
+      if embedded.is_none() {
+        missing.borrow_mut().push(src.to_string());
+      }
+      embedded
+    },
+    macros,
+  );
+  let mut context = template::context(meta, &rendered, lang);
+  context.insert("standalone", Value::Text(String::from("true")));
+  let html = inline_assets(&engine.render(&context), &target_dir.join(TEMPLATES));
+  Standalone {
+    html,
+    missing: missing.into_inner(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::{get_random_key, Setup};
+
+  #[test]
+  fn standalone_export_test() {
+    let dir = std::env::temp_dir().join(format!("notex-standalone-{}", get_random_key()));
+    fs::create_dir_all(dir.join(TEMPLATES)).unwrap();
+    fs::write(dir.join("dot.png"), [0x89, b'P', b'N', b'G']).unwrap();
+    fs::write(dir.join(TEMPLATES).join("note.css"), "body { color: red; }").unwrap();
+    let setup = Setup::init();
+
+    let builtin = template::load(&dir, None).unwrap();
+    let res = export(
+      setup.get_meta(),
+      "![dot](./dot.png) ![lost](blob:http://localhost/1) ![web](https://example.com/a.png)\n\n$x^2$",
+      &[],
+      &builtin,
+      &[dir.clone()],
+      &dir,
+      "en",
+    );
+    assert!(res.html.contains("src=\"data:image/png;base64,iVBORw==\""));
+    assert!(res.html.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+    assert!(!res.html.contains("cdn.jsdelivr.net"));
+    assert_eq!(
+      res.missing,
+      vec![
+        String::from("blob:http://localhost/1"),
+        String::from("https://example.com/a.png")
+      ]
+    );
+
+    let user = Template::parse(
+      "<link rel=\"stylesheet\" href=\"note.css\" /><link rel=\"stylesheet\" href=\"https://example.com/a.css\" />{{{body}}}",
+    )
+    .unwrap();
+    let res = export(
+      setup.get_meta(),
+      "text",
+      &[],
+      &user,
+      &[dir.clone()],
+      &dir,
+      "en",
+    );
+    assert!(res.html.contains("<style>\nbody { color: red; }\n</style>"));
+    assert!(res.html.contains("href=\"https://example.com/a.css\""));
+
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
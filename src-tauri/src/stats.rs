@@ -0,0 +1,335 @@
+use super::constants::TIME_FORMAT;
+use super::model::Meta;
+use super::render::{self, Segment};
+use chrono::{offset::TimeZone, Local};
+use pulldown_cmark::{CodeBlockKind, Event, Parser, Tag};
+use std::collections::BTreeMap;
+
+/*
+ * statistics of a body, kept in the index and summed up for the writing progress.
+ * - words are runs of letters and digits in the latin like scripts, which are separated by spaces,
+ *   and runs of kanji, hiragana or katakana in japanese, which aren't.
+ *   it is rough for japanese, where a run of hiragana is often a particle and the okurigana in between,
+ *   but counts a sentence as more than one word without a dictionary.
+ * - characters are those in the text other than spaces. code, math and alt texts are not counted as text.
+ * - math is the display math, and code is the code blocks other than the diagrams.
+ * - reading time is in seconds, at 200 words a minute for the spaced scripts and 500 characters for japanese.
+ */
+
+const WORDS_PER_MINUTE: u64 = 200;
+const JAPANESE_PER_MINUTE: u64 = 500;
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Stats {
+  pub words: usize,
+  pub characters: usize,
+  pub math: usize,
+  pub code: usize,
+  pub images: usize,
+  pub reading_time: u64,
+}
+impl Stats {
+  pub fn add(&mut self, other: &Stats) {
+    self.words += other.words;
+    self.characters += other.characters;
+    self.math += other.math;
+    self.code += other.code;
+    self.images += other.images;
+    self.reading_time += other.reading_time;
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Script {
+  Spaced,
+  Han,
+  Hiragana,
+  Katakana,
+}
+
+fn script_of(c: char) -> Option<Script> {
+  match c {
+    '\u{3041}'..='\u{309f}' => Some(Script::Hiragana),
+    '\u{30a0}'..='\u{30ff}' | '\u{31f0}'..='\u{31ff}' | '\u{ff66}'..='\u{ff9f}' => {
+      Some(Script::Katakana)
+    }
+    '\u{3005}'
+    | '\u{3007}'
+    | '\u{3400}'..='\u{4dbf}'
+    | '\u{4e00}'..='\u{9fff}'
+    | '\u{f900}'..='\u{faff}' => Some(Script::Han),
+    c if c.is_alphanumeric() => Some(Script::Spaced),
+    _ => None,
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Count {
+  words: usize,      //in the spaced scripts
+  japanese: usize,   //words in japanese
+  characters: usize, //other than spaces
+  japanese_characters: usize,
+}
+
+fn count(text: &str, res: &mut Count) {
+  let chars = text.chars().collect::<Vec<_>>();
+  let mut prev: Option<Script> = None;
+  for (i, &c) in chars.iter().enumerate() {
+    if !c.is_whitespace() {
+      res.characters += 1;
+    }
+    let script = match script_of(c) {
+      // an apostrophe or a hyphen inside a word, e.g. don't and well-known
+      None
+        if (c == '\'' || c == '’' || c == '-')
+          && prev == Some(Script::Spaced)
+          && chars.get(i + 1).and_then(|&n| script_of(n)) == Some(Script::Spaced) =>
+      {
+        continue;
+      }
+      script => script,
+    };
+    if let Some(s) = script {
+      if s != Script::Spaced {
+        res.japanese_characters += 1;
+      }
+      if prev != script {
+        match s {
+          Script::Spaced => res.words += 1,
+          _ => res.japanese += 1,
+        }
+      }
+    }
+    prev = script;
+  }
+}
+
+/*
+ * the statistics of a body without front matter.
+ */
+pub fn of(md: &str) -> Stats {
+  let (src, maths) = render::extract(md);
+  let mut counted = Count::default();
+  let mut stats = Stats {
+    math: maths.iter().filter(|math| math.display).count(),
+    ..Default::default()
+  };
+  let mut in_code = false;
+  let mut in_image = false;
+  // the parser may split a text at the punctuations, so the texts in a row are joined
+  let mut text = String::new();
+  for event in Parser::new_ext(&src, render::options()) {
+    match event {
+      Event::Text(t) => {
+        if !in_code && !in_image {
+          text.push_str(&t);
+        }
+        continue;
+      }
+      Event::Start(Tag::CodeBlock(kind)) => {
+        in_code = true;
+        match kind {
+          CodeBlockKind::Fenced(lang) if render::is_diagram(&lang) => (),
+          _ => stats.code += 1,
+        }
+      }
+      Event::End(Tag::CodeBlock(_)) => in_code = false,
+      Event::Start(Tag::Image(..)) => {
+        in_image = true;
+        stats.images += 1;
+      }
+      Event::End(Tag::Image(..)) => in_image = false,
+      _ => (),
+    }
+    for segment in render::split_math(&text, &maths) {
+      if let Segment::Text(t) = segment {
+        count(t, &mut counted);
+      }
+    }
+    text.clear();
+  }
+  for segment in render::split_math(&text, &maths) {
+    if let Segment::Text(t) = segment {
+      count(t, &mut counted);
+    }
+  }
+
+  stats.words = counted.words + counted.japanese;
+  stats.characters = counted.characters;
+  let millis = counted.words as u64 * 60_000 / WORDS_PER_MINUTE
+    + counted.japanese_characters as u64 * 60_000 / JAPANESE_PER_MINUTE;
+  stats.reading_time = (millis + 999) / 1000;
+  stats
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Group {
+  #[serde(rename = "none")]
+  None,
+  #[serde(rename = "tag")]
+  Tag,
+  #[serde(rename = "author")]
+  Author,
+}
+impl Default for Group {
+  fn default() -> Self {
+    Group::None
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub enum Period {
+  #[serde(rename = "day")]
+  Day,
+  #[serde(rename = "week")]
+  Week,
+  #[serde(rename = "month")]
+  Month,
+  #[serde(rename = "year")]
+  Year,
+}
+impl Default for Period {
+  fn default() -> Self {
+    Period::Month
+  }
+}
+impl Period {
+  fn format(&self) -> &'static str {
+    match self {
+      Period::Day => "%Y-%m-%d",
+      Period::Week => "%G-W%V",
+      Period::Month => "%Y-%m",
+      Period::Year => "%Y",
+    }
+  }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StatsOptions {
+  pub group: Group,
+  pub period: Period,
+}
+
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct Bucket {
+  pub period: String,
+  pub key: String, //the tag or the author. empty if not grouped, or for the notes without tags
+  pub documents: usize,
+  #[serde(flatten)]
+  pub stats: Stats,
+}
+
+/*
+ * the statistics summed up by the period when the notes were created, and by the group.
+ * a note with some tags is counted in each of them. the notes with the invalid creation time are skipped.
+ */
+pub fn aggregate(metas: &[Meta], options: &StatsOptions) -> Vec<Bucket> {
+  let mut buckets = BTreeMap::new();
+  for meta in metas {
+    let created = match Local.datetime_from_str(meta.get_created_at(), TIME_FORMAT) {
+      Ok(created) => created,
+      Err(_) => continue,
+    };
+    let period = created.format(options.period.format()).to_string();
+    let keys = match options.group {
+      Group::None => vec![String::new()],
+      Group::Author => vec![meta.get_author().to_string()],
+      Group::Tag if meta.get_tags().is_empty() => vec![String::new()],
+      Group::Tag => meta.get_tags().to_vec(),
+    };
+    for key in keys {
+      let bucket = buckets
+        .entry((period.clone(), key.clone()))
+        .or_insert_with(|| Bucket {
+          period: period.clone(),
+          key,
+          documents: 0,
+          stats: Stats::default(),
+        });
+      bucket.documents += 1;
+      bucket.stats.add(meta.get_stats());
+    }
+  }
+  buckets.into_iter().map(|(_, bucket)| bucket).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests;
+
+  #[test]
+  fn stats_test() {
+    let stats = of(
+      "# Title\n\nIt's a well-known fact $x_1 + x_2$.\n\n$$\n\\int f\n$$\n\n```rust\nlet words = 0;\n```\n\n```mermaid\ngraph TD\n```\n\n![figure](a.png)\n",
+    );
+    assert_eq!(
+      stats,
+      Stats {
+        words: 5,
+        characters: 25,
+        math: 1,
+        code: 1,
+        images: 1,
+        reading_time: 2,
+      }
+    );
+
+    // 私 は 東京 へ 行きました: kanji and hiragana take turns
+    let stats = of("私は東京へ行きました。コーヒーを飲む");
+    assert_eq!(stats.words, 10);
+    assert_eq!(stats.characters, 18);
+    assert_eq!(stats.reading_time, 3);
+  }
+
+  #[test]
+  fn aggregate_test() {
+    let meta = |created_at: &str, tags: &[&str], words: usize| {
+      let mut meta = tests::meta(created_at, tags, created_at);
+      meta.set_stats(Stats {
+        words,
+        ..Default::default()
+      });
+      meta
+    };
+    let created_at = |date: &str| {
+      Local
+        .datetime_from_str(&format!("{} 12:00:00", date), "%Y/%m/%d %H:%M:%S")
+        .unwrap()
+        .format(TIME_FORMAT)
+        .to_string()
+    };
+    let metas = vec![
+      meta(&created_at("2021/10/01"), &["math"], 10),
+      meta(&created_at("2021/10/20"), &["math", "physics"], 20),
+      meta(&created_at("2021/11/02"), &[], 5),
+      meta("invalid", &["math"], 100),
+    ];
+
+    let buckets = aggregate(&metas, &StatsOptions::default());
+    assert_eq!(
+      buckets
+        .iter()
+        .map(|b| (b.period.as_str(), b.documents, b.stats.words))
+        .collect::<Vec<_>>(),
+      vec![("2021-10", 2, 30), ("2021-11", 1, 5)]
+    );
+
+    let buckets = aggregate(
+      &metas,
+      &StatsOptions {
+        group: Group::Tag,
+        period: Period::Year,
+      },
+    );
+    assert_eq!(
+      buckets
+        .iter()
+        .map(|b| (b.key.as_str(), b.documents, b.stats.words))
+        .collect::<Vec<_>>(),
+      vec![("", 1, 5), ("math", 2, 30), ("physics", 1, 20)]
+    );
+  }
+}
@@ -0,0 +1,330 @@
+use super::{
+  constants::{TEMPLATES, TEMPLATE_EXTENTION},
+  model::Meta,
+  render::{escape_html, Heading, Rendered},
+};
+use std::{collections::HashMap, fs, io, path::Path};
+
+/*
+ * templates for html export, in a small subset of mustache.
+ * - `{{name}}` => the value html escaped
+ * - `{{{name}}}` => the value as is, for body, toc, css and js
+ * - `{{#name}}...{{/name}}` => repeated for each item of a list with `{{.}}`, or once if a text is not empty
+ * - `{{^name}}...{{/name}}` => once if the value is empty
+ * - `{{! comment }}`
+ *
+ * variables are title, author, created_at, updated_at, tags, keywords (tags joined), toc, body, lang, css and js.
+ * site and root (relative path to the top page) are given only when building a site -- see site.rs
+ * standalone is given only for a single file export, to leave out what needs the network -- see standalone.rs
+ * the builtin template is assets/template.html and used when a document doesn't choose one.
+ * user defined ones are `<target_dir>/.templates/<name>.html`.
+ */
+
+pub static BUILTIN: &str = "default";
+static BUILTIN_HTML: &str = include_str!("../assets/template.html");
+static BUILTIN_CSS: &str = include_str!("../assets/template.css");
+static BUILTIN_JS: &str = include_str!("../assets/template.js");
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+  Text(String),
+  List(Vec<String>),
+}
+impl Value {
+  fn is_empty(&self) -> bool {
+    match self {
+      Value::Text(text) => text.is_empty(),
+      Value::List(list) => list.is_empty(),
+    }
+  }
+}
+
+pub type Context = HashMap<&'static str, Value>;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Node {
+  Text(String),
+  Var {
+    name: String,
+    raw: bool,
+  },
+  Section {
+    name: String,
+    inverted: bool,
+    children: Vec<Node>,
+  },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Template {
+  nodes: Vec<Node>,
+}
+impl Template {
+  pub fn parse(src: &str) -> Result<Template, String> {
+    // the sections being opened with their names and the nodes collected so far
+    let mut stack: Vec<(String, bool, Vec<Node>)> = vec![];
+    let mut nodes = vec![];
+    let mut rest = src;
+
+    while let Some(start) = rest.find("{{") {
+      if 0 < start {
+        nodes.push(Node::Text(rest[..start].to_string()));
+      }
+      let raw = rest[start..].starts_with("{{{");
+      let (open, close) = if raw { ("{{{", "}}}") } else { ("{{", "}}") };
+      let inner = &rest[start + open.len()..];
+      let end = inner
+        .find(close)
+        .ok_or_else(|| format!("unclosed tag at byte {}", src.len() - rest.len() + start))?;
+      let tag = inner[..end].trim();
+      rest = &inner[end + close.len()..];
+
+      if raw {
+        nodes.push(Node::Var {
+          name: tag.to_string(),
+          raw: true,
+        });
+        continue;
+      }
+      match tag.chars().next() {
+        Some('!') => (),
+        Some(c @ '#') | Some(c @ '^') => {
+          stack.push((tag[1..].trim().to_string(), c == '^', nodes));
+          nodes = vec![];
+        }
+        Some('/') => {
+          let name = tag[1..].trim();
+          match stack.pop() {
+            Some((open, inverted, mut parent)) if open == name => {
+              parent.push(Node::Section {
+                name: open,
+                inverted,
+                children: nodes,
+              });
+              nodes = parent;
+            }
+            _ => return Err(format!("unexpected closing of section {}", name)),
+          }
+        }
+        _ => nodes.push(Node::Var {
+          name: tag.to_string(),
+          raw: false,
+        }),
+      }
+    }
+    if !rest.is_empty() {
+      nodes.push(Node::Text(rest.to_string()));
+    }
+    match stack.pop() {
+      Some((name, _, _)) => Err(format!("section {} is not closed", name)),
+      None => Ok(Template { nodes }),
+    }
+  }
+
+  pub fn render(&self, context: &Context) -> String {
+    let mut res = String::new();
+    render_nodes(&self.nodes, context, None, &mut res);
+    res
+  }
+}
+
+fn render_nodes(nodes: &[Node], context: &Context, item: Option<&str>, res: &mut String) {
+  for node in nodes {
+    match node {
+      Node::Text(text) => res.push_str(text),
+      Node::Var { name, raw } => {
+        let value = match (name.as_str(), item) {
+          (".", Some(item)) => item.to_string(),
+          (name, _) => match context.get(name) {
+            Some(Value::Text(text)) => text.clone(),
+            Some(Value::List(list)) => list.join(", "),
+            None => String::new(),
+          },
+        };
+        if *raw {
+          res.push_str(&value);
+        } else {
+          res.push_str(&escape_html(&value));
+        }
+      }
+      Node::Section {
+        name,
+        inverted,
+        children,
+      } => {
+        let value = context.get(name.as_str());
+        let is_empty = value.map_or(true, Value::is_empty);
+        if *inverted {
+          if is_empty {
+            render_nodes(children, context, item, res);
+          }
+          continue;
+        }
+        match value {
+          Some(Value::List(list)) => {
+            for x in list {
+              render_nodes(children, context, Some(x), res);
+            }
+          }
+          Some(Value::Text(text)) if !is_empty => render_nodes(children, context, Some(text), res),
+          _ => (),
+        }
+      }
+    }
+  }
+}
+
+/*
+ * nested list of links to the headings.
+ */
+pub fn toc(headings: &[Heading]) -> String {
+  let mut res = String::new();
+  let mut levels: Vec<u32> = vec![];
+  for heading in headings {
+    while levels.last().map_or(false, |&l| heading.level < l) {
+      res.push_str("</li></ul>");
+      levels.pop();
+    }
+    match levels.last() {
+      Some(&l) if l == heading.level => res.push_str("</li><li>"),
+      _ => {
+        res.push_str("<ul><li>");
+        levels.push(heading.level);
+      }
+    }
+    res.push_str(&format!(
+      "<a href=\"#{}\">{}</a>",
+      escape_html(&heading.id),
+      escape_html(&heading.text)
+    ));
+  }
+  for _ in levels {
+    res.push_str("</li></ul>");
+  }
+  res
+}
+
+pub fn context(meta: &Meta, rendered: &Rendered, lang: &str) -> Context {
+  let mut context = Context::new();
+  context.insert("title", Value::Text(meta.get_filename().to_string()));
+  context.insert("author", Value::Text(meta.get_author().to_string()));
+  context.insert("created_at", Value::Text(meta.get_created_at().to_string()));
+  context.insert(
+    "updated_at",
+    Value::Text(meta.get_updated_at().unwrap_or_default().to_string()),
+  );
+  context.insert("tags", Value::List(meta.get_tags().to_vec()));
+  context.insert("keywords", Value::Text(meta.get_tags().join(", ")));
+  context.insert("toc", Value::Text(toc(&rendered.headings)));
+  context.insert("body", Value::Text(rendered.html.clone()));
+  context.insert("lang", Value::Text(lang.to_string()));
+  context.insert("css", Value::Text(BUILTIN_CSS.to_string()));
+  context.insert("js", Value::Text(BUILTIN_JS.to_string()));
+  context
+}
+
+/*
+ * for the pages not from a document, e.g. the listing pages of a site.
+ */
+pub fn page_context(title: &str, body: String, lang: &str) -> Context {
+  let mut context = Context::new();
+  context.insert("title", Value::Text(title.to_string()));
+  context.insert("body", Value::Text(body));
+  context.insert("lang", Value::Text(lang.to_string()));
+  context.insert("css", Value::Text(BUILTIN_CSS.to_string()));
+  context.insert("js", Value::Text(BUILTIN_JS.to_string()));
+  context
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+  !name.is_empty() && !name.starts_with('.') && !name.contains(|c| c == '/' || c == '\\')
+}
+
+/*
+ * the builtin one followed by the user defined ones.
+ */
+pub fn list(target_dir: &Path) -> io::Result<Vec<String>> {
+  let mut res = vec![];
+  let dir = target_dir.join(TEMPLATES);
+  if dir.is_dir() {
+    for entry in fs::read_dir(dir)? {
+      let name = entry?.file_name().to_string_lossy().to_string();
+      if let Some(name) = name.strip_suffix(TEMPLATE_EXTENTION) {
+        if is_valid_name(name) && name != BUILTIN {
+          res.push(name.to_string());
+        }
+      }
+    }
+  }
+  res.sort();
+  res.insert(0, String::from(BUILTIN));
+  Ok(res)
+}
+
+pub fn load(target_dir: &Path, name: Option<&str>) -> Result<Template, String> {
+  match name {
+    None => Template::parse(BUILTIN_HTML),
+    Some(name) if name == BUILTIN => Template::parse(BUILTIN_HTML),
+    Some(name) if !is_valid_name(name) => Err(format!("invalid template name {}", name)),
+    Some(name) => {
+      let path = target_dir
+        .join(TEMPLATES)
+        .join(format!("{}{}", name, TEMPLATE_EXTENTION));
+      let src = fs::read_to_string(path).map_err(|err| format!("{}: {}", name, err.to_string()))?;
+      Template::parse(&src).map_err(|err| format!("{}: {}", name, err))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::model::tests::Setup;
+  use crate::render;
+
+  #[test]
+  fn template_render_test() {
+    let template = Template::parse(
+      "<h1>{{title}}</h1>{{! ignored }}<ul>{{#tags}}<li>{{.}}</li>{{/tags}}</ul>\
+       {{^author}}anonymous{{/author}}{{#author}}by {{author}}{{/author}}{{{body}}}",
+    )
+    .unwrap();
+    let mut context = Context::new();
+    context.insert("title", Value::Text(String::from("a < b")));
+    context.insert(
+      "tags",
+      Value::List(vec![String::from("t1"), String::from("t2")]),
+    );
+    context.insert("author", Value::Text(String::new()));
+    context.insert("body", Value::Text(String::from("<p>body</p>")));
+    assert_eq!(
+      template.render(&context),
+      "<h1>a &lt; b</h1><ul><li>t1</li><li>t2</li></ul>anonymous<p>body</p>"
+    );
+  }
+
+  #[test]
+  fn template_error_test() {
+    assert!(Template::parse("{{#tags}}").is_err());
+    assert!(Template::parse("{{#tags}}{{/author}}").is_err());
+    assert!(Template::parse("{{title").is_err());
+  }
+
+  #[test]
+  fn builtin_template_test() {
+    let setup = Setup::init();
+    let rendered = render::render("# first\n\n## second\n\n# third");
+    assert_eq!(
+      toc(&rendered.headings),
+      "<ul><li><a href=\"#first\">first</a><ul><li><a href=\"#second\">second</a></li></ul>\
+       </li><li><a href=\"#third\">third</a></li></ul>"
+    );
+    let html =
+      load(Path::new("."), None)
+        .unwrap()
+        .render(&context(setup.get_meta(), &rendered, "en"));
+    assert!(html.contains("<title>test file</title>"));
+    assert!(html.contains("<h2 id=\"second\">second</h2>"));
+    assert!(!html.contains("{{"));
+  }
+}
@@ -0,0 +1,115 @@
+use super::{constants, Casher, Env, Memo};
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::{ffi::OsStr, path::Path, sync::mpsc::channel, thread, time::Duration};
+use tauri::{AppHandle, Manager, Window};
+
+/*
+ * payload of event "workspace_changed".
+ * target is "setting" when notex.conf is reloaded and "index" when index.jsonl is.
+ */
+#[derive(Debug, PartialEq, Clone, serde::Serialize)]
+pub struct WorkspaceChanged {
+  target: &'static str,
+}
+
+/*
+ * returns true only if the setting on disk differs from the one in memory,
+ * so that the writes by update_setting itself are not notified.
+ */
+fn reload_setting(env: &Env) -> bool {
+  let mut setting = match crate::read_setting() {
+    Ok(setting) => setting,
+    Err(err) => {
+      println!("WARNING: failed to reload setting\n{}", err.to_string());
+      return false;
+    }
+  };
+  match env.0.lock() {
+    Ok(mut inner) => {
+      setting.is_new = inner.is_new;
+      if *inner == setting {
+        false
+      } else {
+        *inner = setting;
+        true
+      }
+    }
+    Err(_) => false,
+  }
+}
+
+fn refresh_memo(cashe: &Casher) -> bool {
+  let memo = match Memo::from_index() {
+    Ok(memo) => memo,
+    Err(err) => {
+      println!("WARNING: failed to refresh index\n{}", err.to_string());
+      return false;
+    }
+  };
+  match cashe.0.lock() {
+    Ok(mut inner) => {
+      if *inner == memo {
+        false
+      } else {
+        *inner = memo;
+        true
+      }
+    }
+    Err(_) => false,
+  }
+}
+
+fn changed_path(event: DebouncedEvent) -> Option<std::path::PathBuf> {
+  match event {
+    DebouncedEvent::Create(path)
+    | DebouncedEvent::Write(path)
+    | DebouncedEvent::Rename(_, path) => Some(path),
+    _ => None,
+  }
+}
+
+fn is_file_of(path: &Path, name: &str) -> bool {
+  path.file_name() == Some(OsStr::new(name))
+}
+
+/*
+ * watch the workspace root and reflect the changes made outside of this process.
+ * the watcher lives as long as the spawned thread, that is, the app.
+ */
+pub fn spawn(app: AppHandle, main_window: Window) {
+  thread::spawn(move || {
+    let (tx, rx) = channel();
+    let mut watcher = match watcher(tx, Duration::from_millis(500)) {
+      Ok(watcher) => watcher,
+      Err(err) => {
+        println!(
+          "WARNING: failed to start workspace watcher\n{}",
+          err.to_string()
+        );
+        return;
+      }
+    };
+    if let Err(err) = watcher.watch(crate::root_path(), RecursiveMode::NonRecursive) {
+      println!("WARNING: failed to watch workspace\n{}", err.to_string());
+      return;
+    }
+
+    for event in rx {
+      let path = match changed_path(event) {
+        Some(path) => path,
+        None => continue,
+      };
+      let target = if is_file_of(&path, constants::CONF) && reload_setting(&app.state::<Env>()) {
+        "setting"
+      } else if is_file_of(&path, constants::INDEX) && refresh_memo(&app.state::<Casher>()) {
+        "index"
+      } else {
+        continue;
+      };
+      println!("workspace changed: {:?}", path);
+      if let Err(err) = main_window.emit("workspace_changed", WorkspaceChanged { target }) {
+        println!("{}", err);
+      }
+    }
+  });
+}
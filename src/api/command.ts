@@ -1,25 +1,38 @@
 import { invoke } from "@tauri-apps/api";
-import { SettingType } from "../redux/settings";
-import { Meta } from "../redux/write";
+import { SettingType, Layout, Variant } from "../redux/settings";
+import { Meta, Section, Stats } from "../redux/write";
+
+export type Conflict = {
+  on_disk: string;
+  proposal: {
+    body: string;
+    conflicts: number;
+  };
+};
 
 export type Response = {
   code: number;
   message: string;
+  conflict?: Conflict;
+  meta?: Meta; //as stored in the index
 };
 
 interface ErrorResponse extends Error {
   code: number;
   message: string;
+  conflict?: Conflict;
 }
 
 class ErrorResponse extends Error {
   code: number;
   message: string;
+  conflict?: Conflict;
 
-  constructor(message: string, code: number) {
+  constructor(message: string, code: number, conflict?: Conflict) {
     super(message);
     this.code = code;
     this.message = message;
+    this.conflict = conflict;
   }
 }
 
@@ -33,7 +46,168 @@ export type RequestDocs = {
   tags?: string[]; //if length is 0, ignored
   author?: string; //if empty, ignored
   is_html_src_exists?: boolean | null; //if null,  ignored
+  heading_contain?: string; //matches any heading ignoring the case. if empty, ignored
+};
+export type Transfer = "move" | "copy";
+export type MigrateProgress = {
+  phase: "copy" | "verify" | "cleanup" | "rollback";
+  done: number;
+  total: number;
+  file: string;
+};
+
+/*
+ * a file in the attachment store of the workspace. notes link it by `link`, i.e. `notex://<sha256>.<ext>`
+ */
+export type Attachment = {
+  hash: string;
+  name: string; //file name when added
+  ext: string;
+  mime: string;
+  size: number;
+  added_at: number; //millis
+  notes: string[]; //titles of the notes which refer to it
+  image?: {
+    width: number;
+    height: number;
+    optimized: string | null; //file name in the store
+    thumbnail: string | null;
+  };
+  link: string;
+};
+
+/*
+ * `[[title#heading|label]]` in a body. an empty target is the same note.
+ */
+export type WikiLink = {
+  target: string;
+  heading: string | null;
+  label: string | null;
+};
+export type Backlink = {
+  source: string; //title of the note which links
+  heading: string | null;
+  label: string | null;
+};
+export type Unresolved = {
+  reason: "missing_note" | "missing_heading";
+  source: string;
+  link: WikiLink;
+};
+export type Backlinks = {
+  backlinks: Backlink[];
+  unresolved: Unresolved[]; //of the links in the note itself
+};
+
+/*
+ * ids are `note:<title>` and `tag:<tag>`. link edges are directed from the note which links.
+ */
+export type GraphNode = {
+  id: string;
+  kind: "note" | "tag";
+  label: string;
+  weight: number; //links to the note, or notes with the tag
+};
+export type GraphEdge = {
+  source: string;
+  target: string;
+  kind: "link" | "tag" | "shared_tags";
+  weight: number;
+};
+export type Graph = {
+  nodes: GraphNode[];
+  edges: GraphEdge[];
+};
+export type GraphOptions = {
+  tags?: boolean; //tags as nodes
+  shared_tags?: boolean; //edges between the notes with tags in common
+  center?: string | null; //title of the note to take the neighbourhood of. if null, the whole graph
+  hops?: number;
+};
+
+/*
+ * the notes are put in the periods by when they were created. with group "tag", a note is counted in each of its tags.
+ */
+export type StatsOptions = {
+  group?: "none" | "tag" | "author";
+  period?: "day" | "week" | "month" | "year";
+};
+/*
+ * a template for new documents, `.templates/<name>.md` in the workspace.
+ * {{date}}, {{time}}, {{author}} and {{title}} are filled in on creation.
+ */
+export type DocTemplate = {
+  name: string;
+  title: string; //of new documents, where {{title}} is the one given
+  description: string;
+  tags: string[];
+  shortcut: { [key: string]: string };
+  body: string;
+};
+/*
+ * a date of the month with the daily note or the notes created on it.
+ */
+export type CalendarDay = {
+  date: string; //YYYY-MM-DD
+  daily: string | null; //title of the daily note
+  created: string[]; //titles of the notes created on the date
+};
+export type StatsBucket = Stats & {
+  period: string; //e.g. 2021-10, 2021-W42
+  key: string; //the tag or the author. empty if not grouped, or for the notes without tags
+  documents: number;
+};
+
+/*
+ * external urls are requested only if `external` is given. `proxy` points every request at e.g. a local stand-in.
+ */
+export type CheckOptions = {
+  external?: { proxy?: string | null; timeout?: number } | null;
+};
+export type Problem = { line: number } & (
+  | {
+      kind:
+        | "missing_note"
+        | "missing_heading"
+        | "missing_attachment"
+        | "missing_file";
+      dest: string;
+    }
+  | { kind: "malformed_link"; text: string }
+  | { kind: "duplicate_anchor"; anchor: string }
+  | {
+      kind: "broken_url";
+      dest: string;
+      status: number | null;
+      error: string | null;
+    }
+);
+export type LinkReport = {
+  checked: number; //documents
+  documents: { title: string; problems: Problem[] }[]; //with problems only
+  unchecked: string[]; //https urls without the proxy
 };
+
+export type WorkspaceChanged = {
+  target: "setting" | "index";
+};
+
+export type EpubOptions = {
+  title: string;
+  sort: "created_at" | "updated_at" | "title";
+  descending: boolean;
+};
+
+export type JobId = number;
+export type JobProgress = {
+  id: JobId;
+  done: number;
+  total: number;
+  message: string;
+};
+export type JobResult = Response & { id: JobId }; //payload of "job_done" and "job_failed"
+export type LinkReportResult = LinkReport & { id: JobId }; //payload of "link_report"
+
 export type ResponseDocs = {
   list: Meta[]; //returned list length will be limit size if limit does not equal to 0
   page: number; //a total number of documents
@@ -74,19 +248,39 @@ const useCommand = () => {
         );
       }
     },
-    saveDocument: async (meta: Meta, body: string, overwrite: boolean) => {
+    changeTargetDir: async (target_dir: string, mode: Transfer) => {
+      try {
+        return (await invoke("change_target_dir", {
+          path: target_dir,
+          mode,
+        })) as Response;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    saveDocument: async (
+      meta: Meta,
+      body: string,
+      overwrite: boolean,
+      base: string | null = null
+    ) => {
       try {
         return (await invoke("save_document", {
           document: {
             overwrite,
             meta,
             body,
+            base,
           },
         })) as Response;
       } catch (err) {
         throw new ErrorResponse(
           (err as ErrorResponse).message,
-          (err as ErrorResponse).code
+          (err as ErrorResponse).code,
+          (err as ErrorResponse).conflict
         );
       }
     },
@@ -112,6 +306,7 @@ const useCommand = () => {
       tags = [],
       author = "",
       is_html_src_exists = null,
+      heading_contain = "",
     }: RequestDocs) => {
       try {
         return (await invoke("get_documents_by_filter", {
@@ -125,6 +320,7 @@ const useCommand = () => {
             tags,
             author,
             is_html_src_exists,
+            heading_contain,
           },
         })) as ResponseDocs;
       } catch (err) {
@@ -146,11 +342,52 @@ const useCommand = () => {
         );
       }
     },
+    renderDocument: async (meta: Meta) => {
+      try {
+        return (await invoke("render_document", {
+          meta,
+        })) as string;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    changeLayout: async (layout: Layout) => {
+      try {
+        return (await invoke("change_layout", {
+          layout,
+        })) as Response;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    rebuildIndex: async () => {
+      try {
+        return (await invoke("rebuild_index")) as Response;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
     print: async (meta: Meta, body: string) => {
-      return (await invoke("print", {
-        meta,
-        body,
-      })) as undefined;
+      try {
+        return (await invoke("print", {
+          meta,
+          body,
+        })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
     },
     html: async (meta: Meta, htmlsrc: string, path: string) => {
       return (await invoke("html", {
@@ -159,6 +396,273 @@ const useCommand = () => {
         path,
       })) as undefined;
     },
+    exportHtml: async (
+      meta: Meta,
+      template: string | null,
+      standalone: boolean,
+      path: string,
+      variant: Variant | null = null //of the attached images. if null, the one in the setting
+    ) => {
+      try {
+        return (await invoke("export_html", {
+          meta,
+          template,
+          standalone,
+          variant,
+          path,
+        })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    exportPdf: async (
+      metas: Meta[],
+      path: string,
+      variant: Variant | null = null
+    ) => {
+      try {
+        return (await invoke("export_pdf", {
+          metas,
+          variant,
+          path,
+        })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    exportLatex: async (
+      meta: Meta,
+      path: string,
+      variant: Variant | null = null
+    ) => {
+      try {
+        return (await invoke("export_latex", {
+          meta,
+          variant,
+          path,
+        })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    exportEpub: async (
+      filter: Omit<RequestDocs, "offset" | "limit">,
+      options: EpubOptions,
+      path: string,
+      variant: Variant | null = null
+    ) => {
+      try {
+        return (await invoke("export_epub", {
+          filter,
+          options,
+          variant,
+          path,
+        })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    buildSite: async (filter: Omit<RequestDocs, "offset" | "limit">, path: string) => {
+      try {
+        return (await invoke("build_site", {
+          filter,
+          path,
+        })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    cancelJob: async (id: JobId) => {
+      try {
+        return (await invoke("cancel_job", {
+          id,
+        })) as Response;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    listExportTemplates: async () => {
+      try {
+        return (await invoke("list_export_templates")) as string[];
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    addAttachment: async (path: string) => {
+      try {
+        return (await invoke("add_attachment", {
+          path,
+        })) as Attachment;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    listAttachments: async () => {
+      try {
+        return (await invoke("list_attachments")) as Attachment[];
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    gcAttachments: async () => {
+      try {
+        return (await invoke("gc_attachments")) as Response;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    getOutline: async (meta: Meta) => {
+      try {
+        return (await invoke("get_outline", { meta })) as Section[];
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    getBacklinks: async (meta: Meta) => {
+      try {
+        return (await invoke("get_backlinks", { meta })) as Backlinks;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    checkLinks: async (options: CheckOptions = {}) => {
+      try {
+        return (await invoke("check_links", { options })) as JobId;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    getGraph: async (
+      filter: Omit<RequestDocs, "offset" | "limit">,
+      options: GraphOptions = {}
+    ) => {
+      try {
+        return (await invoke("get_graph", {
+          req: { ...filter, ...options },
+        })) as Graph;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    listTemplates: async () => {
+      try {
+        return (await invoke("list_templates")) as DocTemplate[];
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    /*
+     * the document is saved as made, and the returned meta opens it in the editor.
+     */
+    createFromTemplate: async (name: string, title: string) => {
+      try {
+        return (await invoke("create_from_template", { name, title })) as Meta;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    /*
+     * the note of the date is made from the template in the setting if missing. date is YYYY-MM-DD.
+     */
+    openDailyNote: async (date: string) => {
+      try {
+        return (await invoke("open_daily_note", { date })) as Meta;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    dailyCalendar: async (year: number, month: number) => {
+      try {
+        return (await invoke("daily_calendar", {
+          year,
+          month,
+        })) as CalendarDay[];
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    workspaceStats: async (
+      filter: Omit<RequestDocs, "offset" | "limit">,
+      options: StatsOptions = {}
+    ) => {
+      try {
+        return (await invoke("workspace_stats", {
+          req: { ...filter, ...options },
+        })) as StatsBucket[];
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
+    /*
+     * the wiki links in the other notes follow the new title. returns the renamed meta.
+     */
+    renameDocument: async (meta: Meta, filename: string) => {
+      try {
+        return (await invoke("rename_document", { meta, filename })) as Meta;
+      } catch (err) {
+        throw new ErrorResponse(
+          (err as ErrorResponse).message,
+          (err as ErrorResponse).code
+        );
+      }
+    },
   };
 };
 
@@ -23,6 +23,7 @@ import useScroll from "../hooks/Scroll";
 import sectionMsg from "../utils/constant/write/section";
 import { useSettings } from "../redux/hooks";
 import ReactMarkdown from "react-markdown";
+import { transformUri } from "../utils/lib/asset";
 import {
   ComponentType,
   TableRowComponent,
@@ -43,6 +44,7 @@ import { Prism as SyntaxHighlighter } from "react-syntax-highlighter";
 import { dark } from "react-syntax-highlighter/dist/esm/styles/prism";
 import mermaid from "mermaid";
 import * as katex from "katex";
+import { useMacros } from "../context/Macros";
 import { v4 as uuidv4 } from "uuid";
 import crypto from "crypto";
 
@@ -378,6 +380,7 @@ type MarkdownProps = {
 };
 const Markdown: React.FC<MarkdownProps> = ({ md, container }) => {
   const { pickEl } = useScroll(container?.current || undefined);
+  const macros = useMacros();
 
   useEffect(() => {
     mermaid.initialize({
@@ -509,6 +512,8 @@ const Markdown: React.FC<MarkdownProps> = ({ md, container }) => {
         },
       }}
       linkTarget={"_blank"}
+      transformLinkUri={transformUri}
+      transformImageUri={transformUri}
       remarkPlugins={[
         remarkGfm,
         remarkMath,
@@ -516,7 +521,9 @@ const Markdown: React.FC<MarkdownProps> = ({ md, container }) => {
         remarkDirective,
         customMarker,
       ]}
-      rehypePlugins={[[rehypeKatex, { throwOnError: false, output: "html" }]]}
+      rehypePlugins={[
+        [rehypeKatex, { throwOnError: false, output: "html", macros }],
+      ]}
     >
       {md}
     </ReactMarkdown>
@@ -1,6 +1,6 @@
 /** @jsxImportSource @emotion/react */
-import React from "react";
-import { Link, useLocation } from "react-router-dom";
+import React, { useState } from "react";
+import { Link, useHistory, useLocation } from "react-router-dom";
 import { css, useTheme } from "@emotion/react";
 import PropTypes from "prop-types";
 import {
@@ -11,10 +11,18 @@ import {
   useScrollTrigger,
   CssBaseline,
   Slide,
+  Menu,
+  MenuItem,
+  Dialog,
+  DialogActions,
+  DialogContent,
+  TextField,
 } from "@mui/material";
 import SettingsIcon from "@mui/icons-material/Settings";
 import utilMsg from "../utils/constant/util";
 import { useSettings } from "../redux/hooks";
+import useCommand, { DocTemplate, Response } from "../api/command";
+import { useSnackHandler } from "../context/SnackHandler";
 
 const HideOnScroll: React.FC<{children: React.ReactElement}> = ({ children }) => {
   const trigger = useScrollTrigger();
@@ -50,6 +58,97 @@ export const DummyHeader: React.FC<DummyProps> = ({ width }) => {
   );
 };
 
+/*
+ * a new note is blank, today's daily note, or made from one of the templates in the workspace with the title asked.
+ */
+const NewMenu: React.FC<{ utilMsgs: ReturnType<typeof utilMsg> }> = ({
+  utilMsgs,
+}) => {
+  const history = useHistory();
+  const { handleErr } = useSnackHandler();
+  const { listTemplates, createFromTemplate, openDailyNote } = useCommand();
+  const [anchor, setAnchor] = useState<HTMLElement | null>(null);
+  const [templates, setTemplates] = useState<DocTemplate[]>([]);
+  const [chosen, setChosen] = useState<string | null>(null);
+  const [title, setTitle] = useState("");
+  const handleOpen = (e: React.MouseEvent<HTMLElement>) => {
+    setAnchor(e.currentTarget);
+    listTemplates()
+      .then(setTemplates)
+      .catch((err) => handleErr((err as Response).message));
+  };
+  const handleBlank = () => {
+    setAnchor(null);
+    history.push({ pathname: "/write", search: "" });
+  };
+  const handleDaily = async () => {
+    setAnchor(null);
+    const now = new Date();
+    const pad = (n: number) => String(n).padStart(2, "0");
+    const today = `${now.getFullYear()}-${pad(now.getMonth() + 1)}-${pad(
+      now.getDate()
+    )}`;
+    const meta = await openDailyNote(today).catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (meta)
+      history.push({ pathname: "/write", search: meta.filename, state: meta });
+  };
+  const handleTemplate = (name: string) => () => {
+    setAnchor(null);
+    setTitle("");
+    setChosen(name);
+  };
+  const handleCreate = async () => {
+    if (chosen === null) return;
+    const meta = await createFromTemplate(chosen, title).catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (meta) {
+      setChosen(null);
+      history.push({ pathname: "/write", search: meta.filename, state: meta });
+    }
+  };
+
+  return (
+    <>
+      <Button onClick={handleOpen} color={"inherit"}>
+        {utilMsgs.write}
+      </Button>
+      <Menu
+        anchorEl={anchor}
+        open={anchor !== null}
+        onClose={() => setAnchor(null)}
+      >
+        <MenuItem onClick={handleBlank}>{utilMsgs.blank}</MenuItem>
+        <MenuItem onClick={handleDaily}>{utilMsgs.today}</MenuItem>
+        {templates.map((t) => (
+          <MenuItem key={t.name} onClick={handleTemplate(t.name)}>
+            {t.description ? `${t.name} - ${t.description}` : t.name}
+          </MenuItem>
+        ))}
+      </Menu>
+      <Dialog open={chosen !== null} onClose={() => setChosen(null)}>
+        <DialogContent>
+          <TextField
+            autoFocus
+            label={utilMsgs.title}
+            value={title}
+            onChange={(e) => setTitle(e.target.value)}
+            size="small"
+          />
+        </DialogContent>
+        <DialogActions>
+          <Button onClick={() => setChosen(null)}>{utilMsgs.cancel}</Button>
+          <Button onClick={handleCreate}>{utilMsgs.create}</Button>
+        </DialogActions>
+      </Dialog>
+    </>
+  );
+};
+
 const Header: React.FC<{ online?: boolean }> = ({ online = false }) => {
   const theme = useTheme();
   const utilMsgs = utilMsg(useSettings().language);
@@ -87,13 +186,7 @@ const Header: React.FC<{ online?: boolean }> = ({ online = false }) => {
             >
               NoTeX
             </Typography>
-            <Button
-              component={Link}
-              to={{ pathname: "/write", search: "" }}
-              color={"inherit"}
-            >
-              {utilMsgs.write}
-            </Button>
+            <NewMenu utilMsgs={utilMsgs} />
             <Button component={Link} to={"/browse"} color={"inherit"}>
               {utilMsgs.browse}
             </Button>
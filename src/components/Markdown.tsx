@@ -14,6 +14,10 @@ import sectionMsg from "../utils/constant/write/section";
 import { useSettings } from "../redux/hooks";
 import scrollRegister from "../utils/lib/scrollRegister";
 import ReactMarkdown from "react-markdown";
+import { transformUri } from "../utils/lib/asset";
+import { wikiToMarkdown, noteOf, NOTE } from "../utils/lib/wiki";
+import { useHistory } from "react-router-dom";
+import useCommand from "../api/command";
 import remarkGfm from "remark-gfm";
 import remarkMath from "remark-math";
 import remarkToc from "remark-toc";
@@ -28,6 +32,8 @@ import { Prism as SyntaxHighlighter } from "react-syntax-highlighter";
 import { dark } from "react-syntax-highlighter/dist/esm/styles/prism";
 import mermaid from "mermaid";
 import * as katex from "katex";
+import { useMacros } from "../context/Macros";
+import { Meta } from "../redux/write";
 import { v4 as uuidv4 } from "uuid";
 import functionPlot from "function-plot";
 import {
@@ -369,6 +375,30 @@ type MarkdownProps = {
 };
 const Markdown = forwardRef<HTMLDivElement, MarkdownProps>(
   ({ md, container }, ref) => {
+    const macros = useMacros();
+    const history = useHistory();
+    const { getDocumentsByFilter } = useCommand();
+    /*
+     * the exact title first, then ignoring the case, as the backend resolves it.
+     */
+    const handleNote = async (href: string) => {
+      const { title, anchor } = noteOf(href);
+      const { list } = await getDocumentsByFilter({
+        offset: 0,
+        limit: 0,
+        filename_contain: title,
+      }).catch(() => ({ list: [] as Meta[] }));
+      const meta =
+        list.find((m) => m.filename === title) ??
+        list.find((m) => m.filename.toLowerCase() === title.toLowerCase());
+      if (meta)
+        history.push({
+          pathname: "/view",
+          search: meta.filename,
+          hash: anchor,
+          state: meta,
+        });
+    };
     useEffect(() => {
       mermaid.initialize({
         startOnLoad: true,
@@ -476,7 +506,17 @@ const Markdown = forwardRef<HTMLDivElement, MarkdownProps>(
               </div>
             ),
             a: ({ node, href, children, ...props }) => {
-              if (href && href[0] === "#") {
+              if (href && href.startsWith(NOTE)) {
+                return (
+                  <a
+                    href={"javascript:void(0);"}
+                    onClick={() => handleNote(href)}
+                    title={noteOf(href).title}
+                  >
+                    {children}
+                  </a>
+                );
+              } else if (href && href[0] === "#") {
                 return (
                   <a href={"javascript:" + href + ";"} {...props}>
                     {children}
@@ -529,6 +569,8 @@ const Markdown = forwardRef<HTMLDivElement, MarkdownProps>(
             },
           }}
           linkTarget={"_blank"}
+          transformLinkUri={transformUri}
+          transformImageUri={transformUri}
           remarkPlugins={[
             remarkGfm,
             remarkMath,
@@ -537,11 +579,11 @@ const Markdown = forwardRef<HTMLDivElement, MarkdownProps>(
             customMarker,
           ]}
           rehypePlugins={[
-            [rehypeKatex, { throwOnError: false, output: "html" }],
+            [rehypeKatex, { throwOnError: false, output: "html", macros }],
             rehypeSlug,
           ]}
         >
-          {md}
+          {wikiToMarkdown(md)}
         </ReactMarkdown>
       </div>
     );
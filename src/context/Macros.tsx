@@ -0,0 +1,26 @@
+import React, { createContext, useContext, useMemo } from "react";
+import { Macros } from "../redux/settings";
+import { useSettings } from "../redux/hooks";
+import { mergeMacros } from "../utils/lib/macros";
+
+const MacrosContext = createContext<Macros>({});
+
+/**
+ * math macros for katex in the markdown below, the shortcut of the document over the global ones
+ */
+export const MacrosProvider: React.FC<{ shortcut?: Macros }> = ({
+  shortcut,
+  children,
+}) => {
+  const { macros } = useSettings();
+  const value = useMemo(
+    () => mergeMacros(macros, shortcut || {}),
+    [macros, shortcut]
+  );
+
+  return (
+    <MacrosContext.Provider value={value}>{children}</MacrosContext.Provider>
+  );
+};
+// katex may define new macros into the object, so a copy is given for each render
+export const useMacros = () => ({ ...useContext(MacrosContext) });
@@ -16,6 +16,8 @@ import {
 } from "@mui/material";
 import {
   ReplayRounded,
+  PublicRounded,
+  MenuBookRounded,
   ExpandLessRounded,
   ChevronRightRounded,
 } from "@mui/icons-material";
@@ -24,8 +26,10 @@ import useModal from "../context/Modal";
 import { useSnackHandler } from "../context/SnackHandler";
 import useCommand, { Response } from "../api/command";
 import { useSettings } from "../redux/hooks";
-import { RequestDocs, ResponseDocs } from "../api/command";
+import { RequestDocs, ResponseDocs, WorkspaceChanged } from "../api/command";
+import { listen, Event as TauriEvent, UnlistenFn } from "@tauri-apps/api/event";
 import { Meta } from "../redux/write";
+import { dialog } from "@tauri-apps/api";
 import browseMsg from "../utils/constant/browse";
 import utilMsg from "../utils/constant/util";
 
@@ -120,12 +124,16 @@ const cancelButton = (theme: Theme) =>
 type ControlerProps = {
   handleLoad: () => void;
   handleTag: (tags: string[]) => void;
+  handleBuild: () => void;
+  handleEpub: () => void;
   allTags: string[];
   selectedTags: string[];
 };
 const Controler: React.FC<ControlerProps> = ({
   handleLoad,
   handleTag,
+  handleBuild,
+  handleEpub,
   allTags,
   selectedTags,
 }) => {
@@ -170,6 +178,38 @@ const Controler: React.FC<ControlerProps> = ({
       >
         <ReplayRounded />
       </Grid>
+      <Grid
+        item
+        component={Button}
+        onClick={handleBuild}
+        title={msgs.buildSite}
+        css={css({
+          maxWidth: "10%",
+          height: "auto",
+          "&:hover": {
+            backgroundColor: "#42C0C2",
+          },
+          borderRadius: theme.shape.borderRadius,
+        })}
+      >
+        <PublicRounded />
+      </Grid>
+      <Grid
+        item
+        component={Button}
+        onClick={handleEpub}
+        title={msgs.exportEpub}
+        css={css({
+          maxWidth: "10%",
+          height: "auto",
+          "&:hover": {
+            backgroundColor: "#42C0C2",
+          },
+          borderRadius: theme.shape.borderRadius,
+        })}
+      >
+        <MenuBookRounded />
+      </Grid>
       <Grid item css={half}>
         <Accordion
           square
@@ -587,8 +627,8 @@ const Browse: React.FC = () => {
     });
     setLoad(undefined);
   };
-  const { handleErr } = useSnackHandler();
-  const { getDocumentsByFilter } = useCommand();
+  const { handleSuc, handleErr } = useSnackHandler();
+  const { getDocumentsByFilter, buildSite, exportEpub } = useCommand();
   const [requestOption, setRequestOption] = useState<RequestDocs>({
     offset: 0,
     limit: 15,
@@ -599,8 +639,61 @@ const Browse: React.FC = () => {
     tags: [],
     author: "",
     is_html_src_exists: null,
+    heading_contain: "",
   });
 
+  const handleBuild = async () => {
+    const path = await dialog
+      .open({
+        directory: true,
+        multiple: false,
+      })
+      .catch(() => "");
+    if (path && typeof path === "string") {
+      // eslint-disable-next-line @typescript-eslint/no-unused-vars
+      const { offset, limit, ...filter } = requestOption;
+      buildSite(filter, path)
+        .then((id) => handleSuc(`${msgs.queued} #${id}`))
+        .catch((err) => handleErr((err as Response).message));
+    }
+  };
+  const handleEpub = async () => {
+    const path = await dialog
+      .open({
+        directory: true,
+        multiple: false,
+      })
+      .catch(() => "");
+    if (path && typeof path === "string") {
+      // eslint-disable-next-line @typescript-eslint/no-unused-vars
+      const { offset, limit, ...filter } = requestOption;
+      const options = {
+        title: (filter.tags || []).join(", "),
+        sort: "created_at" as const,
+        descending: false,
+      };
+      exportEpub(filter, options, path)
+        .then((id) => handleSuc(`${msgs.queued} #${id}`))
+        .catch((err) => handleErr((err as Response).message));
+    }
+  };
+
+  useEffect(() => {
+    let unlisten: UnlistenFn | undefined = undefined;
+    listen("workspace_changed", (e: TauriEvent<WorkspaceChanged>) => {
+      if (e.payload.target === "index") handleLoad();
+    })
+      .then((ulf) => {
+        unlisten = ulf;
+      })
+      .catch((err) => handleErr(err.message));
+
+    return () => {
+      if (unlisten) unlisten();
+    };
+    // eslint-disable-next-line
+  }, []);
+
   useEffect(() => {
     (async () => {
       const res = await getDocumentsByFilter(requestOption).catch((err) => {
@@ -629,6 +722,8 @@ const Browse: React.FC = () => {
       <Controler
         handleLoad={handleLoad}
         handleTag={handleTag}
+        handleBuild={handleBuild}
+        handleEpub={handleEpub}
         allTags={result.all_tags}
         selectedTags={requestOption.tags || []}
       />
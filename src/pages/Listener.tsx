@@ -2,6 +2,7 @@
 import React, { useRef, useEffect, useState } from "react";
 import { css } from "@emotion/react";
 import Markdown from "../components/Markdown";
+import { MacrosProvider } from "../context/Macros";
 import { Meta } from "../redux/write";
 import { Event as TauriEvent } from "@tauri-apps/api/event";
 import { getCurrent } from "@tauri-apps/api/window";
@@ -54,7 +55,9 @@ const Listner: React.FC = () => {
       `}
       ref={container}
     >
-      <Markdown md={state.body} container={container} />
+      <MacrosProvider shortcut={state.meta.shortcut}>
+        <Markdown md={state.body} container={container} />
+      </MacrosProvider>
     </div>
   );
 };
@@ -11,14 +11,35 @@ import {
   Button,
 } from "@mui/material";
 import { alpha } from "@mui/material/styles";
-import { SettingType, languages, Language } from "../redux/settings";
+import {
+  SettingType,
+  languages,
+  Language,
+  frontMatters,
+  FrontMatter,
+  layouts,
+  Layout,
+  pageSizes,
+  PageSize,
+  variants,
+  Variant,
+} from "../redux/settings";
 import { useAppDispatch, useSettings } from "../redux/hooks";
 import { NoTeXSettings } from "../redux/settings";
-import useCommand, { Response } from "../api/command";
+import useCommand, {
+  Response,
+  MigrateProgress,
+  JobId,
+  JobResult,
+  LinkReport,
+  LinkReportResult,
+} from "../api/command";
 import { useSnackHandler } from "../context/SnackHandler";
 import utilMsg from "../utils/constant/util";
 import settingMsg from "../utils/constant/setting";
+import { macrosToText, textToMacros } from "../utils/lib/macros";
 import { dialog } from "@tauri-apps/api";
+import { listen, Event as TauriEvent, UnlistenFn } from "@tauri-apps/api/event";
 
 const label = (theme: Theme) =>
   css({
@@ -36,16 +57,129 @@ const labeled = (theme: Theme) =>
 
 const Settings: React.FC = () => {
   const theme = useTheme();
-  const { handleSuc, handleErr } = useSnackHandler();
+  const { handleSuc, handleWarn, handleErr } = useSnackHandler();
   const [langOpen, setLangOpen] = useState(false);
+  const [matterOpen, setMatterOpen] = useState(false);
+  const [layoutOpen, setLayoutOpen] = useState(false);
+  const [pageSizeOpen, setPageSizeOpen] = useState(false);
+  const [variantOpen, setVariantOpen] = useState(false);
   const dispatch = useAppDispatch();
-  const [temp, setTemp] = useState<SettingType>(useSettings());
+  const current = useSettings();
+  const [temp, setTemp] = useState<SettingType>(current);
   const msgs = {
     ...utilMsg(temp.language),
     ...settingMsg(temp.language),
   };
-  const { updateSetting } = useCommand();
+  const {
+    updateSetting,
+    changeTargetDir,
+    changeLayout,
+    rebuildIndex,
+    gcAttachments,
+    checkLinks,
+  } = useCommand();
+  const handleRebuild = async () => {
+    const res = await rebuildIndex().catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (res) handleSuc(res.message);
+  };
+  const handleGc = async () => {
+    const res = await gcAttachments().catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (res) handleSuc(res.message);
+  };
+  const showReport = (res: LinkReport) => {
+    const problems = res.documents.reduce(
+      (sum, doc) => sum + doc.problems.length,
+      0
+    );
+    if (problems === 0) handleSuc(`${msgs.noLinkProblems} (${res.checked})`);
+    else
+      handleWarn(
+        `${msgs.linkProblems}: ${problems} / ${res.documents
+          .map((doc) => doc.title)
+          .join(", ")}`
+      );
+  };
+  /*
+   * only the summary is shown here. external urls are not requested from the settings.
+   * the check is a job, whose report or failure may come before its id.
+   */
+  const handleCheckLinks = async () => {
+    const unlisten: UnlistenFn[] = [];
+    const early = new Map<JobId, LinkReport | undefined>();
+    let id: JobId | undefined = undefined;
+    const finish = (jobId: JobId, res: LinkReport | undefined) => {
+      if (id === undefined) {
+        early.set(jobId, res);
+      } else if (jobId === id) {
+        for (const ulf of unlisten) ulf();
+        if (res) showReport(res);
+      }
+    };
+    const listening = await Promise.all([
+      listen("link_report", (e: TauriEvent<LinkReportResult>) =>
+        finish(e.payload.id, e.payload)
+      ),
+      listen("job_failed", (e: TauriEvent<JobResult>) =>
+        finish(e.payload.id, undefined)
+      ),
+    ]).catch((err) => {
+      handleErr(err.message);
+      return undefined;
+    });
+    if (!listening) return;
+    unlisten.push(...listening);
+
+    id = await checkLinks().catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (id === undefined) {
+      for (const ulf of unlisten) ulf();
+    } else if (early.has(id)) {
+      finish(id, early.get(id));
+    }
+  };
+  const handleTargetDir = async () => {
+    const isMove = await dialog.ask(msgs.moveOrCopy, msgs.targetDir);
+    const ulf = await listen(
+      "migrate_progress",
+      (e: TauriEvent<MigrateProgress>) => {
+        handleSuc(
+          `${msgs.migrating} (${e.payload.phase}): ${e.payload.done}/${e.payload.total}`
+        );
+      }
+    ).catch(() => undefined);
+    const res = await changeTargetDir(
+      temp.target_dir,
+      isMove ? "move" : "copy"
+    ).catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (ulf) ulf();
+
+    return res;
+  };
   const handleUpdate = async () => {
+    if (temp.target_dir !== current.target_dir) {
+      const migrated = await handleTargetDir();
+      if (!migrated) return;
+      handleSuc(migrated.message);
+    }
+    if (temp.layout !== current.layout) {
+      const relocated = await changeLayout(temp.layout).catch((err) => {
+        handleErr((err as Response).message);
+        return undefined;
+      });
+      if (!relocated) return;
+      handleSuc(relocated.message);
+    }
     const res = await updateSetting(temp).catch((err) => {
       handleErr((err as Response).message);
       return undefined;
@@ -173,6 +307,331 @@ const Settings: React.FC = () => {
           </MenuItem>
         ))}
       </Select>
+      <span css={label}>{msgs.frontMatter}</span>
+      <Select
+        id={"front_matter_selector"}
+        open={matterOpen}
+        onOpen={() => setMatterOpen(true)}
+        onClose={() => setMatterOpen(false)}
+        onChange={(e) =>
+          setTemp({
+            ...temp,
+            front_matter: e.target.value as FrontMatter,
+          })
+        }
+        value={temp.front_matter}
+        css={labeled}
+      >
+        {frontMatters.map((fm) => (
+          <MenuItem value={fm} key={"front_matter_selector_" + fm}>
+            {fm}
+          </MenuItem>
+        ))}
+      </Select>
+      <span css={label}>{msgs.layout}</span>
+      <Select
+        id={"layout_selector"}
+        open={layoutOpen}
+        onOpen={() => setLayoutOpen(true)}
+        onClose={() => setLayoutOpen(false)}
+        onChange={(e) =>
+          setTemp({
+            ...temp,
+            layout: e.target.value as Layout,
+          })
+        }
+        value={temp.layout}
+        css={labeled}
+      >
+        {layouts.map((layout) => (
+          <MenuItem value={layout} key={"layout_selector_" + layout}>
+            {layout}
+          </MenuItem>
+        ))}
+      </Select>
+      <span css={label}>{msgs.siteTitle}</span>
+      <TextField
+        label={msgs.siteTitle}
+        defaultValue={temp.site.title}
+        onBlur={(e) =>
+          setTemp({
+            ...temp,
+            site: { ...temp.site, title: e.target.value },
+          })
+        }
+        css={labeled}
+      />
+      <span css={label}>{msgs.siteBaseUrl}</span>
+      <TextField
+        label={msgs.siteBaseUrl}
+        defaultValue={temp.site.base_url}
+        placeholder={"https://example.com/notes"}
+        onBlur={(e) =>
+          setTemp({
+            ...temp,
+            site: { ...temp.site, base_url: e.target.value },
+          })
+        }
+        css={labeled}
+      />
+      <span css={label}>{msgs.pageSize}</span>
+      <div css={labeled}>
+        <Select
+          id={"page_size_selector"}
+          open={pageSizeOpen}
+          onOpen={() => setPageSizeOpen(true)}
+          onClose={() => setPageSizeOpen(false)}
+          onChange={(e) =>
+            setTemp({
+              ...temp,
+              pdf: { ...temp.pdf, page_size: e.target.value as PageSize },
+            })
+          }
+          value={temp.pdf.page_size}
+        >
+          {pageSizes.map((size) => (
+            <MenuItem value={size} key={"page_size_selector_" + size}>
+              {size}
+            </MenuItem>
+          ))}
+        </Select>
+        <FormControlLabel
+          control={
+            <Switch
+              checked={temp.pdf.landscape}
+              onChange={() =>
+                setTemp({
+                  ...temp,
+                  pdf: { ...temp.pdf, landscape: !temp.pdf.landscape },
+                })
+              }
+            />
+          }
+          label={msgs.landscape}
+        />
+        <FormControlLabel
+          control={
+            <Switch
+              checked={temp.pdf.page_numbers}
+              onChange={() =>
+                setTemp({
+                  ...temp,
+                  pdf: { ...temp.pdf, page_numbers: !temp.pdf.page_numbers },
+                })
+              }
+            />
+          }
+          label={msgs.pageNumbers}
+        />
+      </div>
+      <span css={label}>{msgs.margin}</span>
+      <div css={labeled}>
+        {(["top", "bottom", "left", "right"] as const).map((side) => (
+          <TextField
+            key={"margin_" + side}
+            label={side}
+            type="number"
+            size="small"
+            defaultValue={temp.pdf.margin[side]}
+            onBlur={(e) =>
+              setTemp({
+                ...temp,
+                pdf: {
+                  ...temp.pdf,
+                  margin: {
+                    ...temp.pdf.margin,
+                    [side]: Number(e.target.value) || 0,
+                  },
+                },
+              })
+            }
+          />
+        ))}
+      </div>
+      <span css={label}>{msgs.pdfHeader}</span>
+      <TextField
+        label={msgs.pdfHeader}
+        defaultValue={temp.pdf.header}
+        placeholder={"{{title}}"}
+        onBlur={(e) =>
+          setTemp({
+            ...temp,
+            pdf: { ...temp.pdf, header: e.target.value },
+          })
+        }
+        css={labeled}
+      />
+      <span css={label}>{msgs.pdfFooter}</span>
+      <TextField
+        label={msgs.pdfFooter}
+        defaultValue={temp.pdf.footer}
+        placeholder={"{{date}} {{page}} / {{pages}}"}
+        onBlur={(e) =>
+          setTemp({
+            ...temp,
+            pdf: { ...temp.pdf, footer: e.target.value },
+          })
+        }
+        css={labeled}
+      />
+      <span css={label}>{msgs.browser}</span>
+      <TextField
+        label={msgs.browser}
+        defaultValue={temp.pdf.browser}
+        onBlur={(e) =>
+          setTemp({
+            ...temp,
+            pdf: { ...temp.pdf, browser: e.target.value },
+          })
+        }
+        css={labeled}
+      />
+      <span css={label}>{msgs.images}</span>
+      <div css={labeled}>
+        <FormControlLabel
+          control={
+            <Switch
+              checked={temp.images.optimize}
+              onChange={() =>
+                setTemp({
+                  ...temp,
+                  images: { ...temp.images, optimize: !temp.images.optimize },
+                })
+              }
+            />
+          }
+          label={msgs.optimizeImages}
+        />
+        <FormControlLabel
+          control={
+            <Switch
+              checked={temp.images.strip_exif}
+              onChange={() =>
+                setTemp({
+                  ...temp,
+                  images: {
+                    ...temp.images,
+                    strip_exif: !temp.images.strip_exif,
+                  },
+                })
+              }
+            />
+          }
+          label={msgs.stripExif}
+        />
+        {(["max_dimension", "quality", "thumbnail"] as const).map((key) => (
+          <TextField
+            key={"images_" + key}
+            label={msgs[key]}
+            type="number"
+            size="small"
+            defaultValue={temp.images[key]}
+            onBlur={(e) =>
+              setTemp({
+                ...temp,
+                images: {
+                  ...temp.images,
+                  [key]: Math.max(Number(e.target.value) || 0, 0),
+                },
+              })
+            }
+          />
+        ))}
+        <Select
+          id={"variant_selector"}
+          open={variantOpen}
+          onOpen={() => setVariantOpen(true)}
+          onClose={() => setVariantOpen(false)}
+          onChange={(e) =>
+            setTemp({
+              ...temp,
+              images: { ...temp.images, export: e.target.value as Variant },
+            })
+          }
+          value={temp.images.export}
+        >
+          {variants.map((variant) => (
+            <MenuItem value={variant} key={"variant_selector_" + variant}>
+              {msgs[variant]}
+            </MenuItem>
+          ))}
+        </Select>
+      </div>
+      <span css={label}>{msgs.daily}</span>
+      <div css={labeled}>
+        <TextField
+          label={msgs.dailyTitle}
+          size="small"
+          defaultValue={temp.daily.title}
+          onBlur={(e) =>
+            setTemp({
+              ...temp,
+              daily: { ...temp.daily, title: e.target.value },
+            })
+          }
+        />
+        <TextField
+          label={msgs.dailyTemplate}
+          size="small"
+          defaultValue={temp.daily.template || ""}
+          onBlur={(e) =>
+            setTemp({
+              ...temp,
+              daily: { ...temp.daily, template: e.target.value || null },
+            })
+          }
+        />
+        <TextField
+          label={msgs.dailyTag}
+          size="small"
+          defaultValue={temp.daily.tag}
+          onBlur={(e) =>
+            setTemp({
+              ...temp,
+              daily: { ...temp.daily, tag: e.target.value },
+            })
+          }
+        />
+      </div>
+      <span css={label}>{msgs.macros}</span>
+      <TextField
+        label={msgs.macros}
+        defaultValue={macrosToText(temp.macros)}
+        placeholder={"\\R = \\mathbb{R}"}
+        multiline
+        minRows={3}
+        onBlur={(e) =>
+          setTemp({
+            ...temp,
+            macros: textToMacros(e.target.value),
+          })
+        }
+        css={labeled}
+      />
+      <Button
+        onClick={handleRebuild}
+        css={css({
+          margin: "1rem 1rem 1rem auto",
+        })}
+      >
+        {msgs.rebuildIndex}
+      </Button>
+      <Button
+        onClick={handleGc}
+        css={css({
+          margin: "1rem 1rem 1rem auto",
+        })}
+      >
+        {msgs.gcAttachments}
+      </Button>
+      <Button
+        onClick={handleCheckLinks}
+        css={css({
+          margin: "1rem 1rem 1rem auto",
+        })}
+      >
+        {msgs.checkLinks}
+      </Button>
       <Button
         onClick={handleUpdate}
         css={css({
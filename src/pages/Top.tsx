@@ -18,7 +18,12 @@ import Home from "./Home";
 import Edit from "./Write";
 import Listner from "./Listener";
 import Settings from "./Setting";
-import useCommand, { Response } from "../api/command";
+import useCommand, {
+  Response,
+  WorkspaceChanged,
+  JobProgress,
+  JobResult,
+} from "../api/command";
 import { useSnackHandler } from "../context/SnackHandler";
 import { listen, Event as TauriEvent, UnlistenFn } from "@tauri-apps/api/event";
 
@@ -39,7 +44,20 @@ const Main: React.FC = () => {
       })
       .catch((err) => handleErr(err.message));
 
-    listen("success", (e: TauriEvent<Response>) => {
+    /*
+     * print and export jobs -- see src-tauri/src/jobs.rs
+     */
+    listen("job_progress", (e: TauriEvent<JobProgress>) => {
+      handleSuc(
+        `${e.payload.message} (${e.payload.done}/${e.payload.total})`
+      );
+    })
+      .then((ulf) => {
+        unlisten.push(ulf);
+      })
+      .catch((err) => handleErr(err.message));
+
+    listen("job_done", (e: TauriEvent<JobResult>) => {
       handleSuc(e.payload.message);
     })
       .then((ulf) => {
@@ -47,7 +65,7 @@ const Main: React.FC = () => {
       })
       .catch((err) => handleErr(err.message));
 
-    listen("fail", (e: TauriEvent<Response>) => {
+    listen("job_failed", (e: TauriEvent<JobResult>) => {
       handleErr(e.payload.message);
     })
       .then((ulf) => {
@@ -103,7 +121,7 @@ const Top: React.FC = () => {
   const { handleErr } = useSnackHandler();
 
   useEffect(() => {
-    (async () => {
+    const loadSetting = async () => {
       const setting = await getSetting().catch((err) => {
         handleErr((err as Response).message);
         return undefined;
@@ -111,7 +129,24 @@ const Top: React.FC = () => {
       if (setting) {
         dispatch(NoTeXSettings.setSettings(setting));
       }
-    })();
+    };
+    loadSetting();
+
+    /*
+     * notex.conf may be edited outside the app -- see src-tauri/src/watcher.rs
+     */
+    let unlisten: UnlistenFn | undefined = undefined;
+    listen("workspace_changed", (e: TauriEvent<WorkspaceChanged>) => {
+      if (e.payload.target === "setting") loadSetting();
+    })
+      .then((ulf) => {
+        unlisten = ulf;
+      })
+      .catch((err) => handleErr(err.message));
+
+    return () => {
+      if (unlisten) unlisten();
+    };
     // eslint-disable-next-line
   }, []);
 
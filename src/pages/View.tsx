@@ -2,120 +2,29 @@
 import React, { useRef, useState, useEffect } from "react";
 import { useLocation } from "react-router-dom";
 import { css, useTheme, Theme } from "@emotion/react";
-import { Button, CircularProgress } from "@mui/material";
+import {
+  Button,
+  Checkbox,
+  CircularProgress,
+  FormControlLabel,
+  MenuItem,
+  Select,
+} from "@mui/material";
 import { alpha } from "@mui/material/styles";
-import useCommand, { Response } from "../api/command";
-import { Meta } from "../redux/write";
-import { Language } from "../redux/settings";
+import useCommand, {
+  Response,
+  JobId,
+  JobResult,
+  Backlinks,
+} from "../api/command";
+import utilMsg from "../utils/constant/util";
+import { Meta, Section } from "../redux/write";
 import Markdown from "../components/Markdown";
 import { useSettings } from "../redux/hooks";
 import { dialog } from "@tauri-apps/api";
-import { getCurrent } from "@tauri-apps/api/window";
-import { Event as TauriEvent } from "@tauri-apps/api/event";
+import { listen, Event as TauriEvent, UnlistenFn } from "@tauri-apps/api/event";
 import { useSnackHandler } from "../context/SnackHandler";
-import scrollRegister from "../utils/lib/scrollRegister";
-
-const notexScripts: Function[] = [scrollRegister];
-/**
- * created html may cause xss
- */
-const createHTMLSource = (
-  meta: Meta,
-  templateHtml: string,
-  templateCss: string,
-  styles: HTMLCollectionOf<HTMLStyleElement>, //stylesheet required by mui, katex, ...etc and created by emotion
-  contents: string, //html rendered from markdown
-  lang?: Language
-) => {
-  const parser = new DOMParser();
-  const doc = parser.parseFromString(templateHtml, "text/html");
-  const html = doc.documentElement;
-  switch (lang) {
-    case "english":
-      html.setAttribute("lang", "en");
-      break;
-    case "japanese":
-      html.setAttribute("lang", "ja");
-      break;
-  }
-
-  /**
-   * head building
-   */
-  {
-    const head = doc.head;
-    if (!head.querySelector("meta[charset]")) {
-      const encoding = document.createElement("meta");
-      encoding.setAttribute("charset", "UTF-8");
-      head.appendChild(encoding);
-    }
-    if (meta.author && !head.querySelector('meta[name="author"]')) {
-      const author = document.createElement("meta");
-      author.setAttribute("author", meta.author);
-      head.appendChild(author);
-    }
-    const katexCdn = document.createElement("link");
-    katexCdn.setAttribute("rel", "stylesheet");
-    katexCdn.setAttribute(
-      "href",
-      "https://cdn.jsdelivr.net/npm/katex@0.15.1/dist/katex.min.css"
-    );
-    katexCdn.setAttribute(
-      "integrity",
-      "sha384-R4558gYOUz8mP9YWpZJjofhk+zx0AS11p36HnD2ZKj/6JR5z27gSSULCNHIRReVs"
-    );
-    katexCdn.setAttribute("crossorigin", "anonymous");
-    head.appendChild(katexCdn);
-
-    const muiCdn = document.createElement("link");
-    muiCdn.setAttribute("rel", "stylesheet");
-    muiCdn.setAttribute(
-      "href",
-      "https://cdnjs.cloudflare.com/ajax/libs/mui/3.7.1/css/mui.min.css"
-    );
-    muiCdn.setAttribute("type", "text/css");
-    muiCdn.setAttribute("media", "screen");
-    head.appendChild(muiCdn);
-
-    const mermaidCdn = document.createElement("link");
-    mermaidCdn.setAttribute("rel", "stylesheet");
-    mermaidCdn.setAttribute(
-      "href",
-      "https://cdnjs.cloudflare.com/ajax/libs/mermaid/7.0.10/mermaid.min.css"
-    );
-    head.appendChild(mermaidCdn);
-
-    for (const style of styles) {
-      if (!!style.dataset["emotion"]) {
-        head.appendChild(style.cloneNode(true));
-      }
-    }
-    const css = document.createElement("style");
-    css.innerText = templateCss;
-    head.appendChild(css);
-  }
-
-  /**
-   * notex main building
-   */
-  const notex = doc.getElementById("notex");
-  if (notex) notex.innerHTML = contents.replaceAll("\n", "");
-
-  /**
-   * script building
-   */
-  {
-    const scripts = document.createElement("script");
-    const loadScripts = notexScripts.reduce(
-      (pre, curr) => `${pre}(${curr.toString().replaceAll("\n", "")})();`,
-      ""
-    );
-    scripts.innerText = loadScripts;
-    doc.body.appendChild(scripts);
-  }
-
-  return html.outerHTML;
-};
+import { MacrosProvider } from "../context/Macros";
 
 const hoverAlpha = 0.5;
 const root = (theme: Theme) => css`
@@ -139,26 +48,121 @@ const controler = css({
   justifyContent: "flex-start",
   alignItems: "center",
 });
-const Controler: React.FC<{ handlePDF: () => void; handleHTML: () => void }> =
-  ({ handlePDF, handleHTML }) => {
-    return (
-      <div css={controler}>
-        <Button onClick={handlePDF} css={buttonIconDefault}>
-          export PDF
-        </Button>
-        <Button onClick={handleHTML} css={buttonIconDefault}>
-          export HTML
+const Controler: React.FC<{
+  handlePDF: () => void;
+  handleHTML: () => void;
+  handleLatex: () => void;
+  templates: string[];
+  template: string;
+  setTemplate: (template: string) => void;
+  standalone: boolean;
+  setStandalone: (standalone: boolean) => void;
+  running: boolean;
+  handleCancel: () => void;
+  cancelLabel: string;
+}> = ({
+  handlePDF,
+  handleHTML,
+  handleLatex,
+  templates,
+  template,
+  setTemplate,
+  standalone,
+  setStandalone,
+  running,
+  handleCancel,
+  cancelLabel,
+}) => {
+  return (
+    <div css={controler}>
+      <Button onClick={handlePDF} css={buttonIconDefault}>
+        export PDF
+      </Button>
+      <Button onClick={handleHTML} css={buttonIconDefault}>
+        export HTML
+      </Button>
+      <Button onClick={handleLatex} css={buttonIconDefault}>
+        export TeX
+      </Button>
+      <Select
+        value={template}
+        onChange={(e) => setTemplate(e.target.value as string)}
+        size="small"
+      >
+        {templates.map((t) => (
+          <MenuItem key={t} value={t}>
+            {t}
+          </MenuItem>
+        ))}
+      </Select>
+      <FormControlLabel
+        control={
+          <Checkbox
+            checked={standalone}
+            onChange={(e) => setStandalone(e.target.checked)}
+            size="small"
+          />
+        }
+        label="single file"
+      />
+      {running && (
+        <Button onClick={handleCancel} css={buttonIconDefault}>
+          {cancelLabel}
         </Button>
-      </div>
-    );
-  };
+      )}
+    </div>
+  );
+};
+
+/*
+ * the backlinks are listed as wiki links, so they navigate as the ones in the body.
+ */
+const linksToMarkdown = (
+  { backlinks, unresolved }: Backlinks,
+  msgs: ReturnType<typeof utilMsg>
+) => {
+  const lines: string[] = [];
+  if (backlinks.length > 0) {
+    lines.push(`### ${msgs.backlinks}`, "");
+    for (const source of [...new Set(backlinks.map((b) => b.source))])
+      lines.push(`- [[${source}]]`);
+    lines.push("");
+  }
+  if (unresolved.length > 0) {
+    lines.push(`### ${msgs.unresolved}`, "");
+    for (const { reason, link } of unresolved)
+      lines.push(
+        `- \`${link.target}${link.heading ? "#" + link.heading : ""}\` ${
+          reason === "missing_note" ? msgs.missingNote : msgs.missingHeading
+        }`
+      );
+  }
+  return lines.join("\n");
+};
+
+/*
+ * the table of contents jumps to the anchors which rehype-slug gives to the headings.
+ */
+const outlineToMarkdown = (outline: Section[]) => {
+  const top = Math.min(...outline.map((s) => s.level));
+  return outline
+    .map(
+      ({ level, text, anchor }) =>
+        `${"  ".repeat(level - top)}- [${text.replace(
+          /([\\[\]])/g,
+          "\\$1"
+        )}](#${anchor})`
+    )
+    .join("\n");
+};
 
 const View: React.FC = () => {
   const location = useLocation<Meta>();
   const theme = useTheme();
   const { handleSuc, handleErr } = useSnackHandler();
   const markdown = useRef<HTMLDivElement>(null);
-  const { language: lang, target_dir: defaultPath } = useSettings();
+  const { target_dir: defaultPath, language } = useSettings();
+  const msgs = utilMsg(language);
   const [load, setLoad] = useState<{
     status: boolean | undefined;
     res: string;
@@ -166,63 +170,118 @@ const View: React.FC = () => {
     status: undefined,
     res: "",
   });
-  const { getDocument, print, html } = useCommand();
+  const {
+    getDocument,
+    exportPdf,
+    exportHtml,
+    exportLatex,
+    listExportTemplates,
+    cancelJob,
+    getBacklinks,
+    getOutline,
+  } = useCommand();
   const meta = location.state;
-  const handlePDF = () =>
-    print(meta, load.res)
-      .then(() => {})
-      .catch(() => {});
-  const handleHTML = async () => {
-    const mainWindow = getCurrent();
-    const path = await dialog
+  const [templates, setTemplates] = useState<string[]>(["default"]);
+  const [template, setTemplate] = useState(meta.template || "default");
+  const [standalone, setStandalone] = useState(false);
+  const [jobs, setJobs] = useState<JobId[]>([]);
+  const [outline, setOutline] = useState<Section[]>([]);
+  const [links, setLinks] = useState<Backlinks>({
+    backlinks: [],
+    unresolved: [],
+  });
+  /*
+   * a job may finish before its id comes back to handleQueued.
+   */
+  const finished = useRef<Set<JobId>>(new Set());
+  const handleQueued = (id: JobId) => {
+    if (finished.current.has(id)) finished.current.delete(id);
+    else setJobs((jobs) => [...jobs, id]);
+    handleSuc(`${msgs.queued} #${id}`);
+  };
+  const handleCancel = () => {
+    for (const id of jobs)
+      cancelJob(id)
+        .then(() => {})
+        .catch((err) => handleErr(err.message));
+  };
+  const selectDir = () =>
+    dialog
       .open({
         defaultPath,
         directory: true,
         multiple: false,
       })
       .catch(() => "");
+  const handlePDF = async () => {
+    const path = await selectDir();
 
     if (path && typeof path === "string") {
-      console.log("template waiting");
-      const ulf = await mainWindow
-        .once(
-          "return_template",
-          (
-            e: TauriEvent<{
-              html: string;
-              css: string;
-              js: string;
-            }>
-          ) => {
-            try {
-              const htmlsrc = createHTMLSource(
-                meta,
-                e.payload.html,
-                e.payload.css,
-                document.head.getElementsByTagName("style"),
-                markdown.current?.innerHTML || "",
-                lang
-              );
+      exportPdf([meta], path)
+        .then(handleQueued)
+        .catch((err) => handleErr(err.message));
+    }
+  };
+  const handleHTML = async () => {
+    const path = await selectDir();
 
-              html(meta, htmlsrc, path)
-                .then(() => handleSuc("html file successfully created"))
-                .catch((err) => handleErr(err.message));
-            } catch (err) {
-              handleErr((err as Error).message);
-            }
-          }
-        )
-        .catch(() => undefined);
+    if (path && typeof path === "string") {
+      exportHtml(meta, template, standalone, path)
+        .then(handleQueued)
+        .catch((err) => handleErr(err.message));
+    }
+  };
+  const handleLatex = async () => {
+    const path = await selectDir();
 
-      if (ulf !== undefined) {
-        await mainWindow.emit("template").catch((err) => {
-          handleErr(err.message);
-          return undefined;
-        });
-      }
+    if (path && typeof path === "string") {
+      exportLatex(meta, path)
+        .then(handleQueued)
+        .catch((err) => handleErr(err.message));
     }
   };
 
+  useEffect(() => {
+    const unlisten: UnlistenFn[] = [];
+    const finish = (e: TauriEvent<JobResult>) =>
+      setJobs((jobs) => {
+        if (!jobs.includes(e.payload.id)) finished.current.add(e.payload.id);
+        return jobs.filter((id) => id !== e.payload.id);
+      });
+    for (const event of ["job_done", "job_failed"])
+      listen(event, finish)
+        .then((ulf) => {
+          unlisten.push(ulf);
+        })
+        .catch((err) => handleErr(err.message));
+
+    return () => {
+      for (const ulf of unlisten) ulf();
+    };
+    //eslint-disable-next-line
+  }, []);
+
+  useEffect(() => {
+    listExportTemplates()
+      .then(setTemplates)
+      .catch((err) => handleErr(err.message));
+    //eslint-disable-next-line
+  }, []);
+
+  /*
+   * wiki links navigate from a view to another, which keeps this page mounted.
+   */
+  useEffect(() => {
+    setLoad({ status: undefined, res: "" });
+    getBacklinks(meta)
+      .then(setLinks)
+      .catch((err) => handleErr(err.message));
+    getOutline(meta)
+      .then(setOutline)
+      .catch((err) => handleErr(err.message));
+    //eslint-disable-next-line
+  }, [meta.filename]);
+
   useEffect(() => {
     if (load.status === undefined)
       (async () => {
@@ -248,8 +307,32 @@ const View: React.FC = () => {
     <CircularProgress css={root(theme)} />
   ) : load.status ? (
     <div css={root(theme)}>
-      <Controler handlePDF={handlePDF} handleHTML={handleHTML} />
-      <Markdown md={load.res} ref={markdown} />
+      <Controler
+        handlePDF={handlePDF}
+        handleHTML={handleHTML}
+        handleLatex={handleLatex}
+        templates={templates}
+        template={template}
+        setTemplate={setTemplate}
+        standalone={standalone}
+        setStandalone={setStandalone}
+        running={jobs.length > 0}
+        handleCancel={handleCancel}
+        cancelLabel={msgs.cancel}
+      />
+      {meta.stats && meta.stats.words > 0 && (
+        <div>
+          {meta.stats.words} {msgs.words} ・{" "}
+          {Math.max(1, Math.round(meta.stats.reading_time / 60))} {msgs.minutes}
+        </div>
+      )}
+      {outline.length > 1 && <Markdown md={outlineToMarkdown(outline)} />}
+      <MacrosProvider shortcut={meta.shortcut}>
+        <Markdown md={load.res} ref={markdown} />
+      </MacrosProvider>
+      {(links.backlinks.length > 0 || links.unresolved.length > 0) && (
+        <Markdown md={linksToMarkdown(links, msgs)} />
+      )}
     </div>
   ) : (
     <div css={root(theme)}>
@@ -1,6 +1,5 @@
 /** @jsxImportSource @emotion/react */
 import React, {
-  ChangeEvent,
   forwardRef,
   useEffect,
   useImperativeHandle,
@@ -26,14 +25,21 @@ import {
 import MoreVertIcon from "@mui/icons-material/MoreVert";
 import ImageIcon from "@mui/icons-material/Image";
 import LoopIcon from "@mui/icons-material/Loop";
-import useCommand, { Response } from "../api/command";
+import useCommand, {
+  Attachment,
+  Response,
+  WorkspaceChanged,
+} from "../api/command";
 import { useSettings } from "../redux/hooks";
 import { Meta } from "../redux/write";
 import { useSnackHandler } from "../context/SnackHandler";
+import { MacrosProvider } from "../context/Macros";
 import useKeyAction, { useNativeKeyAction } from "../hooks/Keyboard";
 import writeMsg from "../utils/constant/write/write";
 import Markdown from "../components/Markdown";
 import { Z_INDEXES } from "../utils/constant/util";
+import { listen, Event as TauriEvent, UnlistenFn } from "@tauri-apps/api/event";
+import { dialog } from "@tauri-apps/api";
 
 const contentsHeight = 80;
 const hoverAlpha = 0.5;
@@ -59,24 +65,6 @@ const scrollable = css`
   }
 `;
 
-const useFileControll = (handleBlobUrl: (url: string) => void) => {
-  const fileRef = useRef<HTMLInputElement | null>(null);
-  const reader = new FileReader();
-  reader.onload = (e: ProgressEvent<FileReader>) => {
-    if (e.target && typeof e.target.result === "string")
-      handleBlobUrl(e.target.result);
-  };
-  const handleChange = (_: ChangeEvent) => {
-    if (fileRef.current && fileRef.current.files?.length)
-      reader.readAsDataURL(fileRef.current.files[0]);
-  };
-
-  return {
-    fileRef,
-    handleChange,
-  };
-};
-
 const buttonIconDefault = (theme: Theme) => css`
   min-width: 5%;
   height: 100%;
@@ -102,7 +90,7 @@ type ControlerProps = {
   meta: Meta;
   overwrite: boolean;
   dispatchMeta: React.Dispatch<MetaUpdateAction>;
-  handleImageUrl: (url: string) => void;
+  handleAttach: () => void;
   handleSave: () => void;
   sync: boolean;
   setSync: React.Dispatch<React.SetStateAction<boolean>>;
@@ -112,7 +100,7 @@ const Controler: React.FC<ControlerProps> = ({
   meta,
   overwrite,
   dispatchMeta,
-  handleImageUrl,
+  handleAttach,
   handleSave,
   sync,
   setSync,
@@ -204,7 +192,6 @@ const Controler: React.FC<ControlerProps> = ({
   );
 
   const handleStop = (e: React.MouseEvent) => e.stopPropagation();
-  const { fileRef, handleChange } = useFileControll(handleImageUrl);
 
   return (
     <>
@@ -212,10 +199,7 @@ const Controler: React.FC<ControlerProps> = ({
         <Button onClick={() => setOpen(!open)} css={buttonIconDefault}>
           <MoreVertIcon />
         </Button>
-        <Button
-          onClick={() => fileRef.current?.click()}
-          css={buttonIconDefault}
-        >
+        <Button onClick={handleAttach} css={buttonIconDefault}>
           <ImageIcon />
         </Button>
         <FormControlLabel
@@ -253,15 +237,6 @@ const Controler: React.FC<ControlerProps> = ({
         >
           {writeMsg(lang).save}
         </Button>
-        <input
-          type={"file"}
-          accept={".png,.jpg,.jpeg,.gif"}
-          ref={fileRef}
-          onChange={handleChange}
-          css={css({
-            display: "none",
-          })}
-        />
       </div>
       <Collapse
         in={open}
@@ -571,18 +546,20 @@ const Edit: React.FC = () => {
   const rawInputRef = useRef<RawInputHandle>(null);
   const [load, setLoad] = useState<ShouldUpdate>(ShouldUpdate.DONE);
   const overwrite = useRef(false);
+  const base = useRef<string | null>(null); //the body on disk which the editing started from
   const [sync, setSync] = useState(false);
   const handleSync = () => setLoad(ShouldUpdate.USERIN);
   const handleRaw = (arg: string) => {
     setRaw(arg);
     if (sync) handleSync();
   };
-  const handleImageUrl = (url: string) => {
+  const handleAttachment = (attachment: Attachment) => {
     const caretpos = rawInputRef.current?.selectionEnd() || raw.length;
+    const link = `[${attachment.name}](${attachment.link})`;
     setRaw(
-      `${raw.slice(0, caretpos)}![may not be image](${url})${raw.slice(
-        caretpos
-      )}`
+      `${raw.slice(0, caretpos)}${
+        attachment.mime.startsWith("image/") ? "!" : ""
+      }${link}${raw.slice(caretpos)}`
     );
     handleSync();
   };
@@ -625,20 +602,65 @@ const Edit: React.FC = () => {
       html_src: false,
     }
   );
-  const { handleSuc, handleErr } = useSnackHandler();
-  const { saveDocument, getDocument } = useCommand();
+  const { handleSuc, handleWarn, handleErr } = useSnackHandler();
+  const { saveDocument, getDocument, addAttachment, renameDocument } =
+    useCommand();
+  /*
+   * the file is copied into the attachment store, so the link keeps working across machines and exports.
+   */
+  const handleAttach = async () => {
+    const path = await dialog
+      .open({ directory: false, multiple: false })
+      .catch((_) => null);
+    if (typeof path !== "string") return;
+    await addAttachment(path)
+      .then(handleAttachment)
+      .catch((err) => handleErr((err as Response).message));
+  };
+  const lang = useSettings().language;
+  /*
+   * a changed title of a saved note is either a rename, which the other notes follow, or a copy.
+   * false if the rename failed, and the save should not go on.
+   */
+  const handleRename = async () => {
+    const saved = location.state;
+    if (!saved?.filename || saved.filename === meta.filename) return true;
+    if (!(await dialog.ask(writeMsg(lang).renameOrCopy).catch(() => false)))
+      return true;
+    const renamed = await renameDocument(saved, meta.filename).catch((err) => {
+      handleErr((err as Response).message);
+      return undefined;
+    });
+    if (!renamed) return false;
+    location.state = renamed;
+    overwrite.current = true;
+    return true;
+  };
   const handleSave = async () => {
     if (meta?.filename) {
-      const res = await saveDocument(meta, raw, overwrite.current).catch(
-        (err) => {
+      if (!(await handleRename())) return;
+      const res = await saveDocument(
+        meta,
+        raw,
+        overwrite.current,
+        base.current
+      ).catch((err) => {
+        const conflict = (err as Response).conflict;
+        if (conflict) {
+          base.current = conflict.on_disk;
+          setRaw(conflict.proposal.body);
+          handleSync();
+          handleWarn((err as Response).message);
+        } else {
           handleErr((err as Response).message);
-          return undefined;
         }
-      );
+        return undefined;
+      });
       if (res) {
         handleSuc(res.message);
-        location.state = meta;
+        location.state = res.meta ?? meta;
         overwrite.current = true;
+        base.current = raw;
       }
     } else {
       handleErr("File Name must contain at least one character");
@@ -671,6 +693,16 @@ const Edit: React.FC = () => {
       );
     }
 
+    let unlisten: UnlistenFn | undefined = undefined;
+    listen("workspace_changed", (e: TauriEvent<WorkspaceChanged>) => {
+      if (e.payload.target === "index" && overwrite.current)
+        handleWarn(writeMsg(lang).externalChange);
+    })
+      .then((ulf) => {
+        unlisten = ulf;
+      })
+      .catch(() => {});
+
     if (location.state) {
       (async () => {
         const body = await getDocument(location.state).catch((err) => {
@@ -679,6 +711,7 @@ const Edit: React.FC = () => {
         });
 
         if (body !== undefined) {
+          base.current = body;
           setRaw(body);
           dispatchMeta({
             type: "set",
@@ -691,6 +724,7 @@ const Edit: React.FC = () => {
     }
 
     return () => {
+      if (unlisten) unlisten();
       if (autosave) {
         clearInterval(autosave);
       }
@@ -717,14 +751,16 @@ const Edit: React.FC = () => {
         meta={meta}
         overwrite={overwrite.current}
         dispatchMeta={dispatchMeta}
-        handleImageUrl={handleImageUrl}
+        handleAttach={handleAttach}
         handleSave={handleSave}
         sync={sync}
         setSync={setSync}
         handleSync={handleSync}
       />
       <RawInput raw={raw} handleRaw={handleRaw} ref={rawInputRef} />
-      <Preview data={{ raw, load }} setLoad={setLoad} sync={sync} />
+      <MacrosProvider shortcut={meta.shortcut}>
+        <Preview data={{ raw, load }} setLoad={setLoad} sync={sync} />
+      </MacrosProvider>
     </Grid>
   );
 };
@@ -14,6 +14,58 @@ type KeyBindings = {
 export const languages = ["japanese", "english"] as const;
 export type Language = typeof languages[number];
 
+export const frontMatters = ["none", "yaml", "toml"] as const;
+export type FrontMatter = typeof frontMatters[number];
+
+export const layouts = [
+  "hashed",
+  "readable",
+  "readable_by_tag",
+  "readable_by_date",
+] as const;
+export type Layout = typeof layouts[number];
+
+export type SiteOptions = {
+  title: string;
+  base_url: string; //if empty, sitemap and feed are not generated
+};
+
+export const pageSizes = ["A4", "A5", "B5", "Letter", "Legal"] as const;
+export type PageSize = typeof pageSizes[number];
+
+export type PdfOptions = {
+  page_size: PageSize;
+  landscape: boolean;
+  margin: { top: number; bottom: number; left: number; right: number }; //in millimeters
+  header: string; //{{title}}, {{date}}, {{page}} and {{pages}} are replaced
+  footer: string;
+  page_numbers: boolean; //if footer is empty, page numbers are the footer
+  browser: string; //if empty, chrome or edge is searched
+};
+
+export const variants = ["optimized", "original"] as const;
+export type Variant = typeof variants[number];
+
+export type ImageOptions = {
+  optimize: boolean;
+  max_dimension: number; //of the longer side in pixels. if 0, not downscaled
+  quality: number; //of jpeg, 1 to 100
+  thumbnail: number; //of the longer side in pixels. if 0, no thumbnail
+  strip_exif: boolean;
+  export: Variant; //which the exports use unless they are told
+};
+
+/*
+ * a daily note is titled by the date in the pattern, and links to the ones before and after it.
+ */
+export type DailyOptions = {
+  title: string; //strftime pattern, e.g. %Y-%m-%d
+  template: string | null; //name of the document template. if null, the notes start empty
+  tag: string; //given to the daily notes. if empty, none
+};
+
+export type Macros = { [name: string]: string };
+
 export type SettingType = {
   target_dir: string;
   username: string;
@@ -22,6 +74,13 @@ export type SettingType = {
   language: Language;
   autosave: number | null;
   key_bindings: KeyBindings;
+  front_matter: FrontMatter;
+  layout: Layout;
+  site: SiteOptions;
+  pdf: PdfOptions;
+  images: ImageOptions;
+  daily: DailyOptions;
+  macros: Macros; //math macros of the workspace, under the shortcut of each document
 };
 
 const initialState: SettingType = {
@@ -32,6 +91,35 @@ const initialState: SettingType = {
   language: "english",
   autosave: null,
   key_bindings: {},
+  front_matter: "none",
+  layout: "hashed",
+  site: {
+    title: "",
+    base_url: "",
+  },
+  pdf: {
+    page_size: "A4",
+    landscape: false,
+    margin: { top: 20, bottom: 20, left: 15, right: 15 },
+    header: "",
+    footer: "",
+    page_numbers: true,
+    browser: "",
+  },
+  images: {
+    optimize: true,
+    max_dimension: 1920,
+    quality: 80,
+    thumbnail: 256,
+    strip_exif: true,
+    export: "optimized",
+  },
+  daily: {
+    title: "%Y-%m-%d",
+    template: null,
+    tag: "daily",
+  },
+  macros: {},
 };
 
 const settingsSlice = createSlice({
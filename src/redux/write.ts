@@ -23,6 +23,34 @@ export type Meta = {
   tags: string[];
   shortcut: { [command: string]: string };
   html_src: boolean;
+  readonly digest?: string | null;
+  readonly mtime?: number | null;
+  readonly path?: string | null;
+  readonly template?: string | null;
+  readonly outline?: Section[]; //headings when last saved
+  readonly stats?: Stats; //of the body when last saved
+};
+
+/*
+ * a heading of a body. line is 1-based without front matter.
+ */
+export type Section = {
+  level: number;
+  text: string;
+  anchor: string;
+  line: number;
+};
+
+/*
+ * code and math are not counted as words. reading_time is in seconds.
+ */
+export type Stats = {
+  words: number;
+  characters: number;
+  math: number;
+  code: number;
+  images: number;
+  reading_time: number;
 };
 
 const initialState: Document = {
@@ -4,7 +4,9 @@ import {Meta} from "../../redux/write"
 type BrowseMsg = {
   searchByTag: string;
   tagName: string;
-  confirmDelete: string
+  confirmDelete: string;
+  buildSite: string;
+  exportEpub: string;
 } & { [key in keyof Omit<Meta, "shortcut">]: string }
 
 export default createMsg<BrowseMsg>({
@@ -17,7 +19,9 @@ export default createMsg<BrowseMsg>({
     html_src: "HTMLソース",
     searchByTag: "タグで検索",
     tagName: "タグ名",
-    confirmDelete: "次のドキュメントを削除してよろしいですか？"
+    confirmDelete: "次のドキュメントを削除してよろしいですか？",
+    buildSite: "表示中の条件でサイトを生成",
+    exportEpub: "表示中の条件でEPUBを作成"
   },
   english: {
     filename: "File name",
@@ -28,6 +32,8 @@ export default createMsg<BrowseMsg>({
     html_src: "HTML source",
     searchByTag: "Search by Tag",
     tagName: "Tag name",
-    confirmDelete: "Please confirm to delete "
+    confirmDelete: "Please confirm to delete ",
+    buildSite: "Build a site from the documents matching the filter",
+    exportEpub: "Make an EPUB of the documents matching the filter"
   }
 })
\ No newline at end of file
@@ -2,13 +2,106 @@ import createMsg from "../lib/msg";
 
 type SettingMsg = {
   targetDir: string;
+  moveOrCopy: string;
+  migrating: string;
+  frontMatter: string;
+  rebuildIndex: string;
+  gcAttachments: string;
+  checkLinks: string;
+  linkProblems: string;
+  noLinkProblems: string;
+  layout: string;
+  siteTitle: string;
+  siteBaseUrl: string;
+  pageSize: string;
+  landscape: string;
+  margin: string;
+  pdfHeader: string;
+  pdfFooter: string;
+  pageNumbers: string;
+  browser: string;
+  macros: string;
+  images: string;
+  optimizeImages: string;
+  stripExif: string;
+  max_dimension: string;
+  quality: string;
+  thumbnail: string;
+  optimized: string;
+  original: string;
+  daily: string;
+  dailyTitle: string;
+  dailyTemplate: string;
+  dailyTag: string;
 };
 
 export default createMsg<SettingMsg>({
   japanese: {
     targetDir: "保存先フォルダ",
+    moveOrCopy: "既存のドキュメントを新しいフォルダへ移動しますか? (キャンセルでコピー)",
+    migrating: "ドキュメントを移行中",
+    frontMatter: "フロントマター",
+    rebuildIndex: "インデックスを再構築",
+    gcAttachments: "使われていない添付ファイルを削除",
+    checkLinks: "リンクを検査",
+    linkProblems: "壊れたリンクがあります",
+    noLinkProblems: "壊れたリンクはありません",
+    layout: "保存形式",
+    siteTitle: "サイト名",
+    siteBaseUrl: "サイトのURL",
+    pageSize: "PDFの用紙サイズ",
+    landscape: "横向き",
+    margin: "PDFの余白 (mm)",
+    pdfHeader: "PDFのヘッダー",
+    pdfFooter: "PDFのフッター",
+    pageNumbers: "ページ番号",
+    browser: "PDF出力に使うブラウザ",
+    macros: "数式マクロ (1行に1つ \\name = 展開)",
+    images: "添付画像",
+    optimizeImages: "縮小・再圧縮した画像を作る",
+    stripExif: "Exif情報を削除",
+    max_dimension: "最大の長辺 (px)",
+    quality: "JPEGの品質",
+    thumbnail: "サムネイルの長辺 (px)",
+    optimized: "エクスポートでは最適化した画像を使う",
+    original: "エクスポートでは元の画像を使う",
+    daily: "デイリーノート",
+    dailyTitle: "タイトルの形式 (%Y-%m-%d など)",
+    dailyTemplate: "テンプレート (空欄で白紙)",
+    dailyTag: "タグ",
   },
   english: {
     targetDir: "target directory",
+    moveOrCopy: "Move existing documents to the new directory? (cancel to copy)",
+    migrating: "migrating documents",
+    frontMatter: "front matter",
+    rebuildIndex: "rebuild index",
+    gcAttachments: "remove unused attachments",
+    checkLinks: "check links",
+    linkProblems: "broken links found",
+    noLinkProblems: "no broken links",
+    layout: "file layout",
+    siteTitle: "site title",
+    siteBaseUrl: "site base URL",
+    pageSize: "PDF page size",
+    landscape: "landscape",
+    margin: "PDF margins (mm)",
+    pdfHeader: "PDF header",
+    pdfFooter: "PDF footer",
+    pageNumbers: "page numbers",
+    browser: "browser for PDF export",
+    macros: "math macros (one per line, \\name = expansion)",
+    images: "attached images",
+    optimizeImages: "make downscaled and re-encoded copies",
+    stripExif: "strip EXIF data",
+    max_dimension: "max longer side (px)",
+    quality: "JPEG quality",
+    thumbnail: "thumbnail longer side (px)",
+    optimized: "exports use the optimized images",
+    original: "exports use the original images",
+    daily: "daily notes",
+    dailyTitle: "title pattern (e.g. %Y-%m-%d)",
+    dailyTemplate: "template (blank if empty)",
+    dailyTag: "tag",
   },
 });
@@ -44,6 +44,17 @@ type UtilMsg = {
   write: string;
   version: string;
   default: string;
+  queued: string;
+  backlinks: string;
+  unresolved: string;
+  missingNote: string;
+  missingHeading: string;
+  words: string;
+  minutes: string;
+  blank: string;
+  title: string;
+  create: string;
+  today: string;
 };
 
 export default createMsg<UtilMsg>({
@@ -69,6 +80,17 @@ export default createMsg<UtilMsg>({
     write: "作成",
     version: "バージョン",
     default: "デフォルト",
+    queued: "ジョブを追加しました",
+    backlinks: "このノートへのリンク",
+    unresolved: "解決できないリンク",
+    missingNote: "ノートがありません",
+    missingHeading: "見出しがありません",
+    words: "語",
+    minutes: "分で読めます",
+    blank: "空のノート",
+    title: "タイトル",
+    create: "作成",
+    today: "今日のノート",
   },
   english: {
     save: "save",
@@ -92,5 +114,16 @@ export default createMsg<UtilMsg>({
     write: "write",
     version: "version",
     default: "default",
+    queued: "job queued",
+    backlinks: "linked from",
+    unresolved: "unresolved links",
+    missingNote: "no such note",
+    missingHeading: "no such heading",
+    words: "words",
+    minutes: "min read",
+    blank: "blank note",
+    title: "title",
+    create: "create",
+    today: "today's note",
   },
 });
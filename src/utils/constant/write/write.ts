@@ -3,15 +3,23 @@ import createMsg from "../../lib/msg";
 type WriteMainMsg = {
   save: string;
   sync: string;
+  externalChange: string;
+  renameOrCopy: string;
 };
 
 export default createMsg<WriteMainMsg>({
   japanese: {
     save: "保存",
     sync: "同期",
+    externalChange: "ワークスペースが外部で変更されました。保存前に確認してください",
+    renameOrCopy:
+      "タイトルが変更されました。名前を変更して他のノートのリンクを更新しますか？ キャンセルすると新しいノートとして保存します",
   },
   english: {
     save: "save",
     sync: "sync",
+    externalChange: "workspace was changed outside the app. check it before saving",
+    renameOrCopy:
+      "the title was changed. rename the note and update the links in the other notes? cancel to save it as a new note",
   },
 });
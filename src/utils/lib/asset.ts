@@ -0,0 +1,28 @@
+import { uriTransformer } from "react-markdown";
+import { NOTE } from "./wiki";
+
+/**
+ * attachments are linked by `notex://<sha256>.<ext>` in the bodies, and the webview loads them
+ * through the `notex-asset` protocol. see src-tauri/src/asset.rs
+ * windows serves custom protocols as `https://<scheme>.localhost/` like convertFileSrc does.
+ */
+const ATTACHMENT = "notex://";
+const ASSET = "notex-asset";
+
+export const assetUri = (link: string) => {
+  const name = link.slice(ATTACHMENT.length);
+  return navigator.userAgent.includes("Windows")
+    ? `https://${ASSET}.localhost/${name}`
+    : `${ASSET}://${name}`;
+};
+
+/**
+ * react-markdown drops unknown schemes, so attachments are rewritten before the default check.
+ * links to notes are kept for Markdown to navigate -- see wiki.ts
+ */
+export const transformUri = (uri: string) =>
+  uri.startsWith(ATTACHMENT)
+    ? assetUri(uri)
+    : uri.startsWith(NOTE)
+    ? uri
+    : uriTransformer(uri);
@@ -0,0 +1,36 @@
+import { Macros } from "../../redux/settings";
+
+/**
+ * math macros are stored as name => expansion, with or without the backslash in the name.
+ * katex wants the backslash, and the document's macros override the global ones.
+ * see src-tauri/src/macros.rs, which expands the same ones in the exports.
+ */
+const withBackslash = (name: string) =>
+  name.trim().startsWith("\\") ? name.trim() : "\\" + name.trim();
+
+export const mergeMacros = (global: Macros, local: Macros): Macros => {
+  const merged: Macros = {};
+  for (const [name, expansion] of [
+    ...Object.entries(global),
+    ...Object.entries(local),
+  ])
+    merged[withBackslash(name)] = expansion;
+  return merged;
+};
+
+/**
+ * one macro per line as `\name = expansion`
+ */
+export const macrosToText = (macros: Macros) =>
+  Object.entries(macros)
+    .map(([name, expansion]) => `${withBackslash(name)} = ${expansion}`)
+    .join("\n");
+
+export const textToMacros = (text: string): Macros => {
+  const macros: Macros = {};
+  for (const line of text.split("\n")) {
+    const idx = line.indexOf("=");
+    if (idx > 0) macros[line.slice(0, idx).trim()] = line.slice(idx + 1).trim();
+  }
+  return macros;
+};
@@ -0,0 +1,83 @@
+/**
+ * `[[title#heading|label]]` links between notes, parsed the same as src-tauri/src/links.rs.
+ * they become markdown links of the `notex-note:` scheme, which Markdown navigates to the note,
+ * with the heading as the anchor rehype-slug gives.
+ */
+export const NOTE = "notex-note:";
+
+const WIKI_LINK = /\[\[([^[\]\n]+)\]\]/g;
+const FENCE = /^ {0,3}(`{3,}|~{3,})/;
+
+export const anchor = (heading: string) =>
+  heading
+    .trim()
+    .toLowerCase()
+    .replace(/ /g, "-")
+    .replace(/[^\p{L}\p{N}_-]/gu, "");
+
+const toLink = (inner: string) => {
+  const [link, label] = inner.split("|", 2).map((s) => s.trim());
+  const idx = link.indexOf("#");
+  const target = (idx < 0 ? link : link.slice(0, idx)).trim();
+  const heading = idx < 0 ? "" : link.slice(idx + 1).trim();
+  if (!target && !heading) return null;
+
+  const text =
+    label ||
+    (target && heading ? `${target} > ${heading}` : target || heading);
+  const href = target
+    ? `${NOTE}${encodeURIComponent(target)}${heading ? "#" + anchor(heading) : ""}`
+    : `#${anchor(heading)}`;
+  return `[${text.replace(/([\\[\]])/g, "\\$1")}](<${href}>)`;
+};
+
+// outside of inline code, i.e. the even parts split by backticks
+const replaceLine = (line: string) =>
+  line
+    .split("`")
+    .map((part, i) =>
+      i % 2 === 0
+        ? part.replace(
+            WIKI_LINK,
+            (whole, inner: string, offset: number) =>
+              (offset > 0 && part[offset - 1] === "\\"
+                ? null
+                : toLink(inner)) ?? whole
+          )
+        : part
+    )
+    .join("`");
+
+export const wikiToMarkdown = (md: string) => {
+  let fence: string | null = null;
+  return md
+    .split("\n")
+    .map((line) => {
+      const opened = FENCE.exec(line)?.[1];
+      if (fence === null && opened) {
+        fence = opened;
+        return line;
+      }
+      if (fence !== null) {
+        // a closing fence has no info string
+        if (
+          opened &&
+          opened[0] === fence[0] &&
+          opened.length >= fence.length &&
+          line.trim() === opened
+        )
+          fence = null;
+        return line;
+      }
+      return replaceLine(line);
+    })
+    .join("\n");
+};
+
+/**
+ * the title and the anchor of a `notex-note:` href.
+ */
+export const noteOf = (href: string) => {
+  const [target, hash] = href.slice(NOTE.length).split("#", 2);
+  return { title: decodeURIComponent(target), anchor: hash ?? "" };
+};